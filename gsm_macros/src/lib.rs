@@ -0,0 +1,90 @@
+// Explicit returns like the gsm crate
+#![allow(clippy::needless_return)]
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Field, Fields};
+
+/// Derives `models::util::FromRow` for a struct with named fields. Every field
+/// is read from the column of the same name, converted through
+/// `models::util::FromColumn`.
+///
+/// A field marked `#[row(default)]` falls back to its `Default` when the
+/// query doesn't select its column, for structs shared by queries that select
+/// different columns.
+#[proc_macro_derive(FromRow, attributes(row))]
+pub fn derive_from_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    return match expand_from_row(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    };
+}
+
+fn expand_from_row(input: DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(
+            &input,
+            "FromRow can only be derived for structs",
+        ));
+    };
+
+    let Fields::Named(fields) = &data.fields else {
+        return Err(Error::new_spanned(
+            &input,
+            "FromRow can only be derived for structs with named fields",
+        ));
+    };
+
+    let mut field_reads = Vec::new();
+
+    for field in fields.named.iter() {
+        let ident = field.ident.as_ref().expect("named fields have an ident");
+        let column_name = ident.to_string();
+
+        let read = if has_default_attribute(field)? {
+            quote! { crate::models::util::column_or_default(row, #column_name)? }
+        } else {
+            quote! { crate::models::util::column(row, #column_name)? }
+        };
+
+        field_reads.push(quote! { #ident: #read });
+    }
+
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    return Ok(quote! {
+        impl #impl_generics crate::models::util::FromRow for #name #type_generics #where_clause {
+            fn from_row(
+                row: &::libsql::Row,
+            ) -> ::std::result::Result<Self, crate::utils::app_error::AppError> {
+                return ::std::result::Result::Ok(Self {
+                    #(#field_reads,)*
+                });
+            }
+        }
+    });
+}
+
+fn has_default_attribute(field: &Field) -> Result<bool, Error> {
+    let mut has_default = false;
+
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("row"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("default") {
+                has_default = true;
+                return Ok(());
+            }
+
+            return Err(meta.error("unsupported row attribute, expected `default`"));
+        })?;
+    }
+
+    return Ok(has_default);
+}
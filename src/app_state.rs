@@ -1,18 +1,142 @@
 use std::{collections::HashMap, sync::Arc};
 
 use libsql::Connection;
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc::UnboundedSender, Mutex};
 use uuid::Uuid;
 
 use crate::{
     config::Config,
-    models::{profile::CacheProfile, user::CacheUser},
+    models::{
+        profile::CacheProfile, session::CacheSession, user::CacheUser, util::TransactionSource,
+    },
+    repositories::{
+        ProfileRepository, RegistrationOtpRepository, SessionRepository, UserRepository,
+    },
+    storage::VideoStore,
+    utils::{
+        cache::Cache, login_throttle::LoginThrottle, mailer::Mailer, rate_limiter::RateLimiter,
+    },
+    views::chat::ChatServerEvent,
 };
 
+/// Open chat sockets by profile id, then by connection id.
+pub type ChatConnections =
+    Arc<Mutex<HashMap<i64, HashMap<Uuid, UnboundedSender<ChatServerEvent>>>>>;
+
 #[derive(Clone)]
 pub struct AppState {
     pub config: Config,
     pub db_conn: Connection,
-    pub profile_cache: Arc<Mutex<HashMap<Uuid, CacheProfile>>>,
-    pub user_cache: Arc<Mutex<HashMap<Uuid, CacheUser>>>,
+    pub transactions: TransactionSource,
+    pub users: Arc<dyn UserRepository>,
+    pub profiles: Arc<dyn ProfileRepository>,
+    pub registration_otps: Arc<dyn RegistrationOtpRepository>,
+    pub sessions: Arc<dyn SessionRepository>,
+    pub profile_cache: Arc<Cache<i64, CacheProfile>>,
+    pub user_cache: Arc<Cache<Uuid, CacheUser>>,
+    pub session_cache: Arc<Cache<Uuid, CacheSession>>,
+    pub mailer: Arc<dyn Mailer>,
+    pub video_store: Arc<dyn VideoStore>,
+    pub chat_connections: ChatConnections,
+    pub login_throttle: Arc<dyn LoginThrottle>,
+    pub rate_limiter: Arc<dyn RateLimiter>,
+}
+
+// Invalidation hooks, to be called after the database row behind a cached
+// entry changes. Entries also expire on their own after the configured TTL.
+impl AppState {
+    /// After the user row changed, like its role, password or suspension.
+    pub fn invalidate_user(&self, user_id: i64) {
+        self.user_cache
+            .invalidate_where(|_, user| user.id as i64 == user_id);
+    }
+
+    /// After sessions of the user were revoked, `keep_session_id` stays cached.
+    pub fn invalidate_user_sessions(&self, user_id: i64, keep_session_id: Option<i64>) {
+        self.session_cache.invalidate_where(|_, session| {
+            session.user_id == user_id && Some(session.id) != keep_session_id
+        });
+    }
+
+    /// After the profile row of the user changed.
+    pub fn invalidate_profile(&self, user_id: i64) {
+        self.profile_cache.invalidate(&user_id);
+    }
+}
+
+#[cfg(test)]
+impl AppState {
+    /// A state for handler tests, everything behind the repositories lives in
+    /// a `MemoryRepository`. The database has no tables, so a handler that
+    /// goes around the repositories fails instead of writing somewhere else.
+    pub async fn for_tests() -> Self {
+        use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+        use crate::{
+            config::{CacheConfig, DatabaseConfig, RateLimitPolicy, RateLimits},
+            repositories::memory::MemoryRepository,
+            storage::local::LocalVideoStore,
+            utils::{
+                login_throttle::init_login_throttle, mailer::LogMailer,
+                rate_limiter::init_rate_limiter,
+            },
+        };
+
+        let policy = RateLimitPolicy {
+            requests: 1000,
+            per_seconds: 60,
+        };
+
+        let config = Config {
+            sqids_alphabet: "abcdefghijklmnopqrstuvwxyz".to_string(),
+            bind_address: SocketAddr::from(([127, 0, 0, 1], 0)),
+            database: DatabaseConfig::Memory,
+            jwt_secret: "test".to_string(),
+            jwt_expiry_minute: 15,
+            jwt_maxage: 60,
+            refresh_token_expiry_day: 30,
+            mailer_outbox_path: None,
+            video_storage_path: std::env::temp_dir().to_string_lossy().to_string(),
+            video_max_bytes: 1024,
+            cache: CacheConfig {
+                max_entries: 100,
+                ttl_secs: 60,
+            },
+            rate_limits: RateLimits {
+                auth: policy,
+                default: policy,
+                profile: policy,
+                swipe: policy,
+                messaging: policy,
+            },
+            trusted_proxies: Vec::new(),
+        };
+
+        let db_conn = libsql::Database::open_in_memory()
+            .and_then(|database| database.connect())
+            .expect("Should open an in-memory database");
+        let transactions = TransactionSource::memory(&db_conn);
+        let repository = Arc::new(MemoryRepository::new());
+        let cache_ttl = Duration::from_secs(config.cache.ttl_secs);
+
+        return AppState {
+            video_store: Arc::new(LocalVideoStore {
+                root: PathBuf::from(&config.video_storage_path),
+            }),
+            config,
+            db_conn,
+            transactions,
+            users: repository.clone(),
+            profiles: repository.clone(),
+            registration_otps: repository.clone(),
+            sessions: repository,
+            profile_cache: Arc::new(Cache::new("profiles", 100, cache_ttl)),
+            user_cache: Arc::new(Cache::new("users", 100, cache_ttl)),
+            session_cache: Arc::new(Cache::new("sessions", 100, cache_ttl)),
+            mailer: Arc::new(LogMailer),
+            chat_connections: Arc::new(Mutex::new(HashMap::new())),
+            login_throttle: init_login_throttle(),
+            rate_limiter: init_rate_limiter(),
+        };
+    }
 }
@@ -0,0 +1,144 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::config::ConfigOverrides;
+
+#[derive(Debug, Parser)]
+#[command(name = "gsm", about = "The gsm API server")]
+pub struct Cli {
+    #[command(flatten)]
+    pub config: ConfigArgs,
+
+    /// Serves the API when no command is given
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Applies pending migrations and serves the API
+    Serve,
+    /// Applies, reverts or lists database migrations
+    Migrate {
+        #[command(subcommand)]
+        action: MigrateAction,
+    },
+    /// Creates a user with the admin role and a profile
+    CreateAdmin(CreateAdminArgs),
+    /// Fills a development database with visible profiles
+    Seed(SeedArgs),
+    /// Looks up and manages a single user
+    User {
+        #[command(subcommand)]
+        action: UserAction,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MigrateAction {
+    /// Applies every pending migration
+    Up,
+    /// Reverts the latest applied migrations
+    Down {
+        #[arg(default_value_t = 1)]
+        steps: usize,
+    },
+    /// Lists every migration and when it was applied
+    Status,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct CreateAdminArgs {
+    pub email: String,
+
+    #[arg(long)]
+    pub first_name: String,
+
+    #[arg(long)]
+    pub last_name: String,
+
+    /// YYYY-MM-DD
+    #[arg(long)]
+    pub birth_date: String,
+
+    /// A random password is generated and printed when left out
+    #[arg(long)]
+    pub password: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SeedArgs {
+    /// Number of users to create, existing seed users are skipped
+    #[arg(long, default_value_t = 20)]
+    pub count: u32,
+
+    /// Location every seeded profile is listed in
+    #[arg(long, default_value = "JPN")]
+    pub location: String,
+
+    /// Password of every seeded user
+    #[arg(long, default_value = "password123")]
+    pub password: String,
+
+    /// Seeds a remote database, which is refused by default
+    #[arg(long)]
+    pub allow_remote: bool,
+}
+
+/// A user is given by email or by pid.
+#[derive(Debug, Subcommand)]
+pub enum UserAction {
+    /// Prints the user and their profile as JSON
+    Show { user: String },
+    /// Suspends the user and revokes every session
+    Suspend { user: String },
+    /// Lifts a suspension
+    Restore { user: String },
+    /// Revokes every session and issues a password reset token
+    Reset {
+        user: String,
+
+        /// Prints the reset token instead of mailing it to the user
+        #[arg(long)]
+        print_token: bool,
+    },
+    /// Sets the role of the user
+    SetRole {
+        user: String,
+
+        #[arg(value_parser = ["user", "moderator", "admin"])]
+        role: String,
+    },
+}
+
+/// Flags that override the config file and env vars.
+#[derive(Debug, clap::Args)]
+pub struct ConfigArgs {
+    /// TOML config file, defaults to GSM_CONFIG or ./gsm.toml when it exists
+    #[arg(long = "config", global = true)]
+    pub config_path: Option<PathBuf>,
+
+    /// Where the database lives
+    #[arg(long = "db", global = true, value_parser = ["local", "remote", "memory"])]
+    pub database_mode: Option<String>,
+
+    /// SQLite file used with `--db local`
+    #[arg(long = "db-path", global = true)]
+    pub database_path: Option<String>,
+
+    /// Address the API listens on, e.g. [::]:8080
+    #[arg(long = "bind", global = true)]
+    pub bind_address: Option<String>,
+}
+
+impl ConfigArgs {
+    pub fn into_overrides(self) -> ConfigOverrides {
+        return ConfigOverrides {
+            config_path: self.config_path,
+            database_mode: self.database_mode,
+            database_path: self.database_path,
+            bind_address: self.bind_address,
+        };
+    }
+}
@@ -0,0 +1,96 @@
+use chrono::NaiveDate;
+
+use crate::{
+    cli::CreateAdminArgs,
+    models::{
+        audit_log::{create_audit_log, AuditLogParams},
+        profile::create_profile,
+        user::{create_user, get_user_ids_by_email, set_user_role, Role},
+        util::{begin_transaction, TransactionSource},
+    },
+    utils::{
+        app_error::AppError, password::hash_password, token::generate_opaque_token,
+        validation::Validator,
+    },
+    views::profile::ProfileParams,
+};
+
+/// Bootstraps an admin on a fresh database, later role changes go through
+/// `gsm user set-role` or /api/admin.
+pub async fn run(args: CreateAdminArgs, transactions: &TransactionSource) -> Result<(), AppError> {
+    let mut validator = Validator::new();
+
+    let email = validator.email("email", &args.email);
+    let first_name = validator.name("first_name", &args.first_name);
+    let last_name = validator.name("last_name", &args.last_name);
+
+    let birth_date = match NaiveDate::parse_from_str(&args.birth_date, "%Y-%m-%d") {
+        Ok(birth_date) => {
+            let birth_date = birth_date
+                .and_hms_opt(0, 0, 0)
+                .ok_or(AppError::InternalServerError)?
+                .and_utc()
+                .timestamp();
+
+            validator.birth_date("birth_date", birth_date)
+        }
+        Err(_) => {
+            validator.add("birth_date", "invalid_format", "Expected YYYY-MM-DD");
+            0
+        }
+    };
+
+    let (password, is_generated) = match args.password {
+        Some(password) => (validator.password("password", &password), false),
+        None => (generate_opaque_token(), true),
+    };
+
+    validator.finish()?;
+
+    // The admin is created whole or not at all
+    let tx = begin_transaction(transactions).await?;
+
+    match get_user_ids_by_email(&email, &tx).await {
+        Ok(_) => return Err(AppError::UserAlreadyExist),
+        Err(AppError::UserDoesNotExist) => (),
+        Err(err) => return Err(err),
+    }
+
+    let (user_id, user_pid) = create_user(&email, &hash_password(&password)?, &tx).await?;
+    set_user_role(user_id, Role::Admin, &tx).await?;
+
+    let profile = create_profile(
+        &tx,
+        ProfileParams {
+            user_id,
+            birth_date,
+            first_name,
+            last_name,
+            location: "JPN".to_string(),
+            is_visible: false,
+        },
+    )
+    .await?;
+
+    create_audit_log(
+        &tx,
+        AuditLogParams {
+            actor_user_id: None,
+            action: "user.create_admin",
+            target_user_id: Some(user_id),
+            target_profile_id: profile.id,
+            details: None,
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    println!("Created admin {} ({})", email, user_pid);
+
+    if is_generated {
+        println!("Password: {}", password);
+    }
+
+    return Ok(());
+}
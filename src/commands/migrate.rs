@@ -0,0 +1,36 @@
+use libsql::Connection;
+
+use crate::{
+    cli::MigrateAction,
+    migrations::{migrate_down, migrate_status, migrate_up},
+    models::util::TransactionSource,
+    utils::app_error::AppError,
+};
+
+pub async fn run(
+    action: MigrateAction,
+    db_conn: &Connection,
+    transactions: &TransactionSource,
+) -> Result<(), AppError> {
+    match action {
+        MigrateAction::Up => {
+            let applied = migrate_up(db_conn, transactions).await?;
+            println!("Applied {} migration(s): {:?}", applied.len(), applied);
+        }
+        MigrateAction::Down { steps } => {
+            let reverted = migrate_down(db_conn, transactions, steps).await?;
+            println!("Reverted {} migration(s): {:?}", reverted.len(), reverted);
+        }
+        MigrateAction::Status => {
+            for migration in migrate_status(db_conn).await? {
+                let state = match migration.applied_at {
+                    Some(applied_at) => format!("applied at {}", applied_at),
+                    None => "pending".to_string(),
+                };
+                println!("{:06}_{}\t{}", migration.version, migration.name, state);
+            }
+        }
+    }
+
+    return Ok(());
+}
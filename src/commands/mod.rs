@@ -0,0 +1,23 @@
+pub mod create_admin;
+pub mod migrate;
+pub mod seed;
+pub mod user;
+
+use libsql::Connection;
+use uuid::Uuid;
+
+use crate::{
+    models::user::{get_user_by_email, get_user_by_pid, User},
+    utils::app_error::AppError,
+};
+
+/// Finds a user given on the command line by pid or by email.
+pub async fn find_user(user: &str, db_conn: &Connection) -> Result<User, AppError> {
+    let user = user.trim();
+
+    if Uuid::try_parse(user).is_ok() {
+        return get_user_by_pid(user, db_conn).await;
+    }
+
+    return get_user_by_email(&user.to_lowercase(), db_conn).await;
+}
@@ -0,0 +1,90 @@
+use chrono::{Duration, Utc};
+use libsql::Connection;
+
+use crate::{
+    cli::SeedArgs,
+    config::{Config, DatabaseConfig},
+    models::{
+        profile::create_profile,
+        user::{create_user, get_user_ids_by_email},
+        util::{begin_transaction, TransactionSource},
+    },
+    utils::{
+        app_error::AppError,
+        password::hash_password,
+        validation::{birth_date_for_age, Validator, MIN_AGE_YEARS},
+    },
+    views::profile::ProfileParams,
+};
+
+const FIRST_NAMES: [&str; 10] = [
+    "Aiko", "Ben", "Chioma", "Diego", "Elif", "Farah", "Goro", "Hana", "Ivan", "Jun",
+];
+const LAST_NAMES: [&str; 7] = [
+    "Sato", "Okafor", "Garcia", "Yilmaz", "Khan", "Tanaka", "Petrov",
+];
+
+/// Creates `seed+<n>@gsm.test` users with visible profiles, so discovery and
+/// matching can be tried out locally. Running it again only adds what is missing.
+pub async fn run(
+    args: SeedArgs,
+    config: &Config,
+    db_conn: &Connection,
+    transactions: &TransactionSource,
+) -> Result<(), AppError> {
+    if matches!(config.database, DatabaseConfig::Remote { .. }) && !args.allow_remote {
+        eprintln!("Refusing to seed a remote database, pass --allow-remote to do it anyway");
+        return Err(AppError::Forbidden);
+    }
+
+    let mut validator = Validator::new();
+    let location = validator.location("location", &args.location);
+    let password = validator.password("password", &args.password);
+    validator.finish()?;
+
+    // Hashing is slow on purpose, every seeded user shares the one hash
+    let hashed_password = hash_password(&password)?;
+    let mut created = 0;
+
+    for n in 1..=args.count {
+        let email = format!("seed+{}@gsm.test", n);
+
+        match get_user_ids_by_email(&email, db_conn).await {
+            Ok(_) => continue,
+            Err(AppError::UserDoesNotExist) => (),
+            Err(err) => return Err(err),
+        }
+
+        // A seed user is only skipped on the next run once it has a profile
+        let tx = begin_transaction(transactions).await?;
+        let (user_id, _) = create_user(&email, &hashed_password, &tx).await?;
+
+        // Spread ages from the youngest allowed to 32 years older
+        let age = MIN_AGE_YEARS + (n * 7) % 33;
+        let birth_date =
+            birth_date_for_age(Utc::now(), age) - Duration::days(n as i64 % 360).num_seconds();
+
+        create_profile(
+            &tx,
+            ProfileParams {
+                user_id,
+                birth_date,
+                first_name: FIRST_NAMES[n as usize % FIRST_NAMES.len()].to_string(),
+                last_name: LAST_NAMES[n as usize % LAST_NAMES.len()].to_string(),
+                location: location.to_owned(),
+                is_visible: true,
+            },
+        )
+        .await?;
+        tx.commit().await?;
+
+        created += 1;
+    }
+
+    println!(
+        "Created {} seed user(s) in {}, the password of every seed user is {}",
+        created, location, password
+    );
+
+    return Ok(());
+}
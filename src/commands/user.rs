@@ -0,0 +1,152 @@
+use libsql::Connection;
+use serde_json::json;
+
+use crate::{
+    cli::UserAction,
+    commands::find_user,
+    config::Config,
+    controllers::{
+        admin::get_admin_user_as_view,
+        password::{get_password_reset_mail, issue_password_reset_token},
+    },
+    models::{
+        audit_log::{create_audit_log, AuditLogParams},
+        profile::get_profile_by_user_id,
+        session::revoke_user_sessions,
+        user::{set_user_role, set_user_suspended, Role},
+        util::{begin_transaction, TransactionSource},
+    },
+    utils::{app_error::AppError, mailer::init_mailer},
+};
+
+// INFO: These write to the database only, a running server keeps the users
+// and sessions it has cached until they are evicted or it restarts!
+pub async fn run(
+    action: UserAction,
+    config: &Config,
+    db_conn: &Connection,
+    transactions: &TransactionSource,
+) -> Result<(), AppError> {
+    match action {
+        UserAction::Show { user } => {
+            let user = find_user(&user, db_conn).await?;
+            let user_id = user.id.ok_or(AppError::InternalServerError)?;
+
+            // Users can exist without a profile if registration was interrupted
+            let profile = match get_profile_by_user_id(user_id, db_conn).await {
+                Ok(profile) => Some(profile),
+                Err(AppError::NotFound) => None,
+                Err(err) => return Err(err),
+            };
+
+            let user_response = get_admin_user_as_view(user, profile);
+
+            let output = serde_json::to_string_pretty(&user_response)
+                .map_err(|_| AppError::InternalServerError)?;
+            println!("{}", output);
+        }
+        UserAction::Suspend { user } => {
+            let user_id = find_user_id(&user, db_conn).await?;
+
+            let tx = begin_transaction(transactions).await?;
+            set_user_suspended(user_id, true, &tx).await?;
+            let revoked = revoke_user_sessions(user_id, &tx).await?;
+
+            audit(
+                "user.suspend",
+                user_id,
+                Some(json!({ "revoked_sessions": revoked })),
+                &tx,
+            )
+            .await?;
+            tx.commit().await?;
+            println!("Suspended {} and revoked {} session(s)", user, revoked);
+        }
+        UserAction::Restore { user } => {
+            let user_id = find_user_id(&user, db_conn).await?;
+
+            // Profiles hidden by moderation stay hidden until edited explicitly
+            let tx = begin_transaction(transactions).await?;
+            set_user_suspended(user_id, false, &tx).await?;
+
+            audit("user.restore", user_id, None, &tx).await?;
+            tx.commit().await?;
+            println!("Restored {}", user);
+        }
+        UserAction::Reset { user, print_token } => {
+            let db_user = find_user(&user, db_conn).await?;
+            let user_id = db_user.id.ok_or(AppError::InternalServerError)?;
+            let email = db_user.email.ok_or(AppError::InternalServerError)?;
+
+            let tx = begin_transaction(transactions).await?;
+            let revoked = revoke_user_sessions(user_id, &tx).await?;
+            let token = issue_password_reset_token(user_id, &tx).await?;
+
+            audit(
+                "user.reset",
+                user_id,
+                Some(json!({ "revoked_sessions": revoked })),
+                &tx,
+            )
+            .await?;
+            tx.commit().await?;
+            println!("Revoked {} session(s) of {}", revoked, email);
+
+            if print_token {
+                println!("Password reset token: {}", token);
+            } else {
+                init_mailer(config)
+                    .send(get_password_reset_mail(email.to_owned(), &token))
+                    .await?;
+                println!("Mailed a password reset token to {}", email);
+            }
+        }
+        UserAction::SetRole { user, role } => {
+            let user_id = find_user_id(&user, db_conn).await?;
+            let role = Role::parse(&role).ok_or(AppError::WrongCredential)?;
+
+            let tx = begin_transaction(transactions).await?;
+            set_user_role(user_id, role, &tx).await?;
+
+            audit(
+                "user.role",
+                user_id,
+                Some(json!({ "role": role.as_str() })),
+                &tx,
+            )
+            .await?;
+            tx.commit().await?;
+            println!("{} is now {}", user, role.as_str());
+        }
+    }
+
+    return Ok(());
+}
+
+async fn find_user_id(user: &str, db_conn: &Connection) -> Result<i64, AppError> {
+    let user = find_user(user, db_conn).await?;
+
+    return user.id.ok_or(AppError::InternalServerError);
+}
+
+// Changes made from a shell have no acting user
+async fn audit(
+    action: &'static str,
+    target_user_id: i64,
+    details: Option<serde_json::Value>,
+    db_conn: &Connection,
+) -> Result<(), AppError> {
+    create_audit_log(
+        db_conn,
+        AuditLogParams {
+            actor_user_id: None,
+            action,
+            target_user_id: Some(target_user_id),
+            target_profile_id: None,
+            details,
+        },
+    )
+    .await?;
+
+    return Ok(());
+}
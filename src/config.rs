@@ -1,68 +1,651 @@
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+};
+
 use libsql::{Connection, Database};
+use serde::Deserialize;
+use sqids::Sqids;
 use tracing::{error, info, warn};
 
+use crate::models::util::TransactionSource;
+
+// Read when neither --config nor GSM_CONFIG name a file
+const DEFAULT_CONFIG_PATH: &str = "gsm.toml";
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub sqids_alphabet: String,
-    pub turso_db_url: String,
-    pub turso_auth_token: String,
+    pub bind_address: SocketAddr,
+    pub database: DatabaseConfig,
     pub jwt_secret: String,
     pub jwt_expiry_minute: u64,
     pub jwt_maxage: u64,
+    pub refresh_token_expiry_day: u64,
+    pub mailer_outbox_path: Option<String>,
+    pub video_storage_path: String,
+    pub video_max_bytes: u64,
+    pub cache: CacheConfig,
+    pub rate_limits: RateLimits,
+    pub trusted_proxies: Vec<IpRange>,
 }
 
-impl Config {
-    pub fn init() -> Config {
-        let sqids_alphabet = std::env::var("SQIDS_ALPHABET").expect("SQIDS_ALPHABET must be set");
-        let turso_db_url = std::env::var("TURSO_URL").expect("DATABASE_URL must be set");
-        let turso_auth_token = std::env::var("TURSO_AUTH_TOKEN").expect("TURSO_AUTH must be set");
-        let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-        let jwt_expiry_minute =
-            std::env::var("JWT_EXPIRY_MINUTE").expect("JWT_EXPIRY_MINUTE must be set");
-        let jwt_maxage = std::env::var("JWT_MAXAGE").expect("JWT_MAXAGE must be set");
-
-        return Config {
+#[derive(Debug, Clone)]
+pub enum DatabaseConfig {
+    /// A SQLite file, for development
+    Local {
+        path: String,
+    },
+    Remote {
+        url: String,
+        auth_token: String,
+    },
+    Memory,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CacheConfig {
+    pub max_entries: u64,
+    pub ttl_secs: u64,
+}
+
+/// A token bucket holding `requests` tokens that refills completely over `per_seconds`.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitPolicy {
+    pub requests: u32,
+    pub per_seconds: u32,
+}
+
+impl RateLimitPolicy {
+    /// Parses `<requests>/<seconds>`, e.g. `60/60`.
+    fn parse(value: &str) -> Option<Self> {
+        let (requests, per_seconds) = value.trim().split_once('/')?;
+        let requests = requests.trim().parse::<u32>().ok()?;
+        let per_seconds = per_seconds.trim().parse::<u32>().ok()?;
+
+        if requests == 0 || per_seconds == 0 {
+            return None;
+        }
+
+        return Some(Self {
+            requests,
+            per_seconds,
+        });
+    }
+}
+
+/// A CIDR block such as `10.0.0.0/8` or `fdaa::/16`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpRange {
+    /// Parses `<address>/<prefix>`, a bare address is a range of one.
+    fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (address, prefix_len) = match value.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (value, None),
+        };
+
+        let network = address.trim().parse::<IpAddr>().ok()?.to_canonical();
+        let max_len: u8 = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len.trim().parse::<u8>().ok()?,
+            None => max_len,
+        };
+
+        if prefix_len > max_len {
+            return None;
+        }
+
+        return Some(Self {
+            network,
+            prefix_len,
+        });
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        return match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum RateLimitGroup {
+    /// Unauthenticated routes, limited per IP
+    Auth,
+    Default,
+    Profile,
+    Swipe,
+    Messaging,
+}
+
+impl RateLimitGroup {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            Self::Auth => "auth",
+            Self::Default => "default",
+            Self::Profile => "profile",
+            Self::Swipe => "swipe",
+            Self::Messaging => "messaging",
+        };
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimits {
+    pub auth: RateLimitPolicy,
+    pub default: RateLimitPolicy,
+    pub profile: RateLimitPolicy,
+    pub swipe: RateLimitPolicy,
+    pub messaging: RateLimitPolicy,
+}
+
+impl RateLimits {
+    pub fn policy_for(&self, group: RateLimitGroup) -> RateLimitPolicy {
+        return match group {
+            RateLimitGroup::Auth => self.auth,
+            RateLimitGroup::Default => self.default,
+            RateLimitGroup::Profile => self.profile,
+            RateLimitGroup::Swipe => self.swipe,
+            RateLimitGroup::Messaging => self.messaging,
+        };
+    }
+}
+
+/// Values given as command line flags, the last and strongest layer.
+#[derive(Debug, Default)]
+pub struct ConfigOverrides {
+    pub config_path: Option<PathBuf>,
+    pub database_mode: Option<String>,
+    pub database_path: Option<String>,
+    pub bind_address: Option<String>,
+}
+
+/// Every problem found while loading the configuration.
+#[derive(Debug)]
+pub struct ConfigErrors(pub Vec<String>);
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Invalid configuration:")?;
+
+        for problem in self.0.iter() {
+            writeln!(f, "  - {}", problem)?;
+        }
+
+        return Ok(());
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+// INFO: One layer of settings, every layer has the shape of the TOML file.
+// Values stay loosely typed until `into_config` so all problems are reported together.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigLayer {
+    sqids_alphabet: Option<String>,
+    bind_address: Option<String>,
+    jwt_secret: Option<String>,
+    jwt_expiry_minute: Option<u64>,
+    jwt_maxage: Option<u64>,
+    refresh_token_expiry_day: Option<u64>,
+    mailer_outbox_path: Option<String>,
+    video_storage_path: Option<String>,
+    video_max_bytes: Option<u64>,
+    #[serde(default)]
+    database: DatabaseLayer,
+    #[serde(default)]
+    cache: CacheLayer,
+    #[serde(default)]
+    rate_limits: RateLimitsLayer,
+    trusted_proxies: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DatabaseLayer {
+    mode: Option<String>,
+    path: Option<String>,
+    turso_url: Option<String>,
+    turso_auth_token: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CacheLayer {
+    max_entries: Option<u64>,
+    ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RateLimitsLayer {
+    auth: Option<String>,
+    default: Option<String>,
+    profile: Option<String>,
+    swipe: Option<String>,
+    messaging: Option<String>,
+}
+
+/// Takes every field set in `$top` over `$base`.
+macro_rules! merge_fields {
+    ($base:expr, $top:expr, $($field:ident),+) => {
+        $(
+            if $top.$field.is_some() {
+                $base.$field = $top.$field;
+            }
+        )+
+    };
+}
+
+impl ConfigLayer {
+    fn defaults() -> Self {
+        return Self {
+            bind_address: Some("[::]:8080".to_string()),
+            jwt_expiry_minute: Some(15),
+            jwt_maxage: Some(60),
+            refresh_token_expiry_day: Some(30),
+            video_storage_path: Some("videos".to_string()),
+            // 50 MiB
+            video_max_bytes: Some(52_428_800),
+            database: DatabaseLayer {
+                mode: Some("remote".to_string()),
+                path: Some("gsm.db".to_string()),
+                ..Default::default()
+            },
+            cache: CacheLayer {
+                max_entries: Some(10_000),
+                ttl_secs: Some(15 * 60),
+            },
+            rate_limits: RateLimitsLayer {
+                auth: Some("10/60".to_string()),
+                default: Some("120/60".to_string()),
+                profile: Some("20/60".to_string()),
+                swipe: Some("60/60".to_string()),
+                messaging: Some("60/60".to_string()),
+            },
+            ..Default::default()
+        };
+    }
+
+    fn from_file(path: &Path, errors: &mut Vec<String>) -> Self {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => {
+                errors.push(format!("config file {}: {}", path.display(), err));
+                return Self::default();
+            }
+        };
+
+        return match toml::from_str::<Self>(&content) {
+            Ok(layer) => layer,
+            Err(err) => {
+                errors.push(format!("config file {}: {}", path.display(), err.message()));
+                Self::default()
+            }
+        };
+    }
+
+    fn from_env(errors: &mut Vec<String>) -> Self {
+        let string = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let mut number = |name: &str| {
+            let value = string(name)?;
+
+            match value.trim().parse::<u64>() {
+                Ok(value) => Some(value),
+                Err(_) => {
+                    errors.push(format!(
+                        "{}: expected a whole number, got {:?}",
+                        name, value
+                    ));
+                    None
+                }
+            }
+        };
+
+        return Self {
+            sqids_alphabet: string("SQIDS_ALPHABET"),
+            bind_address: string("BIND_ADDRESS"),
+            jwt_secret: string("JWT_SECRET"),
+            jwt_expiry_minute: number("JWT_EXPIRY_MINUTE"),
+            jwt_maxage: number("JWT_MAXAGE"),
+            refresh_token_expiry_day: number("REFRESH_TOKEN_EXPIRY_DAY"),
+            mailer_outbox_path: string("MAILER_OUTBOX_PATH"),
+            video_storage_path: string("VIDEO_STORAGE_PATH"),
+            video_max_bytes: number("VIDEO_MAX_BYTES"),
+            database: DatabaseLayer {
+                mode: string("DATABASE_MODE"),
+                path: string("DATABASE_PATH"),
+                turso_url: string("TURSO_URL"),
+                turso_auth_token: string("TURSO_AUTH_TOKEN"),
+            },
+            cache: CacheLayer {
+                max_entries: number("CACHE_MAX_ENTRIES"),
+                ttl_secs: number("CACHE_TTL_SECS"),
+            },
+            rate_limits: RateLimitsLayer {
+                auth: string("RATE_LIMIT_AUTH"),
+                default: string("RATE_LIMIT_DEFAULT"),
+                profile: string("RATE_LIMIT_PROFILE"),
+                swipe: string("RATE_LIMIT_SWIPE"),
+                messaging: string("RATE_LIMIT_MESSAGING"),
+            },
+            trusted_proxies: string("TRUSTED_PROXIES")
+                .map(|value| value.split(',').map(str::to_string).collect()),
+        };
+    }
+
+    fn from_overrides(overrides: &ConfigOverrides) -> Self {
+        return Self {
+            bind_address: overrides.bind_address.to_owned(),
+            database: DatabaseLayer {
+                mode: overrides.database_mode.to_owned(),
+                path: overrides.database_path.to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+    }
+
+    fn merge(&mut self, top: Self) {
+        merge_fields!(
+            self,
+            top,
             sqids_alphabet,
-            turso_db_url,
-            turso_auth_token,
+            bind_address,
             jwt_secret,
-            jwt_expiry_minute: jwt_expiry_minute
-                .parse::<u64>()
-                .expect("Should parse jwt_expiry_minute"),
-            jwt_maxage: jwt_maxage.parse::<u64>().expect("Should parse jwt_maxage"),
+            jwt_expiry_minute,
+            jwt_maxage,
+            refresh_token_expiry_day,
+            mailer_outbox_path,
+            video_storage_path,
+            video_max_bytes,
+            trusted_proxies
+        );
+        merge_fields!(
+            self.database,
+            top.database,
+            mode,
+            path,
+            turso_url,
+            turso_auth_token
+        );
+        merge_fields!(self.cache, top.cache, max_entries, ttl_secs);
+        merge_fields!(
+            self.rate_limits,
+            top.rate_limits,
+            auth,
+            default,
+            profile,
+            swipe,
+            messaging
+        );
+    }
+
+    fn into_config(self, errors: &mut Vec<String>) -> Option<Config> {
+        // Each setting is named by its config file key and its env var
+        let mut required = |value: Option<String>, key: &str, env: &str| match value {
+            Some(value) if !value.trim().is_empty() => Some(value),
+            _ => {
+                errors.push(format!("{} ({}) is required", key, env));
+                None
+            }
+        };
+
+        let sqids_alphabet = required(self.sqids_alphabet, "sqids_alphabet", "SQIDS_ALPHABET");
+        let jwt_secret = required(self.jwt_secret, "jwt_secret", "JWT_SECRET");
+        let bind_address = required(self.bind_address, "bind_address", "BIND_ADDRESS");
+        let video_storage_path = required(
+            self.video_storage_path,
+            "video_storage_path",
+            "VIDEO_STORAGE_PATH",
+        );
+
+        let database = match self.database.mode.as_deref().map(str::trim) {
+            Some("local") => required(self.database.path, "database.path", "DATABASE_PATH")
+                .map(|path| DatabaseConfig::Local { path }),
+            Some("memory") => Some(DatabaseConfig::Memory),
+            Some("remote") => {
+                let url = required(self.database.turso_url, "database.turso_url", "TURSO_URL");
+                let auth_token = required(
+                    self.database.turso_auth_token,
+                    "database.turso_auth_token",
+                    "TURSO_AUTH_TOKEN",
+                );
+
+                match (url, auth_token) {
+                    (Some(url), Some(auth_token)) => {
+                        Some(DatabaseConfig::Remote { url, auth_token })
+                    }
+                    _ => None,
+                }
+            }
+            mode => {
+                errors.push(format!(
+                    "database.mode (DATABASE_MODE) must be local, remote or memory, got {:?}",
+                    mode.unwrap_or_default()
+                ));
+                None
+            }
+        };
+
+        if let Some(alphabet) = &sqids_alphabet {
+            if let Err(err) = Sqids::builder()
+                .alphabet(alphabet.chars().collect())
+                .build()
+            {
+                errors.push(format!("sqids_alphabet (SQIDS_ALPHABET): {}", err));
+            }
+        }
+
+        let bind_address = bind_address.and_then(|value| match value.trim().parse() {
+            Ok(address) => Some(address),
+            Err(_) => {
+                errors.push(format!(
+                    "bind_address (BIND_ADDRESS): expected an address like [::]:8080, got {:?}",
+                    value
+                ));
+                None
+            }
+        });
+
+        let mut positive = |value: Option<u64>, key: &str, env: &str| match value {
+            Some(value) if value > 0 => Some(value),
+            _ => {
+                errors.push(format!("{} ({}) must be greater than 0", key, env));
+                None
+            }
+        };
+
+        let jwt_expiry_minute = positive(
+            self.jwt_expiry_minute,
+            "jwt_expiry_minute",
+            "JWT_EXPIRY_MINUTE",
+        );
+        let jwt_maxage = positive(self.jwt_maxage, "jwt_maxage", "JWT_MAXAGE");
+        let refresh_token_expiry_day = positive(
+            self.refresh_token_expiry_day,
+            "refresh_token_expiry_day",
+            "REFRESH_TOKEN_EXPIRY_DAY",
+        );
+        let video_max_bytes = positive(self.video_max_bytes, "video_max_bytes", "VIDEO_MAX_BYTES");
+        let cache_max_entries = positive(
+            self.cache.max_entries,
+            "cache.max_entries",
+            "CACHE_MAX_ENTRIES",
+        );
+        let cache_ttl_secs = positive(self.cache.ttl_secs, "cache.ttl_secs", "CACHE_TTL_SECS");
+
+        let mut rate_limit = |value: Option<String>, key: &str, env: &str| {
+            let policy = value.as_deref().and_then(RateLimitPolicy::parse);
+
+            if policy.is_none() {
+                errors.push(format!(
+                    "rate_limits.{} ({}): expected <requests>/<seconds>, got {:?}",
+                    key,
+                    env,
+                    value.unwrap_or_default()
+                ));
+            }
+
+            policy
+        };
+
+        let rate_limits = (
+            rate_limit(self.rate_limits.auth, "auth", "RATE_LIMIT_AUTH"),
+            rate_limit(self.rate_limits.default, "default", "RATE_LIMIT_DEFAULT"),
+            rate_limit(self.rate_limits.profile, "profile", "RATE_LIMIT_PROFILE"),
+            rate_limit(self.rate_limits.swipe, "swipe", "RATE_LIMIT_SWIPE"),
+            rate_limit(
+                self.rate_limits.messaging,
+                "messaging",
+                "RATE_LIMIT_MESSAGING",
+            ),
+        );
+
+        let mut trusted_proxies: Vec<IpRange> = Vec::new();
+
+        for value in self.trusted_proxies.unwrap_or_default().iter() {
+            match IpRange::parse(value) {
+                Some(range) => trusted_proxies.push(range),
+                None => errors.push(format!(
+                    "trusted_proxies (TRUSTED_PROXIES): expected an address or CIDR range, got {:?}",
+                    value
+                )),
+            }
+        }
+
+        let (Some(auth), Some(default), Some(profile), Some(swipe), Some(messaging)) = rate_limits
+        else {
+            return None;
+        };
+
+        return Some(Config {
+            sqids_alphabet: sqids_alphabet?,
+            bind_address: bind_address?,
+            database: database?,
+            jwt_secret: jwt_secret?,
+            jwt_expiry_minute: jwt_expiry_minute?,
+            jwt_maxage: jwt_maxage?,
+            refresh_token_expiry_day: refresh_token_expiry_day?,
+            mailer_outbox_path: self.mailer_outbox_path,
+            video_storage_path: video_storage_path?,
+            video_max_bytes: video_max_bytes?,
+            cache: CacheConfig {
+                max_entries: cache_max_entries?,
+                ttl_secs: cache_ttl_secs?,
+            },
+            rate_limits: RateLimits {
+                auth,
+                default,
+                profile,
+                swipe,
+                messaging,
+            },
+            trusted_proxies,
+        });
+    }
+}
+
+impl Config {
+    /// Layers defaults, the TOML config file, env vars and `overrides`, later
+    /// layers win. Every invalid or missing setting is reported at once.
+    pub fn load(overrides: &ConfigOverrides) -> Result<Config, ConfigErrors> {
+        let mut errors: Vec<String> = Vec::new();
+        let mut layer = ConfigLayer::defaults();
+
+        let config_path = overrides
+            .config_path
+            .to_owned()
+            .or_else(|| std::env::var("GSM_CONFIG").ok().map(PathBuf::from));
+
+        match config_path {
+            Some(path) => layer.merge(ConfigLayer::from_file(&path, &mut errors)),
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                layer.merge(ConfigLayer::from_file(
+                    Path::new(DEFAULT_CONFIG_PATH),
+                    &mut errors,
+                ));
+            }
+            None => {}
+        }
+
+        layer.merge(ConfigLayer::from_env(&mut errors));
+        layer.merge(ConfigLayer::from_overrides(overrides));
+
+        let config = layer.into_config(&mut errors);
+
+        return match config {
+            Some(config) if errors.is_empty() => Ok(config),
+            _ => Err(ConfigErrors(errors)),
         };
     }
 }
 
-//Tried to use libsql crate but open_remote conn works even with empty url ???
-//Leaving it here until I figure it out!!!
-pub fn initialize_database(config: &Config, can_use_local_db: bool) -> Connection {
-    let url: &str;
-    let token = &config.turso_auth_token;
+/// Opens the configured database, falling back to an in memory one when the
+/// connection fails. A database that can't be opened at all is a config error.
+pub fn initialize_database(
+    config: &Config,
+) -> Result<(Connection, TransactionSource), ConfigErrors> {
+    let open_error = |err: libsql::Error| ConfigErrors(vec![format!("database: {}", err)]);
 
-    let db = if can_use_local_db {
-        warn!("Using sqlite local file db!");
-        url = "file:////Users/martinsodabi/Developer/RustProjects/gsm/gsm.db";
-        Database::open(url).unwrap()
-    } else {
-        url = config.turso_db_url.as_str();
-        Database::open_remote(url, token).unwrap()
+    let (db, url) = match &config.database {
+        DatabaseConfig::Local { path } => {
+            warn!("Using sqlite local file db!");
+            (Database::open(path).map_err(open_error)?, path.as_str())
+        }
+        DatabaseConfig::Remote { url, auth_token } => (
+            Database::open_remote(url, auth_token).map_err(open_error)?,
+            url.as_str(),
+        ),
+        DatabaseConfig::Memory => {
+            warn!("Using in memory db, nothing will be persisted!");
+            (Database::open_in_memory().map_err(open_error)?, ":memory:")
+        }
     };
 
     match db.connect() {
         Ok(db_conn) => {
-            info!("CONNECTED TO TURSO DB {:?}", url);
-            db_conn
+            info!("CONNECTED TO DB {:?}", url);
+
+            let transactions = match &config.database {
+                DatabaseConfig::Local { path } => TransactionSource::file(path),
+                DatabaseConfig::Remote { .. } => TransactionSource::remote(&db_conn),
+                DatabaseConfig::Memory => TransactionSource::memory(&db_conn),
+            };
+
+            return Ok((db_conn, transactions));
         }
 
         Err(err) => {
             error!("{:?}", err);
-            info!("UNABLE TO CONNECT TO TURSO DB {:?}", url);
+            info!("UNABLE TO CONNECT TO DB {:?}", url);
             info!("REVERTING TO IN_MEMORY DB");
-            Database::open_in_memory()
-                .unwrap()
-                .connect()
-                .expect("UNABLE TO OPEN IN_MEMORY DB")
+            let db_conn = Database::open_in_memory()
+                .and_then(|db| db.connect())
+                .map_err(open_error)?;
+            let transactions = TransactionSource::memory(&db_conn);
+
+            return Ok((db_conn, transactions));
         }
     }
 }
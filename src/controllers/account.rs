@@ -0,0 +1,256 @@
+use axum::{
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use chrono::Utc;
+use tracing::{error, warn};
+
+use crate::{
+    app_state::AppState,
+    controllers::{moderation::get_report_as_view, util::to_uuid},
+    models::{
+        account::{
+            get_blocks_by_profile_id, get_matches_by_profile_id, get_messages_by_profile_id,
+            get_swipes_by_profile_id, ExportedMessage, ProfileRelation,
+        },
+        profile::{get_profile_as_view, Profile},
+        profile_revision::{get_profile_revisions, ProfileRevision},
+        report::get_reports_by_reporter_profile_id,
+        session::{get_sessions_by_user_id, CacheSession},
+        user::CacheUser,
+        video::{get_videos_by_profile_id, Video},
+    },
+    utils::{app_error::AppError, password::verify_password},
+    views::{
+        account::{
+            AccountExportResponse, AccountMessageResponse, AccountProfileRevisionResponse,
+            AccountUserResponse, AccountVideoResponse, DeleteAccountParams,
+            ProfileRelationResponse,
+        },
+        session::SessionResponse,
+    },
+};
+
+/// Permanently deletes the caller's account after the password is entered
+/// again. The profile, videos, swipes, matches, messages, blocks, reports and
+/// sessions are removed with the user row by the cascading foreign keys.
+pub async fn delete_account(
+    State(app_state): State<AppState>,
+    Extension(user): Extension<CacheUser>,
+    Json(params): Json<DeleteAccountParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let password = params.password.trim().to_string();
+
+    if password.is_empty() {
+        return Err(AppError::MissingCredential);
+    }
+
+    let db_conn = &app_state.db_conn;
+    let user_id = user.id as i64;
+    let db_user = app_state.users.get_user_by_id(user_id).await?;
+
+    let hashed_password = db_user
+        .password
+        .as_ref()
+        .ok_or(AppError::InternalServerError)?;
+
+    if !verify_password(&password, hashed_password)? {
+        warn!("From account deletion password condition");
+        return Err(AppError::WrongCredential);
+    }
+
+    let profile = match app_state.profiles.get_profile_by_user_id(user_id).await {
+        Ok(profile) => Some(profile),
+        Err(AppError::NotFound) => None,
+        Err(err) => return Err(err),
+    };
+
+    let videos = match profile.as_ref().and_then(|profile| profile.id) {
+        Some(profile_id) => get_videos_by_profile_id(profile_id, db_conn).await?,
+        None => Vec::new(),
+    };
+
+    app_state.users.delete_user(user_id).await?;
+
+    // The rows are gone, a blob that fails to delete is only logged so the
+    // request doesn't fail after the account no longer exists
+    for video in videos {
+        if let Some(storage_key) = video.storage_key {
+            if app_state.video_store.delete(&storage_key).await.is_err() {
+                error!("Failed to delete video blob {}", storage_key);
+            }
+        }
+    }
+
+    evict_account(&app_state, &user, profile.as_ref()).await;
+
+    return Ok(Json(serde_json::json!({ "status": "success" })));
+}
+
+/// Returns everything the server holds about the caller as a JSON download.
+pub async fn export_account(
+    State(app_state): State<AppState>,
+    Extension(user): Extension<CacheUser>,
+    Extension(current_session): Extension<CacheSession>,
+) -> Result<Response, AppError> {
+    let db_conn = &app_state.db_conn;
+    let user_id = user.id as i64;
+    let db_user = app_state.users.get_user_by_id(user_id).await?;
+
+    let sessions = get_sessions_by_user_id(user_id, db_conn)
+        .await?
+        .into_iter()
+        .map(|session| {
+            let pid = to_uuid(session.pid);
+
+            SessionResponse {
+                is_current: pid == Some(current_session.pid),
+                pid,
+                device_label: session.device_label,
+                ip: session.ip,
+                created_at: session.created_at,
+                last_used_at: session.last_used_at,
+                expires_at: session.expires_at,
+            }
+        })
+        .collect();
+
+    let mut export = AccountExportResponse {
+        exported_at: Utc::now().timestamp(),
+        user: AccountUserResponse {
+            pid: to_uuid(db_user.pid),
+            email: db_user.email,
+            role: db_user.role,
+            suspended_at: db_user.suspended_at,
+            created_at: db_user.created_at,
+            updated_at: db_user.updated_at,
+        },
+        profile: None,
+        profile_revisions: Vec::new(),
+        videos: Vec::new(),
+        sessions,
+        swipes: Vec::new(),
+        matches: Vec::new(),
+        blocks: Vec::new(),
+        messages: Vec::new(),
+        reports: Vec::new(),
+    };
+
+    let profile = match app_state.profiles.get_profile_by_user_id(user_id).await {
+        Ok(profile) => Some(profile),
+        Err(AppError::NotFound) => None,
+        Err(err) => return Err(err),
+    };
+
+    if let Some(profile) = profile {
+        let profile_id = profile.id.ok_or(AppError::InternalServerError)?;
+        let profile_video_id = profile.profile_video_id;
+
+        export.profile_revisions = get_profile_revisions(profile_id, None, i64::MAX, db_conn)
+            .await?
+            .into_iter()
+            .map(|revision| get_profile_revision_as_view(revision, user_id))
+            .collect();
+
+        export.videos = get_videos_by_profile_id(profile_id, db_conn)
+            .await?
+            .into_iter()
+            .map(|video| get_video_as_view(video, profile_video_id))
+            .collect();
+
+        export.swipes = get_relations_as_view(get_swipes_by_profile_id(profile_id, db_conn).await?);
+        export.matches =
+            get_relations_as_view(get_matches_by_profile_id(profile_id, db_conn).await?);
+        export.blocks = get_relations_as_view(get_blocks_by_profile_id(profile_id, db_conn).await?);
+
+        export.messages = get_messages_by_profile_id(profile_id, db_conn)
+            .await?
+            .into_iter()
+            .map(|message| get_message_as_view(message, profile_id))
+            .collect();
+
+        export.reports = get_reports_by_reporter_profile_id(profile_id, db_conn)
+            .await?
+            .into_iter()
+            .map(get_report_as_view)
+            .collect();
+
+        export.profile = Some(get_profile_as_view(profile));
+    }
+
+    let content_disposition = format!("attachment; filename=\"gsm-export-{}.json\"", user.pid);
+
+    return Ok((
+        [(header::CONTENT_DISPOSITION, content_disposition)],
+        Json(export),
+    )
+        .into_response());
+}
+
+/// Drops every cached entry and open chat socket of a deleted account.
+async fn evict_account(app_state: &AppState, user: &CacheUser, profile: Option<&Profile>) {
+    let user_id = user.id as i64;
+
+    app_state.invalidate_user(user_id);
+    app_state.invalidate_user_sessions(user_id, None);
+    app_state.invalidate_profile(user_id);
+
+    if let Some(profile) = profile {
+        if let Some(profile_id) = profile.id {
+            // Dropping the senders ends the socket loops
+            app_state.chat_connections.lock().await.remove(&profile_id);
+        }
+    }
+}
+
+fn get_video_as_view(video: Video, profile_video_id: Option<i64>) -> AccountVideoResponse {
+    return AccountVideoResponse {
+        is_profile_video: video.id.is_some() && video.id == profile_video_id,
+        pid: to_uuid(video.pid),
+        mime_type: video.mime_type,
+        size_bytes: video.size_bytes,
+        created_at: video.created_at,
+    };
+}
+
+fn get_relations_as_view(relations: Vec<ProfileRelation>) -> Vec<ProfileRelationResponse> {
+    return relations
+        .into_iter()
+        .map(|relation| ProfileRelationResponse {
+            profile_pid: to_uuid(relation.profile_pid),
+            is_like: relation.is_like,
+            created_at: relation.created_at,
+        })
+        .collect();
+}
+
+fn get_profile_revision_as_view(
+    revision: ProfileRevision,
+    user_id: i64,
+) -> AccountProfileRevisionResponse {
+    let to_json = |value: Option<String>| value.and_then(|value| serde_json::from_str(&value).ok());
+
+    return AccountProfileRevisionResponse {
+        is_own_edit: revision.editor_user_id == Some(user_id),
+        field: revision.field,
+        old_value: to_json(revision.old_value),
+        new_value: to_json(revision.new_value),
+        created_at: revision.created_at,
+    };
+}
+
+fn get_message_as_view(exported: ExportedMessage, profile_id: i64) -> AccountMessageResponse {
+    let message = exported.message;
+
+    return AccountMessageResponse {
+        pid: to_uuid(message.pid),
+        profile_pid: to_uuid(exported.other_profile_pid),
+        is_sent: message.sender_profile_id == Some(profile_id),
+        body: message.body,
+        delivered_at: message.delivered_at,
+        read_at: message.read_at,
+        created_at: message.created_at,
+    };
+}
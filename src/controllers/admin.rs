@@ -0,0 +1,430 @@
+use axum::{
+    extract::{Path, Query, State},
+    Extension, Json,
+};
+use libsql::Connection;
+use serde_json::json;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    controllers::{
+        moderation::{disconnect_user, suspend_user},
+        profile::{cache_profile, get_profile_update},
+        util::{id_to_sqids, new_sqids},
+    },
+    models::{
+        audit_log::{create_audit_log, get_audit_logs, AuditLog, AuditLogParams},
+        profile::{get_profile_as_view, Profile},
+        profile_revision::{hide_profile_by_moderation, ProfileRevision},
+        session::revoke_user_sessions,
+        user::{set_user_role, set_user_suspended, CacheUser, User},
+        util::begin_transaction,
+    },
+    utils::{
+        app_error::AppError,
+        validation::{Validator, EMAIL_MAX_LEN},
+    },
+    views::{
+        admin::{
+            AdminUserResponse, AdminUsersResponse, AuditLogResponse, AuditLogsParams,
+            AuditLogsResponse, CacheStatsResponse, ProfileRevisionResponse, ProfileRevisionsParams,
+            ProfileRevisionsResponse, SetRoleParams, UserSearchParams,
+        },
+        profile::ProfileResponse,
+    },
+};
+
+const ADMIN_DEFAULT_LIMIT: i64 = 50;
+const ADMIN_MAX_LIMIT: i64 = 100;
+
+pub async fn search_users(
+    State(app_state): State<AppState>,
+    Query(params): Query<UserSearchParams>,
+) -> Result<Json<AdminUsersResponse>, AppError> {
+    let mut validator = Validator::new();
+    let email_query = params.email.trim().to_lowercase();
+
+    if email_query.is_empty() {
+        validator.add("email", "required", "Email is required");
+    } else if email_query.len() > EMAIL_MAX_LEN {
+        let message = format!("Email is longer than {} bytes", EMAIL_MAX_LEN);
+        validator.add("email", "too_long", message);
+    }
+
+    let limit = validator.limit("limit", params.limit, ADMIN_DEFAULT_LIMIT, ADMIN_MAX_LIMIT);
+    let sqids = new_sqids(&app_state.config.sqids_alphabet)?;
+    let after_id = validator.cursor("cursor", params.cursor, &sqids);
+
+    validator.finish()?;
+
+    let after_id = after_id.unwrap_or(0);
+
+    // Fetch one extra row to know whether there is a next page
+    let mut users = app_state
+        .users
+        .search_users_by_email(&email_query, after_id, limit + 1)
+        .await?;
+
+    let next_cursor = if users.len() as i64 > limit {
+        users.truncate(limit as usize);
+
+        let last_id = users
+            .last()
+            .and_then(|user| user.id)
+            .ok_or(AppError::InternalServerError)?;
+
+        Some(id_to_sqids(last_id as u64, &sqids)?)
+    } else {
+        None
+    };
+
+    let mut user_responses: Vec<AdminUserResponse> = Vec::new();
+
+    for user in users {
+        user_responses.push(get_admin_user(&app_state, user).await?);
+    }
+
+    return Ok(Json(AdminUsersResponse {
+        users: user_responses,
+        next_cursor,
+    }));
+}
+
+pub async fn get_user(
+    State(app_state): State<AppState>,
+    Path(user_pid): Path<Uuid>,
+) -> Result<Json<AdminUserResponse>, AppError> {
+    let user = app_state
+        .users
+        .get_user_by_pid(&user_pid.to_string())
+        .await?;
+
+    return Ok(Json(get_admin_user(&app_state, user).await?));
+}
+
+// INFO: Every admin action is written in one transaction with its audit log,
+// caches are only touched once it committed.
+pub async fn update_user_profile(
+    State(app_state): State<AppState>,
+    Extension(admin): Extension<CacheUser>,
+    Path(user_pid): Path<Uuid>,
+    Json(params): Json<serde_json::Value>,
+) -> Result<Json<ProfileResponse>, AppError> {
+    let user_id = get_user_id_by_pid(&app_state, &user_pid).await?;
+    let update = get_profile_update(&app_state, user_id, &params, true).await?;
+
+    // An empty patch changes nothing, so there is nothing to audit either
+    if update.is_empty() {
+        let profile = app_state.profiles.get_profile_by_user_id(user_id).await?;
+        return Ok(Json(get_profile_as_view(profile)));
+    }
+
+    let audit = AuditLogParams {
+        actor_user_id: Some(admin.id as i64),
+        action: "profile.update",
+        target_user_id: Some(user_id),
+        target_profile_id: None,
+        details: Some(params),
+    };
+
+    let profile = app_state
+        .profiles
+        .update_profile(user_id, &update, admin.id as i64, Some(audit))
+        .await?;
+
+    cache_profile(&app_state, &profile)?;
+
+    return Ok(Json(get_profile_as_view(profile)));
+}
+
+pub async fn suspend_account(
+    State(app_state): State<AppState>,
+    Extension(admin): Extension<CacheUser>,
+    Path(user_pid): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let user_id = get_user_id_by_pid(&app_state, &user_pid).await?;
+
+    if user_id == admin.id as i64 {
+        warn!("From self suspend condition");
+        return Err(AppError::WrongCredential);
+    }
+
+    let tx = begin_transaction(&app_state.transactions).await?;
+    suspend_user(user_id, &tx).await?;
+
+    // Users can exist without a profile if registration was interrupted
+    let profile_id = match hide_profile_by_moderation(user_id, admin.id as i64, &tx).await {
+        Ok(profile) => profile.id,
+        Err(AppError::NotFound) => None,
+        Err(err) => return Err(err),
+    };
+
+    audit(&tx, &admin, "user.suspend", user_id, None).await?;
+    tx.commit().await?;
+
+    if profile_id.is_some() {
+        app_state.invalidate_profile(user_id);
+    }
+
+    disconnect_user(&app_state, user_id, profile_id).await;
+
+    return Ok(Json(json!({ "status": "success" })));
+}
+
+pub async fn restore_account(
+    State(app_state): State<AppState>,
+    Extension(admin): Extension<CacheUser>,
+    Path(user_pid): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let user_id = get_user_id_by_pid(&app_state, &user_pid).await?;
+
+    // Profiles hidden by moderation stay hidden until edited explicitly
+    let tx = begin_transaction(&app_state.transactions).await?;
+    set_user_suspended(user_id, false, &tx).await?;
+    audit(&tx, &admin, "user.restore", user_id, None).await?;
+    tx.commit().await?;
+
+    app_state.invalidate_user(user_id);
+
+    return Ok(Json(json!({ "status": "success" })));
+}
+
+pub async fn logout_user(
+    State(app_state): State<AppState>,
+    Extension(admin): Extension<CacheUser>,
+    Path(user_pid): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let user_id = get_user_id_by_pid(&app_state, &user_pid).await?;
+
+    let tx = begin_transaction(&app_state.transactions).await?;
+    let revoked = revoke_user_sessions(user_id, &tx).await?;
+
+    let details = json!({ "revoked_sessions": revoked });
+    audit(&tx, &admin, "user.logout", user_id, Some(details)).await?;
+    tx.commit().await?;
+
+    app_state.invalidate_user_sessions(user_id, None);
+    app_state.invalidate_user(user_id);
+
+    if let Ok(profile_id) = app_state.profiles.get_profile_id_by_user_id(user_id).await {
+        app_state.chat_connections.lock().await.remove(&profile_id);
+    }
+
+    return Ok(Json(json!({ "status": "success" })));
+}
+
+pub async fn set_role(
+    State(app_state): State<AppState>,
+    Extension(admin): Extension<CacheUser>,
+    Path(user_pid): Path<Uuid>,
+    Json(params): Json<SetRoleParams>,
+) -> Result<Json<AdminUserResponse>, AppError> {
+    let user_id = get_user_id_by_pid(&app_state, &user_pid).await?;
+
+    // Keeps at least the caller as an admin
+    if user_id == admin.id as i64 {
+        warn!("From self role condition");
+        return Err(AppError::WrongCredential);
+    }
+
+    let tx = begin_transaction(&app_state.transactions).await?;
+    set_user_role(user_id, params.role, &tx).await?;
+
+    let details = json!({ "role": params.role.as_str() });
+    audit(&tx, &admin, "user.role", user_id, Some(details)).await?;
+    tx.commit().await?;
+
+    // The role is read from the cached user on every request
+    app_state.invalidate_user(user_id);
+
+    let user = app_state
+        .users
+        .get_user_by_pid(&user_pid.to_string())
+        .await?;
+
+    return Ok(Json(get_admin_user(&app_state, user).await?));
+}
+
+/// Hit and miss counts of the in-process caches since the server started.
+pub async fn get_cache_stats(State(app_state): State<AppState>) -> Json<CacheStatsResponse> {
+    return Json(CacheStatsResponse {
+        caches: vec![
+            app_state.user_cache.stats(),
+            app_state.session_cache.stats(),
+            app_state.profile_cache.stats(),
+        ],
+    });
+}
+
+pub async fn list_audit_logs(
+    State(app_state): State<AppState>,
+    Query(params): Query<AuditLogsParams>,
+) -> Result<Json<AuditLogsResponse>, AppError> {
+    let mut validator = Validator::new();
+    let limit = validator.limit("limit", params.limit, ADMIN_DEFAULT_LIMIT, ADMIN_MAX_LIMIT);
+    let sqids = new_sqids(&app_state.config.sqids_alphabet)?;
+    let before_id = validator.cursor("cursor", params.cursor, &sqids);
+
+    validator.finish()?;
+
+    let target_user_id = match params.user {
+        Some(user_pid) => Some(get_user_id_by_pid(&app_state, &user_pid).await?),
+        None => None,
+    };
+
+    // Fetch one extra row to know whether there is a next page
+    let mut audit_logs =
+        get_audit_logs(target_user_id, before_id, limit + 1, &app_state.db_conn).await?;
+
+    let next_cursor = if audit_logs.len() as i64 > limit {
+        audit_logs.truncate(limit as usize);
+
+        let last_id = audit_logs
+            .last()
+            .and_then(|audit_log| audit_log.id)
+            .ok_or(AppError::InternalServerError)?;
+
+        Some(id_to_sqids(last_id as u64, &sqids)?)
+    } else {
+        None
+    };
+
+    return Ok(Json(AuditLogsResponse {
+        audit_logs: audit_logs.into_iter().map(get_audit_log_as_view).collect(),
+        next_cursor,
+    }));
+}
+
+/// Edits of the user's profile, newest first.
+pub async fn list_profile_revisions(
+    State(app_state): State<AppState>,
+    Path(user_pid): Path<Uuid>,
+    Query(params): Query<ProfileRevisionsParams>,
+) -> Result<Json<ProfileRevisionsResponse>, AppError> {
+    let mut validator = Validator::new();
+    let limit = validator.limit("limit", params.limit, ADMIN_DEFAULT_LIMIT, ADMIN_MAX_LIMIT);
+    let sqids = new_sqids(&app_state.config.sqids_alphabet)?;
+    let before_id = validator.cursor("cursor", params.cursor, &sqids);
+
+    validator.finish()?;
+
+    let user_id = get_user_id_by_pid(&app_state, &user_pid).await?;
+    let profile_id = app_state
+        .profiles
+        .get_profile_id_by_user_id(user_id)
+        .await?;
+
+    // Fetch one extra row to know whether there is a next page
+    let mut revisions = app_state
+        .profiles
+        .get_profile_revisions(profile_id, before_id, limit + 1)
+        .await?;
+
+    let next_cursor = if revisions.len() as i64 > limit {
+        revisions.truncate(limit as usize);
+
+        let last_id = revisions
+            .last()
+            .and_then(|revision| revision.id)
+            .ok_or(AppError::InternalServerError)?;
+
+        Some(id_to_sqids(last_id as u64, &sqids)?)
+    } else {
+        None
+    };
+
+    return Ok(Json(ProfileRevisionsResponse {
+        revisions: revisions
+            .into_iter()
+            .map(get_profile_revision_as_view)
+            .collect(),
+        next_cursor,
+    }));
+}
+
+async fn audit(
+    db_conn: &Connection,
+    admin: &CacheUser,
+    action: &'static str,
+    target_user_id: i64,
+    details: Option<serde_json::Value>,
+) -> Result<(), AppError> {
+    create_audit_log(
+        db_conn,
+        AuditLogParams {
+            actor_user_id: Some(admin.id as i64),
+            action,
+            target_user_id: Some(target_user_id),
+            target_profile_id: None,
+            details,
+        },
+    )
+    .await?;
+
+    return Ok(());
+}
+
+async fn get_user_id_by_pid(app_state: &AppState, user_pid: &Uuid) -> Result<i64, AppError> {
+    let user = app_state
+        .users
+        .get_user_by_pid(&user_pid.to_string())
+        .await?;
+
+    return user.id.ok_or(AppError::InternalServerError);
+}
+
+async fn get_admin_user(app_state: &AppState, user: User) -> Result<AdminUserResponse, AppError> {
+    let user_id = user.id.ok_or(AppError::InternalServerError)?;
+
+    // Users can exist without a profile if registration was interrupted
+    let profile = match app_state.profiles.get_profile_by_user_id(user_id).await {
+        Ok(profile) => Some(profile),
+        Err(AppError::NotFound) => None,
+        Err(err) => return Err(err),
+    };
+
+    return Ok(get_admin_user_as_view(user, profile));
+}
+
+pub fn get_admin_user_as_view(user: User, profile: Option<Profile>) -> AdminUserResponse {
+    return AdminUserResponse {
+        pid: user
+            .pid
+            .and_then(|pid| Uuid::from_slice(pid.as_slice()).ok()),
+        email: user.email,
+        role: user.role,
+        suspended_at: user.suspended_at,
+        created_at: user.created_at,
+        profile: profile.map(get_profile_as_view),
+    };
+}
+
+fn get_audit_log_as_view(audit_log: AuditLog) -> AuditLogResponse {
+    let to_uuid = |pid: Option<Vec<u8>>| pid.and_then(|pid| Uuid::from_slice(pid.as_slice()).ok());
+
+    return AuditLogResponse {
+        actor_user_pid: to_uuid(audit_log.actor_user_pid),
+        action: audit_log.action,
+        target_user_pid: to_uuid(audit_log.target_user_pid),
+        details: audit_log
+            .details
+            .and_then(|details| serde_json::from_str(&details).ok()),
+        created_at: audit_log.created_at,
+    };
+}
+
+fn get_profile_revision_as_view(revision: ProfileRevision) -> ProfileRevisionResponse {
+    let to_json = |value: Option<String>| value.and_then(|value| serde_json::from_str(&value).ok());
+
+    return ProfileRevisionResponse {
+        editor_user_pid: revision
+            .editor_user_pid
+            .and_then(|pid| Uuid::from_slice(pid.as_slice()).ok()),
+        field: revision.field,
+        old_value: to_json(revision.old_value),
+        new_value: to_json(revision.new_value),
+        created_at: revision.created_at,
+    };
+}
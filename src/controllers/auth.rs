@@ -1,118 +1,202 @@
 use crate::{
     app_state::AppState,
-    config::Config,
-    middlewares::jwt_auth::create_jwt_token,
+    controllers::{profile::cache_profile, session::create_session_tokens},
     models::{
-        profile::{
-            create_profile, get_profile_by_user_id, get_profile_id_by_user_id, CacheProfile,
-        },
-        user::{create_user, get_user_by_email, get_user_ids_by_email, CacheUser},
+        audit_log::{create_audit_log, AuditLogParams},
+        profile::Profile,
+        registration_otp::{RegistrationOtp, RegistrationOtpParams},
+        user::{CacheUser, Role},
+    },
+    utils::{
+        app_error::AppError,
+        client_info::ClientInfo,
+        login_throttle::{LoginAttempt, LoginThrottleKey},
+        mailer::Mail,
+        password::{hash_password, verify_password},
+        validation::Validator,
     },
-    utils::{app_error::AppError, password::verify_password},
     views::{
         profile::ProfileParams,
-        user::{LoginParams, LoginResponse, RegisterParams, RegisterResponse},
+        user::{
+            LoginParams, LoginResponse, RegisterParams, RegisterResponse, RegisterStartResponse,
+            RegisterVerifyParams,
+        },
     },
 };
+use argon2::password_hash::rand_core::{OsRng, RngCore};
 use axum::{extract::State, Json};
 use chrono::{Duration, Utc};
-use libsql::Connection;
-use tracing::{error, info, warn};
-use uuid::Uuid;
+use serde_json::json;
+use tracing::warn;
 
-//Production: This must only be called from validate_registration_otp
-pub async fn register_user(
+const OTP_EXPIRY_MINUTE: i64 = 10;
+const OTP_MAX_ATTEMPTS: i64 = 5;
+
+pub async fn start_registration(
     State(app_state): State<AppState>,
     Json(mut params): Json<RegisterParams>,
+) -> Result<Json<RegisterStartResponse>, AppError> {
+    validate_register_params(&mut params)?;
+
+    // A user without a profile is repaired by register_user after verification
+    match app_state.users.get_user_ids_by_email(&params.email).await {
+        Ok((user_id, _)) => match app_state.profiles.get_profile_id_by_user_id(user_id).await {
+            Ok(_) => return Err(AppError::UserAlreadyExist),
+            Err(AppError::NotFound) => {}
+            Err(err) => return Err(err),
+        },
+        Err(AppError::UserDoesNotExist) => {}
+        Err(err) => return Err(err),
+    };
+
+    let code = format!("{:06}", OsRng.next_u32() % 1_000_000);
+    let expires_at = (Utc::now() + Duration::minutes(OTP_EXPIRY_MINUTE)).timestamp();
+
+    app_state
+        .registration_otps
+        .upsert_registration_otp(RegistrationOtpParams {
+            email: params.email.to_owned(),
+            code_hash: hash_password(&code)?,
+            password_hash: hash_password(&params.password)?,
+            first_name: params.first_name,
+            last_name: params.last_name,
+            birth_date: params.birth_date,
+            expires_at,
+        })
+        .await?;
+
+    app_state
+        .mailer
+        .send(Mail {
+            to: params.email.to_owned(),
+            subject: "Your verification code".to_string(),
+            body: format!(
+                "Your verification code is {}. It expires in {} minutes.",
+                code, OTP_EXPIRY_MINUTE
+            ),
+        })
+        .await?;
+
+    return Ok(Json(RegisterStartResponse {
+        email: params.email,
+        expires_at,
+    }));
+}
+
+pub async fn validate_registration_otp(
+    State(app_state): State<AppState>,
+    client_info: ClientInfo,
+    Json(mut params): Json<RegisterVerifyParams>,
 ) -> Result<Json<RegisterResponse>, AppError> {
     params.email = params.email.trim().to_lowercase();
-    params.password = params.password.trim().to_string();
-    params.first_name = params.first_name.trim_matches(' ').to_string();
-    params.last_name = params.last_name.trim_matches(' ').to_string();
+    params.code = params.code.trim().to_string();
 
-    if params.email.is_empty() || params.email.len() > 255 {
-        error!("From email condition");
-        return Err(AppError::WrongCredential);
+    if params.email.is_empty() || params.code.is_empty() {
+        return Err(AppError::MissingCredential);
     }
 
-    if params.password.is_empty() || params.password.len() > 255 {
-        error!("From password condition");
-        return Err(AppError::WrongCredential);
+    let otp = match app_state
+        .registration_otps
+        .get_registration_otp_by_email(&params.email)
+        .await
+    {
+        Ok(otp) => otp,
+        Err(AppError::NotFound) => return Err(AppError::WrongCredential),
+        Err(err) => return Err(err),
+    };
+
+    let otp_id = otp.id.ok_or(AppError::InternalServerError)?;
+    let expires_at = otp.expires_at.unwrap_or(0);
+
+    if expires_at < Utc::now().timestamp() {
+        warn!("From expired otp condition");
+        app_state
+            .registration_otps
+            .delete_registration_otp(otp_id)
+            .await?;
+        return Err(AppError::InvalidToken);
     }
 
-    if params.first_name.is_empty() || params.first_name.len() > 255 {
-        error!("From first_name condition");
-        return Err(AppError::WrongCredential);
+    // Counted before the code is checked, so concurrent guesses can't go over the limit
+    if !app_state
+        .registration_otps
+        .increment_registration_otp_attempts(otp_id, OTP_MAX_ATTEMPTS)
+        .await?
+    {
+        warn!("From exhausted otp condition");
+        app_state
+            .registration_otps
+            .delete_registration_otp(otp_id)
+            .await?;
+        return Err(AppError::InvalidToken);
     }
 
-    if params.last_name.is_empty() || params.last_name.len() > 255 {
-        error!("From last_name condition");
+    let code_hash = otp
+        .code_hash
+        .as_ref()
+        .ok_or(AppError::InternalServerError)?;
+
+    if !verify_password(&params.code, code_hash)? {
         return Err(AppError::WrongCredential);
     }
 
-    // Typically 13 years ago
-    let youngest_birth_date: i64 = (Utc::now() - Duration::days(365 * 13)).timestamp();
+    return register_user(app_state, otp, params.device_label, &client_info).await;
+}
 
-    // i64 not supported by libsql crate for now...
-    // so more than 121 years ago is the limit!
-    let oldest_birth_date: i64 = (Utc::now() - Duration::days(365 * 121)).timestamp();
+fn validate_register_params(params: &mut RegisterParams) -> Result<(), AppError> {
+    let mut validator = Validator::new();
 
-    if youngest_birth_date < params.birth_date || oldest_birth_date > params.birth_date {
-        error!("{:?}", "From birth_date condition");
-        return Err(AppError::WrongCredential);
-    }
+    params.email = validator.email("email", &params.email);
+    params.password = validator.password("password", &params.password);
+    params.first_name = validator.name("first_name", &params.first_name);
+    params.last_name = validator.name("last_name", &params.last_name);
+    validator.birth_date("birth_date", params.birth_date);
 
-    let db_conn = &app_state.db_conn;
+    return validator.finish();
+}
 
-    //Return UserAlreadyExist if there is a user and profile, or...
-    //create profile if there is only user, otherwise...
-    //continue to create user and profile.
-    match get_user_ids_by_email(&params.email, db_conn).await {
-        Ok((user_id, user_pid)) => {
-            match get_profile_id_by_user_id(user_id, db_conn).await {
-                Ok(_) => {
-                    return Err(AppError::UserAlreadyExist);
-                }
-                Err(err) => match err {
-                    AppError::NotFound => {
-                        return create_profile_and_return(
-                            app_state,
-                            user_id,
-                            &user_pid,
-                            &params.first_name,
-                            &params.last_name,
-                            params.birth_date,
-                            &params.email,
-                        )
-                        .await;
-                    }
-                    _ => return Err(err),
-                },
-            };
-        }
+//Production: This must only be called from validate_registration_otp
+async fn register_user(
+    app_state: AppState,
+    otp: RegistrationOtp,
+    device_label: Option<String>,
+    client_info: &ClientInfo,
+) -> Result<Json<RegisterResponse>, AppError> {
+    let otp_id = otp.id.ok_or(AppError::InternalServerError)?;
+    let email = &otp.email.ok_or(AppError::InternalServerError)?;
+    let hashed_password = &otp.password_hash.ok_or(AppError::InternalServerError)?;
+    let first_name = &otp.first_name.ok_or(AppError::InternalServerError)?;
+    let last_name = &otp.last_name.ok_or(AppError::InternalServerError)?;
+    let birth_date = otp.birth_date.ok_or(AppError::InternalServerError)?;
+
+    let profile_params = ProfileParams {
+        user_id: 0,
+        birth_date,
+        first_name: first_name.to_string(),
+        last_name: last_name.to_string(),
+        location: "JPN".to_string(),
+        is_visible: false,
+    };
 
-        Err(err) => match err {
-            AppError::UserDoesNotExist => { /* info!("Creating User and Profile"); */ }
-            _ => return Err(err),
-        },
+    // The code is only used up once the user exists
+    let (user_id, user_pid, profile) = app_state
+        .registration_otps
+        .complete_registration(otp_id, email, hashed_password, profile_params)
+        .await?;
+
+    let cache_user = CacheUser {
+        id: user_id as i32,
+        pid: user_pid,
+        email: email.to_owned(),
+        role: Role::User,
     };
 
-    let (user_id, user_pid) = create_user(&params, &db_conn).await?;
-
-    create_profile_and_return(
-        app_state,
-        user_id,
-        &user_pid,
-        &params.first_name,
-        &params.last_name,
-        params.birth_date,
-        &params.email,
-    )
-    .await
+    finish_registration(app_state, cache_user, profile, device_label, client_info).await
 }
 
 pub async fn login(
     State(app_state): State<AppState>,
+    client_info: ClientInfo,
     Json(mut params): Json<LoginParams>,
 ) -> Result<Json<LoginResponse>, AppError> {
     params.email = params.email.trim().to_lowercase();
@@ -122,7 +206,17 @@ pub async fn login(
         return Err(AppError::MissingCredential);
     }
 
-    let user = get_user_by_email(&params.email, &app_state.db_conn).await?;
+    let throttle_keys = get_login_throttle_keys(&params.email, &client_info);
+    let attempts = reserve_login_attempts(&app_state, &throttle_keys).await?;
+
+    let user = match app_state.users.get_user_by_email(&params.email).await {
+        Ok(user) => user,
+        Err(AppError::UserDoesNotExist) => {
+            record_login_failure(&app_state, &attempts, None, &client_info).await?;
+            return Err(AppError::UserDoesNotExist);
+        }
+        Err(err) => return Err(err),
+    };
 
     // TODO: Return a more specific error in a case of unsupported password login!
     let hashed_password = user
@@ -130,103 +224,146 @@ pub async fn login(
         .as_ref()
         .ok_or(AppError::InternalServerError)?;
 
-    let user_pid_vec = user.pid.as_ref().ok_or(AppError::InternalServerError)?;
-
-    let user_pid = Uuid::from_slice(user_pid_vec.as_slice()).map_err(|err| {
-        error!("{:?}", err);
-        return AppError::InternalServerError;
-    })?;
-
-    let auth_token = create_jwt_token(&app_state.config, &user_pid.to_string())?;
-
     if verify_password(&params.password, hashed_password)? {
-        let cache_user = CacheUser::from(&user)?;
+        for attempt in attempts.iter() {
+            app_state.login_throttle.release_attempt(attempt).await;
+        }
 
         app_state
-            .user_cache
-            .lock()
-            .await
-            .insert(user_pid, cache_user);
+            .login_throttle
+            .reset(&LoginThrottleKey::Account(params.email.to_owned()))
+            .await;
 
-        let user_id = user.id.ok_or(AppError::InternalServerError)?;
-        let db_profile = get_profile_by_user_id(user_id, &app_state.db_conn).await?;
-        let profile_pid_vec = db_profile
-            .pid
-            .as_ref()
-            .ok_or(AppError::InternalServerError)?;
+        if user.suspended_at.is_some() {
+            return Err(AppError::AccountSuspended);
+        }
 
-        let profile_pid = Uuid::from_slice(profile_pid_vec.as_slice()).map_err(|err| {
-            error!("{:?}", err);
-            return AppError::InternalServerError;
-        })?;
+        let cache_user = CacheUser::from(&user)?;
 
-        let cache_profile = CacheProfile::from(&db_profile)?;
+        let (auth_token, refresh_token) =
+            create_session_tokens(&app_state, &cache_user, params.device_label, &client_info)
+                .await?;
 
-        app_state
-            .profile_cache
-            .lock()
-            .await
-            .insert(profile_pid, cache_profile);
+        app_state.user_cache.insert(cache_user.pid, cache_user);
+
+        let user_id = user.id.ok_or(AppError::InternalServerError)?;
+        let db_profile = app_state.profiles.get_profile_by_user_id(user_id).await?;
+        cache_profile(&app_state, &db_profile)?;
 
         return Ok(Json(LoginResponse {
             email: user.email,
             auth_token: Some(auth_token),
+            refresh_token: Some(refresh_token),
         }));
     } else {
+        record_login_failure(&app_state, &attempts, user.id, &client_info).await?;
         return Err(AppError::WrongCredential);
     }
 }
 
-async fn create_profile_and_return(
-    app_state: AppState,
-    user_id: i64,
-    user_pid: &Uuid,
-    first_name: &String,
-    last_name: &String,
-    birth_date: i64,
-    email: &String,
-) -> Result<Json<RegisterResponse>, AppError> {
-    let profile = create_profile(
-        &app_state.db_conn,
-        ProfileParams {
-            user_id,
-            birth_date,
-            first_name: first_name.to_string(),
-            last_name: last_name.to_string(),
-            location: "JPN".to_string(),
-            is_visible: false,
-        },
-    )
-    .await?;
+fn get_login_throttle_keys(email: &str, client_info: &ClientInfo) -> Vec<LoginThrottleKey> {
+    let mut keys = vec![LoginThrottleKey::Account(email.to_owned())];
 
-    let auth_token = create_jwt_token(&app_state.config, &user_pid.to_string())?;
+    if let Some(ip) = &client_info.ip {
+        keys.push(LoginThrottleKey::Ip(ip.to_owned()));
+    }
 
-    let cache_user = CacheUser {
-        id: user_id as i32,
-        pid: *user_pid,
-        email: email.to_owned(),
-    };
+    return keys;
+}
 
-    app_state
-        .user_cache
-        .lock()
-        .await
-        .insert(*user_pid, cache_user);
+/// Counts an attempt against the account and the IP before the password is
+/// checked. Fails with `AppError::TooManyRequests` when either is locked out.
+async fn reserve_login_attempts(
+    app_state: &AppState,
+    throttle_keys: &[LoginThrottleKey],
+) -> Result<Vec<LoginAttempt>, AppError> {
+    let mut attempts: Vec<LoginAttempt> = Vec::new();
+
+    for key in throttle_keys.iter() {
+        match app_state.login_throttle.reserve_attempt(key).await {
+            Ok(attempt) => attempts.push(attempt),
+            Err(retry_after_secs) => {
+                for attempt in attempts.iter() {
+                    app_state.login_throttle.release_attempt(attempt).await;
+                }
 
-    let profile_pid_vec = profile.pid.as_ref().ok_or(AppError::InternalServerError)?;
+                warn!("From login lockout condition");
+                return Err(AppError::TooManyRequests { retry_after_secs });
+            }
+        }
+    }
 
-    let profile_pid = Uuid::from_slice(profile_pid_vec.as_slice()).map_err(|err| {
-        error!("{:?}", err);
-        return AppError::InternalServerError;
-    })?;
+    return Ok(attempts);
+}
 
-    let cache_profile = CacheProfile::from(&profile)?;
+/// Keeps the reserved attempts as failures. Returns
+/// `AppError::TooManyRequests` when the failure locked the account or the IP.
+async fn record_login_failure(
+    app_state: &AppState,
+    attempts: &[LoginAttempt],
+    user_id: Option<i64>,
+    client_info: &ClientInfo,
+) -> Result<(), AppError> {
+    let mut retry_after_secs: Option<u64> = None;
+
+    for attempt in attempts.iter() {
+        let Some(lockout_secs) = attempt.lockout_secs else {
+            continue;
+        };
+
+        let (scope, target_user_id) = match attempt.key {
+            LoginThrottleKey::Account(_) => ("account", user_id),
+            LoginThrottleKey::Ip(_) => ("ip", None),
+        };
+
+        warn!(
+            target = "Security event",
+            scope,
+            ip = client_info.ip,
+            lockout_secs,
+            "Login locked out after repeated failures"
+        );
+
+        create_audit_log(
+            &app_state.db_conn,
+            AuditLogParams {
+                actor_user_id: None,
+                action: "login.lockout",
+                target_user_id,
+                target_profile_id: None,
+                details: Some(json!({
+                    "scope": scope,
+                    "ip": client_info.ip,
+                    "lockout_secs": lockout_secs,
+                })),
+            },
+        )
+        .await?;
+
+        retry_after_secs = retry_after_secs.max(Some(lockout_secs));
+    }
 
-    app_state
-        .profile_cache
-        .lock()
-        .await
-        .insert(profile_pid, cache_profile);
+    return match retry_after_secs {
+        Some(retry_after_secs) => Err(AppError::TooManyRequests { retry_after_secs }),
+        None => Ok(()),
+    };
+}
+
+async fn finish_registration(
+    app_state: AppState,
+    cache_user: CacheUser,
+    profile: Profile,
+    device_label: Option<String>,
+    client_info: &ClientInfo,
+) -> Result<Json<RegisterResponse>, AppError> {
+    let (auth_token, refresh_token) =
+        create_session_tokens(&app_state, &cache_user, device_label, client_info).await?;
+
+    let email = cache_user.email.to_owned();
+
+    app_state.user_cache.insert(cache_user.pid, cache_user);
+
+    cache_profile(&app_state, &profile)?;
 
     Ok(Json(RegisterResponse {
         first_name: profile.first_name,
@@ -234,9 +371,152 @@ async fn create_profile_and_return(
         birth_date: profile.birth_date, //profile.birth_date,
         location: profile.location,
         is_visible: profile.is_visible,
-        email: Some(email.to_owned()),
+        email: Some(email),
         auth_token: Some(auth_token),
+        refresh_token: Some(refresh_token),
     }))
 }
 
-pub async fn validate_registration_otp() {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = "123456";
+    const PASSWORD: &str = "password123";
+
+    fn client_info() -> ClientInfo {
+        return ClientInfo {
+            ip: Some("127.0.0.1".to_string()),
+            user_agent: None,
+        };
+    }
+
+    async fn verify_registration(
+        app_state: &AppState,
+        email: &str,
+        code: &str,
+    ) -> Result<Json<RegisterResponse>, AppError> {
+        let params = RegisterVerifyParams {
+            email: email.to_string(),
+            code: code.to_string(),
+            device_label: None,
+        };
+
+        return validate_registration_otp(State(app_state.clone()), client_info(), Json(params))
+            .await;
+    }
+
+    async fn start(app_state: &AppState, email: &str) {
+        app_state
+            .registration_otps
+            .upsert_registration_otp(RegistrationOtpParams {
+                email: email.to_string(),
+                code_hash: hash_password(CODE).unwrap(),
+                password_hash: hash_password(PASSWORD).unwrap(),
+                first_name: "Aiko".to_string(),
+                last_name: "Sato".to_string(),
+                birth_date: 631152000,
+                expires_at: (Utc::now() + Duration::minutes(OTP_EXPIRY_MINUTE)).timestamp(),
+            })
+            .await
+            .unwrap();
+    }
+
+    async fn login_with(app_state: &AppState, email: &str, password: &str) -> Result<(), AppError> {
+        let params = LoginParams {
+            email: email.to_string(),
+            password: password.to_string(),
+            device_label: None,
+        };
+
+        let Json(response) = login(State(app_state.clone()), client_info(), Json(params)).await?;
+        assert!(response.auth_token.is_some());
+        assert!(response.refresh_token.is_some());
+
+        return Ok(());
+    }
+
+    #[tokio::test]
+    async fn register_user_creates_the_user_and_profile() {
+        let app_state = AppState::for_tests().await;
+        start(&app_state, "aiko@gsm.test").await;
+
+        let Json(response) = verify_registration(&app_state, "aiko@gsm.test", CODE)
+            .await
+            .unwrap();
+        assert_eq!(response.email.as_deref(), Some("aiko@gsm.test"));
+        assert_eq!(response.first_name.as_deref(), Some("Aiko"));
+        assert!(response.auth_token.is_some());
+
+        let (user_id, _) = app_state
+            .users
+            .get_user_ids_by_email("aiko@gsm.test")
+            .await
+            .unwrap();
+        let profile = app_state.profiles.get_profile_by_user_id(user_id).await;
+        assert!(profile.is_ok());
+    }
+
+    #[tokio::test]
+    async fn register_user_rejects_a_registered_email() {
+        let app_state = AppState::for_tests().await;
+        start(&app_state, "aiko@gsm.test").await;
+        assert!(verify_registration(&app_state, "aiko@gsm.test", CODE)
+            .await
+            .is_ok());
+
+        start(&app_state, "aiko@gsm.test").await;
+        let result = verify_registration(&app_state, "aiko@gsm.test", CODE).await;
+        assert!(matches!(result, Err(AppError::UserAlreadyExist)));
+    }
+
+    #[tokio::test]
+    async fn register_user_needs_the_right_code() {
+        let app_state = AppState::for_tests().await;
+        start(&app_state, "aiko@gsm.test").await;
+
+        let result = verify_registration(&app_state, "aiko@gsm.test", "654321").await;
+        assert!(matches!(result, Err(AppError::WrongCredential)));
+
+        let result = app_state.users.get_user_ids_by_email("aiko@gsm.test").await;
+        assert!(matches!(result, Err(AppError::UserDoesNotExist)));
+    }
+
+    #[tokio::test]
+    async fn register_user_stops_guesses_after_the_limit() {
+        let app_state = AppState::for_tests().await;
+        start(&app_state, "aiko@gsm.test").await;
+
+        for _ in 0..OTP_MAX_ATTEMPTS {
+            let result = verify_registration(&app_state, "aiko@gsm.test", "654321").await;
+            assert!(matches!(result, Err(AppError::WrongCredential)));
+        }
+
+        // Even the right code is refused once the guesses are used up
+        let result = verify_registration(&app_state, "aiko@gsm.test", CODE).await;
+        assert!(matches!(result, Err(AppError::InvalidToken)));
+
+        let result = verify_registration(&app_state, "aiko@gsm.test", CODE).await;
+        assert!(matches!(result, Err(AppError::WrongCredential)));
+    }
+
+    #[tokio::test]
+    async fn login_checks_the_password() {
+        let app_state = AppState::for_tests().await;
+        start(&app_state, "aiko@gsm.test").await;
+        assert!(verify_registration(&app_state, "aiko@gsm.test", CODE)
+            .await
+            .is_ok());
+
+        let result = login_with(&app_state, "aiko@gsm.test", "wrong-password").await;
+        assert!(matches!(result, Err(AppError::WrongCredential)));
+
+        let result = login_with(&app_state, "nobody@gsm.test", PASSWORD).await;
+        assert!(matches!(result, Err(AppError::UserDoesNotExist)));
+
+        // Emails are matched case insensitively
+        login_with(&app_state, "Aiko@GSM.test", PASSWORD)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,469 @@
+use std::time::Duration as StdDuration;
+
+use axum::{
+    extract::{
+        ws::{Message as WsMessage, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
+    http::HeaderMap,
+    response::Response,
+    Extension, Json,
+};
+use chrono::Utc;
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedSender},
+    time::{sleep_until, Instant},
+};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    config::RateLimitGroup,
+    controllers::util::{id_to_sqids, new_sqids},
+    middlewares::{
+        jwt_auth::{authorize_user_claims, bearer_token, decode_user_claims},
+        rate_limit::rate_limit_key,
+    },
+    models::{
+        matches::get_match_id,
+        message::{
+            create_message, get_message_by_pid, get_messages_by_match_id, mark_message_delivered,
+            mark_messages_delivered, mark_messages_read, Message, MessageParams,
+        },
+        user::CacheUser,
+    },
+    utils::{
+        app_error::{AppError, ResultExt},
+        validation::Validator,
+    },
+    views::chat::{
+        ChatClientEvent, ChatServerEvent, ChatSocketParams, MessageResponse, MessagesParams,
+        MessagesResponse,
+    },
+};
+
+const MESSAGE_MAX_CHARS: usize = 2000;
+const MESSAGES_DEFAULT_LIMIT: i64 = 50;
+const MESSAGES_MAX_LIMIT: i64 = 100;
+
+/// The profile behind a chat socket.
+#[derive(Clone, Copy)]
+struct ChatProfile {
+    id: i64,
+    pid: Uuid,
+    user_pid: Uuid,
+}
+
+/// Upgrades to the chat socket. Takes the same access token as every other
+/// authenticated route, from the `Authorization` header or `?token=`.
+pub async fn chat_socket(
+    State(app_state): State<AppState>,
+    Query(params): Query<ChatSocketParams>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Result<Response, AppError> {
+    let jwt_token = match bearer_token(&headers) {
+        Some(jwt_token) => jwt_token.to_string(),
+        None => params.token.ok_or(AppError::Unauthorized)?,
+    };
+
+    let user_claims = decode_user_claims(&app_state.config, &jwt_token)?;
+    let (user, _) = authorize_user_claims(&app_state, &user_claims).await?;
+
+    let profile = app_state
+        .profiles
+        .get_profile_by_user_id(user.id as i64)
+        .await?;
+    let me = ChatProfile {
+        id: profile.id.ok_or(AppError::InternalServerError)?,
+        pid: pid_from_vec(profile.pid)?,
+        user_pid: user.pid,
+    };
+
+    // The socket must not outlive the access token it was opened with
+    let seconds_left = (user_claims.exp as i64 - Utc::now().timestamp()).max(0);
+    let expires_at = Instant::now() + StdDuration::from_secs(seconds_left as u64);
+
+    return Ok(ws.on_upgrade(move |socket| handle_socket(app_state, socket, me, expires_at)));
+}
+
+async fn handle_socket(
+    app_state: AppState,
+    mut socket: WebSocket,
+    me: ChatProfile,
+    expires_at: Instant,
+) {
+    let connection_id = Uuid::new_v4();
+    let (sender, mut receiver) = unbounded_channel::<ChatServerEvent>();
+
+    app_state
+        .chat_connections
+        .lock()
+        .await
+        .entry(me.id)
+        .or_default()
+        .insert(connection_id, sender.clone());
+
+    loop {
+        tokio::select! {
+            incoming = socket.recv() => {
+                let text = match incoming {
+                    Some(Ok(WsMessage::Text(text))) => text,
+                    Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                    // Pings are answered by axum, binary frames aren't part of the protocol
+                    Some(Ok(_)) => continue,
+                };
+
+                let result = match serde_json::from_str::<ChatClientEvent>(&text) {
+                    Ok(event) => {
+                        handle_client_event(&app_state, me, connection_id, &sender, event).await
+                    }
+                    Err(err) => {
+                        warn!("{:?}", err);
+                        Err(AppError::WrongCredential)
+                    }
+                };
+
+                if let Err(err) = result {
+                    let (_, err_msg) = err.status_and_message();
+                    let error = err_msg.to_string();
+                    sender.send(ChatServerEvent::Error { error }).ok();
+                }
+            }
+            outgoing = receiver.recv() => {
+                let event = match outgoing {
+                    Some(event) => event,
+                    None => break,
+                };
+
+                let text = match serde_json::to_string(&event) {
+                    Ok(text) => text,
+                    Err(err) => {
+                        error!("{:?}", err);
+                        continue;
+                    }
+                };
+
+                if socket.send(WsMessage::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+            _ = sleep_until(expires_at) => {
+                socket.send(WsMessage::Close(None)).await.ok();
+                break;
+            }
+        }
+    }
+
+    let mut chat_connections = app_state.chat_connections.lock().await;
+
+    if let Some(connections) = chat_connections.get_mut(&me.id) {
+        connections.remove(&connection_id);
+
+        if connections.is_empty() {
+            chat_connections.remove(&me.id);
+        }
+    }
+}
+
+async fn handle_client_event(
+    app_state: &AppState,
+    me: ChatProfile,
+    connection_id: Uuid,
+    sender: &UnboundedSender<ChatServerEvent>,
+    event: ChatClientEvent,
+) -> Result<(), AppError> {
+    let db_conn = &app_state.db_conn;
+
+    match event {
+        ChatClientEvent::Send {
+            to,
+            body,
+            client_id,
+        } => {
+            // Shares the bucket of the messaging routes
+            let decision = app_state
+                .rate_limiter
+                .acquire(
+                    &rate_limit_key(RateLimitGroup::Messaging, &format!("user:{}", me.user_pid)),
+                    &app_state.config.rate_limits.messaging,
+                )
+                .await;
+
+            if !decision.is_allowed {
+                warn!("From rate limit condition");
+                return Err(AppError::TooManyRequests {
+                    retry_after_secs: decision.retry_after_secs,
+                });
+            }
+
+            let body = body.trim().to_string();
+            let mut validator = Validator::new();
+
+            if body.is_empty() {
+                validator.add("body", "required", "Message is required");
+            } else if body.chars().count() > MESSAGE_MAX_CHARS {
+                let message = format!("Message is longer than {} characters", MESSAGE_MAX_CHARS);
+                validator.add("body", "too_long", message);
+            }
+
+            validator.finish()?;
+
+            let recipient = get_matched_profile(app_state, me, to).await?;
+
+            let message = create_message(
+                db_conn,
+                MessageParams {
+                    match_id: recipient.match_id,
+                    sender_profile_id: me.id,
+                    recipient_profile_id: recipient.id,
+                    body,
+                },
+            )
+            .await?;
+
+            let message_id = message.id.ok_or(AppError::InternalServerError)?;
+            let mut message = message_as_view(message, me.pid, to)?;
+
+            let is_delivered = push_event(
+                app_state,
+                recipient.id,
+                None,
+                ChatServerEvent::Message {
+                    message: message.to_owned(),
+                },
+            )
+            .await;
+
+            if is_delivered {
+                mark_message_delivered(message_id, db_conn).await?;
+                message.delivered_at = Some(Utc::now().timestamp());
+            }
+
+            // Keep the sender's other devices in sync
+            push_event(
+                app_state,
+                me.id,
+                Some(connection_id),
+                ChatServerEvent::Message {
+                    message: message.to_owned(),
+                },
+            )
+            .await;
+
+            sender
+                .send(ChatServerEvent::Sent { client_id, message })
+                .ok();
+        }
+
+        ChatClientEvent::Typing { to, is_typing } => {
+            let recipient = get_matched_profile(app_state, me, to).await?;
+
+            push_event(
+                app_state,
+                recipient.id,
+                None,
+                ChatServerEvent::Typing {
+                    from: me.pid,
+                    is_typing,
+                },
+            )
+            .await;
+        }
+
+        ChatClientEvent::Read { message } => {
+            let db_message = get_message_by_pid(&message, db_conn).await?;
+
+            if db_message.recipient_profile_id != Some(me.id) {
+                return Err(AppError::NotFound);
+            }
+
+            let message_id = db_message.id.ok_or(AppError::InternalServerError)?;
+            let match_id = db_message.match_id.ok_or(AppError::InternalServerError)?;
+            let sender_profile_id = db_message
+                .sender_profile_id
+                .ok_or(AppError::InternalServerError)?;
+
+            let updated = mark_messages_read(match_id, me.id, message_id, db_conn).await?;
+
+            if updated > 0 {
+                push_event(
+                    app_state,
+                    sender_profile_id,
+                    None,
+                    ChatServerEvent::Read {
+                        message,
+                        read_at: Utc::now().timestamp(),
+                    },
+                )
+                .await;
+            }
+        }
+    };
+
+    return Ok(());
+}
+
+pub async fn get_messages(
+    State(app_state): State<AppState>,
+    Extension(user): Extension<CacheUser>,
+    Path(profile_pid): Path<Uuid>,
+    Query(params): Query<MessagesParams>,
+) -> Result<Json<MessagesResponse>, AppError> {
+    let mut validator = Validator::new();
+    let limit = validator.limit(
+        "limit",
+        params.limit,
+        MESSAGES_DEFAULT_LIMIT,
+        MESSAGES_MAX_LIMIT,
+    );
+
+    let sqids = new_sqids(&app_state.config.sqids_alphabet)?;
+    let before_id = validator.cursor("cursor", params.cursor, &sqids);
+
+    validator.finish()?;
+
+    let db_conn = &app_state.db_conn;
+    let profile = app_state
+        .profiles
+        .get_profile_by_user_id(user.id as i64)
+        .await?;
+    let me = ChatProfile {
+        id: profile.id.ok_or(AppError::InternalServerError)?,
+        pid: pid_from_vec(profile.pid)?,
+        user_pid: user.pid,
+    };
+
+    let other = get_matched_profile(&app_state, me, profile_pid).await?;
+
+    // Fetch one extra row to know whether there is a next page
+    let mut messages =
+        get_messages_by_match_id(other.match_id, before_id, limit + 1, db_conn).await?;
+
+    let next_cursor = if messages.len() as i64 > limit {
+        messages.truncate(limit as usize);
+
+        let last_id = messages
+            .last()
+            .and_then(|message| message.id)
+            .ok_or(AppError::InternalServerError)?;
+
+        Some(id_to_sqids(last_id as u64, &sqids)?)
+    } else {
+        None
+    };
+
+    // Fetching the history counts as delivery of everything received in it
+    let newest_received = messages
+        .iter()
+        .find(|message| message.recipient_profile_id == Some(me.id));
+
+    if let Some(newest_received) = newest_received {
+        let message_id = newest_received.id.ok_or(AppError::InternalServerError)?;
+        let updated = mark_messages_delivered(other.match_id, me.id, message_id, db_conn).await?;
+
+        if updated > 0 {
+            push_event(
+                &app_state,
+                other.id,
+                None,
+                ChatServerEvent::Delivered {
+                    message: pid_from_vec(newest_received.pid.to_owned())?,
+                    delivered_at: Utc::now().timestamp(),
+                },
+            )
+            .await;
+        }
+    }
+
+    let mut message_responses: Vec<MessageResponse> = Vec::new();
+
+    for message in messages {
+        let (from, to) = if message.sender_profile_id == Some(me.id) {
+            (me.pid, profile_pid)
+        } else {
+            (profile_pid, me.pid)
+        };
+
+        message_responses.push(message_as_view(message, from, to)?);
+    }
+
+    return Ok(Json(MessagesResponse {
+        messages: message_responses,
+        next_cursor,
+    }));
+}
+
+struct MatchedProfile {
+    id: i64,
+    match_id: i64,
+}
+
+/// Only matched profiles can talk to each other, anyone else is Forbidden.
+async fn get_matched_profile(
+    app_state: &AppState,
+    me: ChatProfile,
+    profile_pid: Uuid,
+) -> Result<MatchedProfile, AppError> {
+    let db_conn = &app_state.db_conn;
+
+    let profile = app_state.profiles.get_profile_by_pid(&profile_pid).await?;
+    let profile_id = profile.id.ok_or(AppError::InternalServerError)?;
+
+    let match_id = match get_match_id(me.id, profile_id, db_conn).await {
+        Ok(match_id) => match_id,
+        Err(AppError::NotFound) => {
+            warn!("From unmatched chat condition");
+            return Err(AppError::Forbidden);
+        }
+        Err(err) => return Err(err),
+    };
+
+    return Ok(MatchedProfile {
+        id: profile_id,
+        match_id,
+    });
+}
+
+/// Sends an event to every open socket of a profile, except `skip_connection`.
+/// Returns whether at least one socket got it.
+async fn push_event(
+    app_state: &AppState,
+    profile_id: i64,
+    skip_connection: Option<Uuid>,
+    event: ChatServerEvent,
+) -> bool {
+    let chat_connections = app_state.chat_connections.lock().await;
+    let mut is_sent = false;
+
+    if let Some(connections) = chat_connections.get(&profile_id) {
+        for (connection_id, sender) in connections {
+            if Some(*connection_id) == skip_connection {
+                continue;
+            }
+
+            is_sent = sender.send(event.to_owned()).is_ok() || is_sent;
+        }
+    }
+
+    return is_sent;
+}
+
+fn message_as_view(message: Message, from: Uuid, to: Uuid) -> Result<MessageResponse, AppError> {
+    return Ok(MessageResponse {
+        pid: pid_from_vec(message.pid)?,
+        from,
+        to,
+        body: message.body.unwrap_or_default(),
+        created_at: message.created_at,
+        delivered_at: message.delivered_at,
+        read_at: message.read_at,
+    });
+}
+
+fn pid_from_vec(pid: Option<Vec<u8>>) -> Result<Uuid, AppError> {
+    let pid = pid.ok_or(AppError::InternalServerError)?;
+
+    return Uuid::from_slice(pid.as_slice()).internal("reading a profile pid");
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    controllers::profile::{get_cached_profile, get_cached_profiles},
+    models::{
+        block::is_blocked,
+        like::create_like,
+        matches::{get_matched_profiles, is_matched},
+        profile::{get_cache_profile_as_view, is_profile_discoverable, CacheProfile},
+        user::CacheUser,
+        util::begin_transaction,
+    },
+    utils::app_error::AppError,
+    views::profile::{MatchResponse, SwipeResponse},
+};
+
+pub async fn like_profile(
+    State(app_state): State<AppState>,
+    Extension(user): Extension<CacheUser>,
+    Path(profile_pid): Path<Uuid>,
+) -> Result<Json<SwipeResponse>, AppError> {
+    return swipe(app_state, user, profile_pid, true).await;
+}
+
+pub async fn pass_profile(
+    State(app_state): State<AppState>,
+    Extension(user): Extension<CacheUser>,
+    Path(profile_pid): Path<Uuid>,
+) -> Result<Json<SwipeResponse>, AppError> {
+    return swipe(app_state, user, profile_pid, false).await;
+}
+
+async fn swipe(
+    app_state: AppState,
+    user: CacheUser,
+    profile_pid: Uuid,
+    is_like: bool,
+) -> Result<Json<SwipeResponse>, AppError> {
+    let my_profile_id = app_state
+        .profiles
+        .get_profile_id_by_user_id(user.id as i64)
+        .await?;
+
+    let profile = app_state.profiles.get_profile_by_pid(&profile_pid).await?;
+    let profile_id = profile.id.ok_or(AppError::InternalServerError)?;
+
+    if profile_id == my_profile_id {
+        warn!("From self swipe condition");
+        return Err(AppError::WrongCredential);
+    }
+
+    // The block check, the swipe and the match lookup see the same rows
+    let tx = begin_transaction(&app_state.transactions).await?;
+
+    // Profiles that can't be discovered can't be swiped on either
+    if !is_profile_discoverable(profile_id, &tx).await?
+        || is_blocked(my_profile_id, profile_id, &tx).await?
+    {
+        return Err(AppError::NotFound);
+    }
+
+    let like = create_like(my_profile_id, profile_id, is_like, &tx).await?;
+    let is_match = is_matched(my_profile_id, profile_id, &tx).await?;
+
+    tx.commit().await?;
+
+    return Ok(Json(SwipeResponse {
+        pid: profile_pid,
+        is_like: like.is_like.unwrap_or(is_like),
+        is_match,
+    }));
+}
+
+pub async fn get_matches(
+    State(app_state): State<AppState>,
+    Extension(user): Extension<CacheUser>,
+) -> Result<Json<Vec<MatchResponse>>, AppError> {
+    let my_profile = get_cached_profile(&app_state, user.id as i64).await?;
+    let matched_profiles = get_matched_profiles(my_profile.id as i64, &app_state.db_conn).await?;
+    let user_ids: Vec<i64> = matched_profiles
+        .iter()
+        .map(|matched_profile| matched_profile.user_id)
+        .collect();
+
+    let mut profiles: HashMap<i64, CacheProfile> = get_cached_profiles(&app_state, &user_ids)
+        .await?
+        .into_iter()
+        .map(|profile| (profile.user_id as i64, profile))
+        .collect();
+
+    let matches = matched_profiles
+        .into_iter()
+        .filter_map(|matched_profile| {
+            let profile = profiles.remove(&matched_profile.user_id)?;
+
+            return Some(MatchResponse {
+                profile: get_cache_profile_as_view(profile),
+                matched_at: matched_profile.matched_at,
+            });
+        })
+        .collect();
+
+    return Ok(Json(matches));
+}
@@ -1,3 +1,11 @@
+pub mod account;
+pub mod admin;
 pub mod auth;
+pub mod chat;
+pub mod matches;
+pub mod moderation;
+pub mod password;
 pub mod profile;
+pub mod session;
 pub mod util;
+pub mod video;
@@ -0,0 +1,302 @@
+use axum::{
+    extract::{Path, Query, State},
+    Extension, Json,
+};
+use libsql::Connection;
+use serde_json::json;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    controllers::util::{id_to_sqids, new_sqids},
+    models::{
+        audit_log::{create_audit_log, AuditLogParams},
+        block::create_block,
+        profile_revision::hide_profile_by_moderation,
+        report::{
+            create_report, get_report_by_pid, get_reports, update_report_status, Report,
+            ReportParams as DBReportParams,
+        },
+        session::revoke_user_sessions,
+        user::{set_user_suspended, CacheUser},
+        util::begin_transaction,
+    },
+    utils::{app_error::AppError, validation::Validator},
+    views::moderation::{
+        ReportCreatedResponse, ReportParams, ReportResponse, ReportStatus, ReportsParams,
+        ReportsResponse, ResolveReportParams,
+    },
+};
+
+const REPORT_DETAILS_MAX_CHARS: usize = 2000;
+const REPORTS_DEFAULT_LIMIT: i64 = 50;
+const REPORTS_MAX_LIMIT: i64 = 100;
+
+pub async fn block_profile(
+    State(app_state): State<AppState>,
+    Extension(user): Extension<CacheUser>,
+    Path(profile_pid): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let db_conn = &app_state.db_conn;
+    let my_profile_id = app_state
+        .profiles
+        .get_profile_id_by_user_id(user.id as i64)
+        .await?;
+
+    let profile = app_state.profiles.get_profile_by_pid(&profile_pid).await?;
+    let profile_id = profile.id.ok_or(AppError::InternalServerError)?;
+
+    if profile_id == my_profile_id {
+        warn!("From self block condition");
+        return Err(AppError::WrongCredential);
+    }
+
+    // Discovery, swipes, matches and chat all filter on the blocks table
+    create_block(my_profile_id, profile_id, db_conn).await?;
+
+    return Ok(Json(json!({ "status": "success" })));
+}
+
+pub async fn report_profile(
+    State(app_state): State<AppState>,
+    Extension(user): Extension<CacheUser>,
+    Path(profile_pid): Path<Uuid>,
+    Json(params): Json<ReportParams>,
+) -> Result<Json<ReportCreatedResponse>, AppError> {
+    let mut validator = Validator::new();
+    let details = validator.optional_text("details", params.details, REPORT_DETAILS_MAX_CHARS);
+    validator.finish()?;
+
+    let db_conn = &app_state.db_conn;
+    let my_profile_id = app_state
+        .profiles
+        .get_profile_id_by_user_id(user.id as i64)
+        .await?;
+
+    let profile = app_state.profiles.get_profile_by_pid(&profile_pid).await?;
+    let profile_id = profile.id.ok_or(AppError::InternalServerError)?;
+
+    if profile_id == my_profile_id {
+        warn!("From self report condition");
+        return Err(AppError::WrongCredential);
+    }
+
+    let pid = create_report(
+        db_conn,
+        DBReportParams {
+            reporter_profile_id: my_profile_id,
+            reported_profile_id: profile_id,
+            reason: params.reason.as_str().to_string(),
+            details,
+        },
+    )
+    .await?;
+
+    return Ok(Json(ReportCreatedResponse { pid }));
+}
+
+pub async fn list_reports(
+    State(app_state): State<AppState>,
+    Query(params): Query<ReportsParams>,
+) -> Result<Json<ReportsResponse>, AppError> {
+    let mut validator = Validator::new();
+    let limit = validator.limit(
+        "limit",
+        params.limit,
+        REPORTS_DEFAULT_LIMIT,
+        REPORTS_MAX_LIMIT,
+    );
+
+    let sqids = new_sqids(&app_state.config.sqids_alphabet)?;
+    let after_id = validator.cursor("cursor", params.cursor, &sqids);
+
+    validator.finish()?;
+
+    let after_id = after_id.unwrap_or(0);
+    let status = params.status.map(|status| status.as_str());
+
+    // Fetch one extra row to know whether there is a next page
+    let mut reports = get_reports(status, after_id, limit + 1, &app_state.db_conn).await?;
+
+    let next_cursor = if reports.len() as i64 > limit {
+        reports.truncate(limit as usize);
+
+        let last_id = reports
+            .last()
+            .and_then(|report| report.id)
+            .ok_or(AppError::InternalServerError)?;
+
+        Some(id_to_sqids(last_id as u64, &sqids)?)
+    } else {
+        None
+    };
+
+    return Ok(Json(ReportsResponse {
+        reports: reports.into_iter().map(get_report_as_view).collect(),
+        next_cursor,
+    }));
+}
+
+/// Claims an open report for review.
+pub async fn triage_report(
+    State(app_state): State<AppState>,
+    Extension(admin): Extension<CacheUser>,
+    Path(report_pid): Path<Uuid>,
+) -> Result<Json<ReportResponse>, AppError> {
+    let db_conn = &app_state.db_conn;
+    let report = get_report_by_pid(&report_pid, db_conn).await?;
+    let report_id = report.id.ok_or(AppError::InternalServerError)?;
+
+    if report.status.as_deref() != Some(ReportStatus::Open.as_str()) {
+        warn!("From report status condition");
+        return Err(AppError::WrongCredential);
+    }
+
+    let tx = begin_transaction(&app_state.transactions).await?;
+
+    update_report_status(
+        report_id,
+        ReportStatus::InReview.as_str(),
+        None,
+        admin.id as i64,
+        &tx,
+    )
+    .await?;
+
+    create_audit_log(
+        &tx,
+        AuditLogParams {
+            actor_user_id: Some(admin.id as i64),
+            action: "report.triage",
+            target_user_id: report.reported_user_id,
+            target_profile_id: report.reported_profile_id,
+            details: Some(json!({ "report": report_pid })),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    let report = get_report_by_pid(&report_pid, db_conn).await?;
+
+    return Ok(Json(get_report_as_view(report)));
+}
+
+pub async fn resolve_report(
+    State(app_state): State<AppState>,
+    Extension(admin): Extension<CacheUser>,
+    Path(report_pid): Path<Uuid>,
+    Json(params): Json<ResolveReportParams>,
+) -> Result<Json<ReportResponse>, AppError> {
+    let mut validator = Validator::new();
+
+    if !matches!(
+        params.status,
+        ReportStatus::Resolved | ReportStatus::Dismissed
+    ) {
+        validator.add("status", "invalid_value", "Must be resolved or dismissed");
+    }
+
+    let note = validator.optional_text("note", params.note, REPORT_DETAILS_MAX_CHARS);
+    validator.finish()?;
+
+    let db_conn = &app_state.db_conn;
+    let report = get_report_by_pid(&report_pid, db_conn).await?;
+    let report_id = report.id.ok_or(AppError::InternalServerError)?;
+    let reported_profile_id = report
+        .reported_profile_id
+        .ok_or(AppError::InternalServerError)?;
+    let reported_user_id = report
+        .reported_user_id
+        .ok_or(AppError::InternalServerError)?;
+
+    // The resolution and its audit log are written together or not at all
+    let tx = begin_transaction(&app_state.transactions).await?;
+
+    if params.hide_profile {
+        hide_profile_by_moderation(reported_user_id, admin.id as i64, &tx).await?;
+    }
+
+    if params.suspend_user {
+        suspend_user(reported_user_id, &tx).await?;
+    }
+
+    update_report_status(
+        report_id,
+        params.status.as_str(),
+        note.to_owned(),
+        admin.id as i64,
+        &tx,
+    )
+    .await?;
+
+    create_audit_log(
+        &tx,
+        AuditLogParams {
+            actor_user_id: Some(admin.id as i64),
+            action: "report.resolve",
+            target_user_id: Some(reported_user_id),
+            target_profile_id: Some(reported_profile_id),
+            details: Some(json!({
+                "report": report_pid,
+                "status": params.status.as_str(),
+                "note": note,
+                "hide_profile": params.hide_profile,
+                "suspend_user": params.suspend_user,
+            })),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    if params.hide_profile {
+        app_state.invalidate_profile(reported_user_id);
+    }
+
+    if params.suspend_user {
+        disconnect_user(&app_state, reported_user_id, Some(reported_profile_id)).await;
+    }
+
+    let report = get_report_by_pid(&report_pid, db_conn).await?;
+
+    return Ok(Json(get_report_as_view(report)));
+}
+
+/// Blocks sign in and revokes every session of the user. Call
+/// `disconnect_user` once the write is committed.
+pub async fn suspend_user(user_id: i64, db_conn: &Connection) -> Result<(), AppError> {
+    set_user_suspended(user_id, true, db_conn).await?;
+    revoke_user_sessions(user_id, db_conn).await?;
+
+    return Ok(());
+}
+
+/// Drops the cached user and sessions of a suspended user so access tokens
+/// stop working at once, and closes their chat sockets.
+pub async fn disconnect_user(app_state: &AppState, user_id: i64, profile_id: Option<i64>) {
+    app_state.invalidate_user_sessions(user_id, None);
+    app_state.invalidate_user(user_id);
+
+    // Dropping the senders ends the socket loops
+    if let Some(profile_id) = profile_id {
+        app_state.chat_connections.lock().await.remove(&profile_id);
+    }
+}
+
+pub fn get_report_as_view(report: Report) -> ReportResponse {
+    let to_uuid = |pid: Option<Vec<u8>>| pid.and_then(|pid| Uuid::from_slice(pid.as_slice()).ok());
+
+    return ReportResponse {
+        pid: to_uuid(report.pid),
+        reporter_profile_pid: to_uuid(report.reporter_profile_pid),
+        reported_profile_pid: to_uuid(report.reported_profile_pid),
+        reason: report.reason,
+        details: report.details,
+        status: report.status,
+        resolution_note: report.resolution_note,
+        created_at: report.created_at,
+        updated_at: report.updated_at,
+    };
+}
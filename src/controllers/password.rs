@@ -0,0 +1,179 @@
+use axum::{extract::State, Extension, Json};
+use chrono::{Duration, Utc};
+use libsql::Connection;
+use tracing::warn;
+
+use crate::{
+    app_state::AppState,
+    models::{
+        password_reset::{
+            consume_password_reset_token, create_password_reset_token,
+            get_password_reset_token_by_hash,
+        },
+        session::{revoke_other_user_sessions, revoke_user_sessions, CacheSession},
+        user::{set_user_password, CacheUser},
+        util::begin_transaction,
+    },
+    utils::{
+        app_error::AppError,
+        mailer::Mail,
+        password::{hash_password, verify_password},
+        token::{generate_opaque_token, hash_opaque_token},
+        validation::Validator,
+    },
+    views::user::{ChangePasswordParams, ForgotPasswordParams, ResetPasswordParams},
+};
+
+const PASSWORD_RESET_EXPIRY_MINUTE: i64 = 30;
+
+/// Mails a reset token when the email belongs to a user. The response is the
+/// same either way so the endpoint can't be used to find registered emails.
+pub async fn forgot_password(
+    State(app_state): State<AppState>,
+    Json(params): Json<ForgotPasswordParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let email = params.email.trim().to_lowercase();
+
+    if email.is_empty() {
+        return Err(AppError::MissingCredential);
+    }
+
+    let db_conn = &app_state.db_conn;
+
+    let user = match app_state.users.get_user_by_email(&email).await {
+        Ok(user) => user,
+        Err(AppError::UserDoesNotExist) => {
+            return Ok(Json(serde_json::json!({ "status": "success" })));
+        }
+        Err(err) => return Err(err),
+    };
+
+    let user_id = user.id.ok_or(AppError::InternalServerError)?;
+    let token = issue_password_reset_token(user_id, db_conn).await?;
+
+    app_state
+        .mailer
+        .send(get_password_reset_mail(email, &token))
+        .await?;
+
+    return Ok(Json(serde_json::json!({ "status": "success" })));
+}
+
+/// Sets a new password with a mailed reset token. Every session is revoked
+/// since whoever held the old password may still be signed in.
+pub async fn reset_password(
+    State(app_state): State<AppState>,
+    Json(mut params): Json<ResetPasswordParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let token = params.token.trim();
+
+    if token.is_empty() {
+        return Err(AppError::MissingCredential);
+    }
+
+    validate_new_password(&mut params.new_password)?;
+
+    let db_conn = &app_state.db_conn;
+
+    let reset_token =
+        match get_password_reset_token_by_hash(&hash_opaque_token(token), db_conn).await {
+            Ok(reset_token) => reset_token,
+            Err(AppError::NotFound) => return Err(AppError::InvalidToken),
+            Err(err) => return Err(err),
+        };
+
+    let reset_token_id = reset_token.id.ok_or(AppError::InternalServerError)?;
+    let user_id = reset_token.user_id.ok_or(AppError::InternalServerError)?;
+
+    let hashed_password = hash_password(&params.new_password)?;
+    let tx = begin_transaction(&app_state.transactions).await?;
+
+    if !consume_password_reset_token(reset_token_id, &tx).await? {
+        warn!("From used or expired password reset token condition");
+        return Err(AppError::InvalidToken);
+    }
+
+    set_user_password(user_id, &hashed_password, &tx).await?;
+    revoke_user_sessions(user_id, &tx).await?;
+
+    tx.commit().await?;
+
+    app_state.invalidate_user_sessions(user_id, None);
+    app_state.invalidate_user(user_id);
+
+    return Ok(Json(serde_json::json!({ "status": "success" })));
+}
+
+/// Changes the password of a signed in user and signs out every other device.
+pub async fn change_password(
+    State(app_state): State<AppState>,
+    Extension(user): Extension<CacheUser>,
+    Extension(session): Extension<CacheSession>,
+    Json(mut params): Json<ChangePasswordParams>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    params.current_password = params.current_password.trim().to_string();
+
+    if params.current_password.is_empty() {
+        return Err(AppError::MissingCredential);
+    }
+
+    validate_new_password(&mut params.new_password)?;
+
+    let user_id = user.id as i64;
+    let db_user = app_state.users.get_user_by_id(user_id).await?;
+
+    let hashed_password = db_user
+        .password
+        .as_ref()
+        .ok_or(AppError::InternalServerError)?;
+
+    if !verify_password(&params.current_password, hashed_password)? {
+        warn!("From current password condition");
+        return Err(AppError::WrongCredential);
+    }
+
+    let new_hashed_password = hash_password(&params.new_password)?;
+    let tx = begin_transaction(&app_state.transactions).await?;
+
+    set_user_password(user_id, &new_hashed_password, &tx).await?;
+    revoke_other_user_sessions(user_id, session.id, &tx).await?;
+
+    tx.commit().await?;
+
+    app_state.invalidate_user_sessions(user_id, Some(session.id));
+    app_state.invalidate_user(user_id);
+
+    return Ok(Json(serde_json::json!({ "status": "success" })));
+}
+
+/// Stores a new reset token for the user, voiding any earlier one.
+pub async fn issue_password_reset_token(
+    user_id: i64,
+    db_conn: &Connection,
+) -> Result<String, AppError> {
+    let token = generate_opaque_token();
+    let expires_at = (Utc::now() + Duration::minutes(PASSWORD_RESET_EXPIRY_MINUTE)).timestamp();
+
+    create_password_reset_token(user_id, &hash_opaque_token(&token), expires_at, db_conn).await?;
+
+    return Ok(token);
+}
+
+pub fn get_password_reset_mail(email: String, token: &str) -> Mail {
+    return Mail {
+        to: email,
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Use this token to reset your password: {}. It expires in {} minutes. \
+            If you didn't ask for a reset you can ignore this email.",
+            token, PASSWORD_RESET_EXPIRY_MINUTE
+        ),
+    };
+}
+
+fn validate_new_password(password: &mut String) -> Result<(), AppError> {
+    let mut validator = Validator::new();
+    *password = validator.password("new_password", password);
+
+    return validator.finish();
+}
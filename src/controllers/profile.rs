@@ -1,156 +1,550 @@
-use std::{collections::HashMap, i32};
+use std::collections::HashMap;
 
-use axum::{extract::State, Extension, Json};
+use axum::{
+    extract::{Query, State},
+    Extension, Json,
+};
 use axum_macros::debug_handler;
-use libsql::Value as DBV;
-use serde_json::from_value;
+use chrono::Utc;
+use serde::de::DeserializeOwned;
 use tracing::warn;
-use uuid::Uuid;
 
 use crate::{
     app_state::AppState,
+    controllers::util::{id_to_sqids, new_sqids},
     models::{
-        profile::{get_profile_by_user_id, Profile},
-        user::User,
-        util::{query_get_one, row_to_value_map},
+        profile::{
+            get_cache_profile_as_view, get_profile_as_view, CacheProfile, DiscoverFilter, Profile,
+            ProfileUpdate,
+        },
+        user::CacheUser,
+    },
+    utils::{
+        app_error::AppError,
+        validation::{birth_date_for_age, Validator, MAX_AGE_YEARS, MIN_AGE_YEARS},
     },
-    utils::app_error::AppError,
-    views::profile::ProfileResponse,
+    views::profile::{DiscoverParams, DiscoverResponse, Patch, ProfilePatch, ProfileResponse},
 };
 
+const DISCOVER_DEFAULT_LIMIT: i64 = 20;
+const DISCOVER_MAX_LIMIT: i64 = 50;
+
 pub async fn get_profile(
     State(app_state): State<AppState>,
-    Extension(user): Extension<User>,
+    Extension(user): Extension<CacheUser>,
 ) -> Result<Json<ProfileResponse>, AppError> {
-    let user_id = user.id.ok_or(AppError::NotFound)?;
-    let db_conn = app_state.db_conn;
+    let profile = get_cached_profile(&app_state, user.id as i64).await?;
 
-    let profile = get_profile_by_user_id(user_id, &db_conn).await?;
+    return Ok(Json(get_cache_profile_as_view(profile)));
+}
 
-    // TODO: Abstract this into a function!
-    let pid: Option<Uuid> = match profile.pid {
-        Some(value) => Uuid::from_slice(value.as_slice()).ok(),
-        _ => None,
-    };
+/// Reads the profile of `user_id` from the cache, or from the database and
+/// caches it when it isn't there or has expired.
+pub async fn get_cached_profile(
+    app_state: &AppState,
+    user_id: i64,
+) -> Result<CacheProfile, AppError> {
+    if let Some(profile) = app_state.profile_cache.get(&user_id) {
+        return Ok(profile);
+    }
 
-    return Ok(Json(ProfileResponse {
-        pid,
-        first_name: profile.first_name,
-        last_name: profile.last_name,
-        location: profile.location,
-        birth_date: profile.birth_date,
-        is_visible: profile.is_visible,
-    }));
+    let db_profile = app_state.profiles.get_profile_by_user_id(user_id).await?;
+
+    return cache_profile(app_state, &db_profile);
+}
+
+/// Like `get_cached_profile` for many users, in the order of `user_ids`. The
+/// misses are read with a single query, users without a profile are skipped.
+pub async fn get_cached_profiles(
+    app_state: &AppState,
+    user_ids: &[i64],
+) -> Result<Vec<CacheProfile>, AppError> {
+    let mut cached: HashMap<i64, CacheProfile> = HashMap::new();
+    let mut missing_user_ids: Vec<i64> = Vec::new();
+
+    for user_id in user_ids {
+        match app_state.profile_cache.get(user_id) {
+            Some(profile) => {
+                cached.insert(*user_id, profile);
+            }
+            None => missing_user_ids.push(*user_id),
+        }
+    }
+
+    for db_profile in app_state
+        .profiles
+        .get_profiles_by_user_ids(&missing_user_ids)
+        .await?
+    {
+        let profile = cache_profile(app_state, &db_profile)?;
+        cached.insert(profile.user_id as i64, profile);
+    }
+
+    return Ok(user_ids
+        .iter()
+        .filter_map(|user_id| cached.remove(user_id))
+        .collect());
+}
+
+/// Caches a profile row that was just read or written.
+pub fn cache_profile(app_state: &AppState, db_profile: &Profile) -> Result<CacheProfile, AppError> {
+    let profile = CacheProfile::from(db_profile)?;
+
+    app_state
+        .profile_cache
+        .insert(profile.user_id as i64, profile.to_owned());
+
+    return Ok(profile);
 }
 
 #[debug_handler]
 pub async fn update_profile(
     State(app_state): State<AppState>,
-    Extension(user): Extension<User>,
+    Extension(user): Extension<CacheUser>,
     Json(params): Json<serde_json::Value>,
 ) -> Result<Json<ProfileResponse>, AppError> {
-    // TODO: Return an appropriate error!
-    let user_id = user.id.ok_or(AppError::NotFound)?;
+    let user_id = user.id as i64;
+    let update = get_profile_update(&app_state, user_id, &params, false).await?;
 
-    let mut query_map: HashMap<&str, DBV> = HashMap::new();
+    let profile = app_state
+        .profiles
+        .update_profile(user_id, &update, user_id, None)
+        .await?;
 
-    if let Some(location) = params.get("location") {
-        if let Some(location) = from_value::<String>(location.to_owned()).ok() {
-            // TODO: Check if location exist in a location_map!
-            if location.is_empty() || location.len() > 255 {
-                warn!("From location condition");
-                return Err(AppError::WrongCredential);
-            }
+    cache_profile(&app_state, &profile)?;
+
+    return Ok(Json(get_profile_as_view(profile)));
+}
 
-            query_map.insert(
-                "location",
-                DBV::from(location.trim().to_uppercase().as_str()),
-            );
+/// Validates a profile update sent as a JSON Merge Patch. A moderation edit
+/// may change visibility of a profile hidden by moderation, and hiding a
+/// profile that way stops its owner from showing it again.
+pub async fn get_profile_update(
+    app_state: &AppState,
+    user_id: i64,
+    params: &serde_json::Value,
+    is_moderation_edit: bool,
+) -> Result<ProfileUpdate, AppError> {
+    let mut validator = Validator::new();
+    let patch = parse_profile_patch(params, &mut validator);
+    let mut update = ProfileUpdate::default();
+
+    match patch.location {
+        Patch::Unchanged => {}
+        Patch::Clear => update.location = Some(None),
+        Patch::Set(location) => {
+            update.location = Some(Some(validator.location("location", &location)))
         }
     }
 
-    if let Some(first_name) = params.get("first_name") {
-        if let Some(first_name) = from_value::<String>(first_name.to_owned()).ok() {
-            if first_name.is_empty() || first_name.len() > 24 {
-                warn!("From first_name condition");
-                return Err(AppError::WrongCredential);
-            }
-
-            query_map.insert("first_name", DBV::from(first_name.trim()));
+    match patch.first_name {
+        Patch::Unchanged => {}
+        Patch::Clear => validator.not_nullable("first_name"),
+        Patch::Set(first_name) => {
+            update.first_name = Some(validator.name("first_name", &first_name))
         }
     }
 
-    if let Some(last_name) = params.get("last_name") {
-        if let Some(last_name) = from_value::<String>(last_name.to_owned()).ok() {
-            if last_name.is_empty() || last_name.len() > 24 {
-                warn!("From last_name condition");
-                return Err(AppError::WrongCredential);
-            }
+    match patch.last_name {
+        Patch::Unchanged => {}
+        Patch::Clear => validator.not_nullable("last_name"),
+        Patch::Set(last_name) => update.last_name = Some(validator.name("last_name", &last_name)),
+    }
 
-            query_map.insert("last_name", DBV::from(last_name.trim()));
+    match patch.birth_date {
+        Patch::Unchanged => {}
+        Patch::Clear => validator.not_nullable("birth_date"),
+        Patch::Set(birth_date) => {
+            update.birth_date = Some(validator.birth_date("birth_date", birth_date))
         }
     }
 
-    if let Some(is_visible) = params.get("is_visible") {
-        if let Some(is_visible) = from_value::<bool>(is_visible.to_owned()).ok() {
-            // TODO: Query to check if this value can be updated safely!
-            if is_visible {
-                query_map.insert("is_visible", DBV::from(1));
-            } else {
-                query_map.insert("is_visible", DBV::from(0));
+    match patch.is_visible {
+        Patch::Unchanged => {}
+        Patch::Clear => validator.not_nullable("is_visible"),
+        Patch::Set(is_visible) => update.is_visible = Some(is_visible),
+    }
+
+    validator.finish()?;
+
+    if let Some(is_visible) = update.is_visible {
+        if is_moderation_edit {
+            update.is_hidden_by_moderation = Some(!is_visible);
+        } else if is_visible {
+            let profile = get_cached_profile(app_state, user_id).await?;
+
+            if profile.is_hidden_by_moderation {
+                warn!("From moderation hidden condition");
+                return Err(AppError::Forbidden);
             }
         }
     }
 
-    if let Some(birth_date) = params.get("birth_date") {
-        if let Some(birth_date) = from_value::<i64>(birth_date.to_owned()).ok() {
-            // Typically 18 years ago
-            let youngest_birth_date: i64 = 1136098230;
+    return Ok(update);
+}
 
-            // i64 not supported by libsql crate for now...
-            // so more than 121 years ago is the limit!
-            let oldest_birth_date: i64 = i32::MIN.into();
+/// Reads the keys of a merge patch into their types. Unknown keys and values
+/// of the wrong type are added to `validator` and left unchanged.
+fn parse_profile_patch(params: &serde_json::Value, validator: &mut Validator) -> ProfilePatch {
+    let mut patch = ProfilePatch::default();
 
-            if youngest_birth_date < birth_date || oldest_birth_date > birth_date {
-                warn!("From birth_date condition");
-                return Err(AppError::WrongCredential);
+    let Some(params) = params.as_object() else {
+        validator.invalid_type("", "an object");
+        return patch;
+    };
+
+    for (field, value) in params.iter() {
+        match field.as_str() {
+            "first_name" => {
+                patch.first_name = parse_patch_field(validator, field, value, "a string")
+            }
+            "last_name" => patch.last_name = parse_patch_field(validator, field, value, "a string"),
+            "birth_date" => {
+                patch.birth_date = parse_patch_field(validator, field, value, "a unix timestamp")
             }
+            "location" => patch.location = parse_patch_field(validator, field, value, "a string"),
+            "is_visible" => {
+                patch.is_visible = parse_patch_field(validator, field, value, "a boolean")
+            }
+            _ => validator.unknown_field(field),
+        }
+    }
 
-            query_map.insert("birth_date", DBV::from(birth_date as i32));
+    return patch;
+}
+
+fn parse_patch_field<T: DeserializeOwned>(
+    validator: &mut Validator,
+    field: &str,
+    value: &serde_json::Value,
+    expected: &str,
+) -> Patch<T> {
+    if value.is_null() {
+        return Patch::Clear;
+    }
+
+    match T::deserialize(value) {
+        Ok(value) => return Patch::Set(value),
+        Err(_) => {
+            validator.invalid_type(field, expected);
+            return Patch::Unchanged;
         }
     }
+}
+
+pub async fn discover(
+    State(app_state): State<AppState>,
+    Extension(user): Extension<CacheUser>,
+    Query(params): Query<DiscoverParams>,
+) -> Result<Json<DiscoverResponse>, AppError> {
+    let mut validator = Validator::new();
+    let limit = validator.limit(
+        "limit",
+        params.limit,
+        DISCOVER_DEFAULT_LIMIT,
+        DISCOVER_MAX_LIMIT,
+    );
 
-    let mut query_statement: String = "UPDATE profiles SET".to_string();
-    let mut query_args: Vec<DBV> = Vec::new();
+    // Same bounds as registration
+    let min_age = params.min_age.unwrap_or(MIN_AGE_YEARS as i64);
+    let max_age = params.max_age.unwrap_or(MAX_AGE_YEARS as i64);
+    let age_range = MIN_AGE_YEARS as i64..=MAX_AGE_YEARS as i64;
+    let age_message = format!(
+        "Age must be between {} and {}",
+        MIN_AGE_YEARS, MAX_AGE_YEARS
+    );
 
-    for (k, v) in query_map {
-        query_statement.push_str(" ");
-        query_statement.push_str(k);
-        query_statement.push_str(" = ?,");
-        query_args.push(v);
+    if !age_range.contains(&min_age) {
+        validator.add("min_age", "out_of_range", age_message.to_owned());
     }
 
-    if query_args.is_empty() {
-        warn!("From query_args condition");
-        return Err(AppError::WrongCredential);
+    if !age_range.contains(&max_age) {
+        validator.add("max_age", "out_of_range", age_message);
+    } else if min_age > max_age {
+        validator.add("max_age", "out_of_range", "Can't be less than min_age");
     }
 
-    query_statement.pop();
-    query_statement.push_str(" WHERE user_id = ? RETURNING *");
-    query_args.push(DBV::from(user_id as i32));
+    let sqids = new_sqids(&app_state.config.sqids_alphabet)?;
+    let after_id = validator.cursor("cursor", params.cursor, &sqids);
+
+    validator.finish()?;
+
+    let after_id = after_id.unwrap_or(0);
+
+    let profile = get_cached_profile(&app_state, user.id as i64).await?;
+
+    // Nobody to match against until the caller has set a location
+    let location = match profile.location {
+        Some(location) => location,
+        None => {
+            return Ok(Json(DiscoverResponse {
+                profiles: Vec::new(),
+                next_cursor: None,
+            }));
+        }
+    };
 
-    let db_conn = app_state.db_conn;
-    let row = query_get_one(query_statement.as_str(), query_args, &db_conn).await?;
+    // Someone is `age` years old until the day before they turn `age + 1`
+    let now = Utc::now();
+    let min_birth_date = birth_date_for_age(now, max_age as u32 + 1) + 1;
+    let max_birth_date = birth_date_for_age(now, min_age as u32);
 
-    let profile = Profile::from(row_to_value_map(row));
-    let pid = Uuid::from_slice(profile.pid.unwrap_or(Vec::new()).as_slice()).ok();
+    // Fetch one extra row to know whether there is a next page
+    let mut user_ids = app_state
+        .profiles
+        .get_user_ids_by_location(&DiscoverFilter {
+            location,
+            exclude_profile_id: profile.id as i64,
+            min_birth_date: Some(min_birth_date),
+            max_birth_date: Some(max_birth_date),
+            after_id,
+            limit: limit + 1,
+        })
+        .await?;
 
-    Ok(Json(ProfileResponse {
-        pid,
-        first_name: profile.first_name,
-        last_name: profile.last_name,
-        location: profile.location,
-        birth_date: profile.birth_date,
-        is_visible: profile.is_visible,
-    }))
+    let has_next_page = user_ids.len() as i64 > limit;
+    user_ids.truncate(limit as usize);
+
+    let profiles = get_cached_profiles(&app_state, &user_ids).await?;
+
+    let next_cursor = if has_next_page {
+        let last_id = profiles
+            .last()
+            .map(|profile| profile.id)
+            .ok_or(AppError::InternalServerError)?;
+
+        Some(id_to_sqids(last_id as u64, &sqids)?)
+    } else {
+        None
+    };
+
+    return Ok(Json(DiscoverResponse {
+        profiles: profiles
+            .into_iter()
+            .map(get_cache_profile_as_view)
+            .collect(),
+        next_cursor,
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{models::user::Role, views::profile::ProfileParams};
+
+    async fn create_user(app_state: &AppState) -> CacheUser {
+        let (user_id, user_pid, _) = app_state
+            .users
+            .create_user_with_profile(
+                "aiko@gsm.test",
+                "not-a-real-hash",
+                ProfileParams {
+                    user_id: 0,
+                    birth_date: 631152000,
+                    first_name: "Aiko".to_string(),
+                    last_name: "Sato".to_string(),
+                    location: "JPN".to_string(),
+                    is_visible: false,
+                },
+            )
+            .await
+            .unwrap();
+
+        return CacheUser {
+            id: user_id as i32,
+            pid: user_pid,
+            email: "aiko@gsm.test".to_string(),
+            role: Role::User,
+        };
+    }
+
+    async fn patch(
+        app_state: &AppState,
+        user: &CacheUser,
+        params: serde_json::Value,
+    ) -> Result<ProfileResponse, AppError> {
+        let Json(profile) = update_profile(
+            State(app_state.clone()),
+            Extension(user.to_owned()),
+            Json(params),
+        )
+        .await?;
+
+        return Ok(profile);
+    }
+
+    async fn count_revisions(app_state: &AppState, user: &CacheUser) -> usize {
+        let profile_id = app_state
+            .profiles
+            .get_profile_id_by_user_id(user.id as i64)
+            .await
+            .unwrap();
+
+        return app_state
+            .profiles
+            .get_profile_revisions(profile_id, None, 100)
+            .await
+            .unwrap()
+            .len();
+    }
+
+    fn parse(params: serde_json::Value) -> (ProfilePatch, Vec<(String, &'static str)>) {
+        let mut validator = Validator::new();
+        let patch = parse_profile_patch(&params, &mut validator);
+
+        let errors = match validator.finish() {
+            Ok(()) => Vec::new(),
+            Err(AppError::Validation(fields)) => fields
+                .into_iter()
+                .map(|field| (field.field, field.code))
+                .collect(),
+            Err(err) => panic!("Expected a validation error, got {:?}", err),
+        };
+
+        return (patch, errors);
+    }
+
+    #[test]
+    fn profile_patch_reads_set_cleared_and_missing_keys() {
+        let (patch, errors) = parse(json!({
+            "first_name": "Hana",
+            "birth_date": 631152000,
+            "location": null,
+            "is_visible": false,
+        }));
+
+        assert!(errors.is_empty());
+        assert!(matches!(patch.first_name, Patch::Set(name) if name == "Hana"));
+        assert!(matches!(patch.last_name, Patch::Unchanged));
+        assert!(matches!(patch.birth_date, Patch::Set(631152000)));
+        assert!(matches!(patch.location, Patch::Clear));
+        assert!(matches!(patch.is_visible, Patch::Set(false)));
+    }
+
+    #[test]
+    fn profile_patch_reports_unknown_keys_and_wrong_types() {
+        let (patch, mut errors) = parse(json!({
+            "first_name": 1,
+            "birth_date": "1990-01-01",
+            "is_visible": "yes",
+            "email": "aiko@gsm.test",
+        }));
+
+        assert!(matches!(patch.first_name, Patch::Unchanged));
+        assert!(matches!(patch.birth_date, Patch::Unchanged));
+        assert!(matches!(patch.is_visible, Patch::Unchanged));
+
+        errors.sort();
+
+        assert_eq!(
+            errors,
+            vec![
+                ("birth_date".to_string(), "invalid_type"),
+                ("email".to_string(), "unknown_field"),
+                ("first_name".to_string(), "invalid_type"),
+                ("is_visible".to_string(), "invalid_type"),
+            ]
+        );
+    }
+
+    #[test]
+    fn profile_patch_has_to_be_an_object() {
+        for params in [json!(null), json!([]), json!("first_name")] {
+            let (_, errors) = parse(params);
+            assert_eq!(errors, vec![(String::new(), "invalid_type")]);
+        }
+
+        let (_, errors) = parse(json!({}));
+        assert!(errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn update_profile_applies_the_patch_and_records_revisions() {
+        let app_state = AppState::for_tests().await;
+        let user = create_user(&app_state).await;
+
+        let profile = patch(
+            &app_state,
+            &user,
+            json!({ "first_name": "Hana", "location": "KOR" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(profile.first_name.as_deref(), Some("Hana"));
+        assert_eq!(profile.last_name.as_deref(), Some("Sato"));
+        assert_eq!(profile.location.as_deref(), Some("KOR"));
+
+        // The cached profile is updated too
+        let cached = get_cached_profile(&app_state, user.id as i64)
+            .await
+            .unwrap();
+        assert_eq!(cached.first_name, "Hana");
+
+        assert_eq!(count_revisions(&app_state, &user).await, 2);
+    }
+
+    #[tokio::test]
+    async fn update_profile_leaves_the_profile_alone_on_an_empty_patch() {
+        let app_state = AppState::for_tests().await;
+        let user = create_user(&app_state).await;
+
+        let profile = patch(&app_state, &user, json!({})).await.unwrap();
+        assert_eq!(profile.first_name.as_deref(), Some("Aiko"));
+        assert_eq!(count_revisions(&app_state, &user).await, 0);
+    }
+
+    #[tokio::test]
+    async fn update_profile_rejects_invalid_fields() {
+        let app_state = AppState::for_tests().await;
+        let user = create_user(&app_state).await;
+
+        let result = patch(
+            &app_state,
+            &user,
+            json!({ "first_name": null, "last_name": 1, "age": 30 }),
+        )
+        .await;
+
+        let Err(AppError::Validation(fields)) = result else {
+            panic!("Expected a validation error, got {:?}", result.map(|_| ()));
+        };
+
+        let mut fields: Vec<(String, &str)> = fields
+            .into_iter()
+            .map(|field| (field.field, field.code))
+            .collect();
+        fields.sort();
+
+        assert_eq!(
+            fields,
+            vec![
+                ("age".to_string(), "unknown_field"),
+                ("first_name".to_string(), "not_nullable"),
+                ("last_name".to_string(), "invalid_type"),
+            ]
+        );
+        assert_eq!(count_revisions(&app_state, &user).await, 0);
+    }
+
+    #[tokio::test]
+    async fn update_profile_keeps_a_moderated_profile_hidden() {
+        let app_state = AppState::for_tests().await;
+        let user = create_user(&app_state).await;
+
+        let hide = ProfileUpdate {
+            is_visible: Some(false),
+            is_hidden_by_moderation: Some(true),
+            ..Default::default()
+        };
+        app_state
+            .profiles
+            .update_profile(user.id as i64, &hide, user.id as i64, None)
+            .await
+            .unwrap();
+
+        let result = patch(&app_state, &user, json!({ "is_visible": true })).await;
+        assert!(matches!(result, Err(AppError::Forbidden)));
+    }
 }
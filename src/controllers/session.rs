@@ -0,0 +1,217 @@
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use chrono::{Duration, Utc};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    middlewares::jwt_auth::create_jwt_token,
+    models::{
+        session::{CacheSession, SessionParams},
+        user::CacheUser,
+    },
+    utils::{
+        app_error::AppError,
+        client_info::ClientInfo,
+        token::{generate_opaque_token, hash_opaque_token},
+    },
+    views::session::{RefreshTokenParams, SessionResponse, TokenResponse},
+};
+
+/// Opens a new session for the user and returns `(auth_token, refresh_token)`.
+pub async fn create_session_tokens(
+    app_state: &AppState,
+    user: &CacheUser,
+    device_label: Option<String>,
+    client_info: &ClientInfo,
+) -> Result<(String, String), AppError> {
+    let refresh_token = generate_opaque_token();
+    let expiry_day = app_state.config.refresh_token_expiry_day as i64;
+
+    let device_label = device_label
+        .map(|label| label.trim().chars().take(255).collect::<String>())
+        .filter(|label| !label.is_empty())
+        .or(client_info.user_agent.to_owned());
+
+    let session = app_state
+        .sessions
+        .create_session(SessionParams {
+            user_id: user.id as i64,
+            refresh_token_hash: hash_opaque_token(&refresh_token),
+            device_label,
+            ip: client_info.ip.to_owned(),
+            expires_at: (Utc::now() + Duration::days(expiry_day)).timestamp(),
+        })
+        .await?;
+
+    let cache_session = CacheSession::from(&session)?;
+
+    let auth_token = create_jwt_token(
+        &app_state.config,
+        &user.pid.to_string(),
+        &cache_session.pid.to_string(),
+    )?;
+
+    app_state
+        .session_cache
+        .insert(cache_session.pid, cache_session);
+
+    return Ok((auth_token, refresh_token));
+}
+
+pub async fn refresh_token(
+    State(app_state): State<AppState>,
+    client_info: ClientInfo,
+    Json(params): Json<RefreshTokenParams>,
+) -> Result<Json<TokenResponse>, AppError> {
+    let refresh_token = params.refresh_token.trim();
+
+    if refresh_token.is_empty() {
+        return Err(AppError::MissingCredential);
+    }
+
+    let sessions = &app_state.sessions;
+    let token_hash = hash_opaque_token(refresh_token);
+
+    let session = match sessions
+        .get_session_by_refresh_token_hash(&token_hash)
+        .await
+    {
+        Ok(session) => session,
+        Err(AppError::NotFound) => {
+            // A rotated out token being presented again means it was leaked,
+            // so the whole session is revoked for both parties.
+            if let Ok(session_id) = sessions
+                .get_session_id_by_rotated_token_hash(&token_hash)
+                .await
+            {
+                warn!(
+                    target = "Security event",
+                    session_id, "Refresh token reuse detected, revoking session"
+                );
+                sessions.revoke_session(session_id).await?;
+                evict_session_by_id(&app_state, session_id).await;
+            }
+
+            return Err(AppError::Unauthorized);
+        }
+        Err(err) => return Err(err),
+    };
+
+    let cache_session = CacheSession::from(&session)?;
+
+    if cache_session.is_revoked || cache_session.expires_at < Utc::now().timestamp() {
+        return Err(AppError::Unauthorized);
+    }
+
+    let new_refresh_token = generate_opaque_token();
+    let new_token_hash = hash_opaque_token(&new_refresh_token);
+
+    let is_rotated = sessions
+        .rotate_session_refresh_token(
+            cache_session.id,
+            &token_hash,
+            &new_token_hash,
+            &client_info.ip,
+        )
+        .await?;
+
+    if !is_rotated {
+        // Lost a race against a concurrent refresh with the same token
+        warn!(
+            target = "Security event",
+            session_id = cache_session.id,
+            "Concurrent refresh token use, revoking session"
+        );
+        sessions.revoke_session(cache_session.id).await?;
+        evict_session_by_id(&app_state, cache_session.id).await;
+        return Err(AppError::Unauthorized);
+    }
+
+    let user = app_state
+        .users
+        .get_user_by_id(cache_session.user_id)
+        .await?;
+    let user_pid = CacheUser::from(&user)?.pid;
+
+    let auth_token = create_jwt_token(
+        &app_state.config,
+        &user_pid.to_string(),
+        &cache_session.pid.to_string(),
+    )?;
+
+    return Ok(Json(TokenResponse {
+        auth_token,
+        refresh_token: new_refresh_token,
+    }));
+}
+
+pub async fn logout(
+    State(app_state): State<AppState>,
+    Extension(session): Extension<CacheSession>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    app_state.sessions.revoke_session(session.id).await?;
+    app_state.session_cache.invalidate(&session.pid);
+
+    return Ok(Json(serde_json::json!({ "status": "success" })));
+}
+
+pub async fn get_sessions(
+    State(app_state): State<AppState>,
+    Extension(user): Extension<CacheUser>,
+    Extension(current_session): Extension<CacheSession>,
+) -> Result<Json<Vec<SessionResponse>>, AppError> {
+    let sessions = app_state
+        .sessions
+        .get_active_sessions_by_user_id(user.id as i64)
+        .await?;
+
+    let sessions = sessions
+        .into_iter()
+        .map(|session| {
+            let pid = session
+                .pid
+                .and_then(|pid| Uuid::from_slice(pid.as_slice()).ok());
+
+            SessionResponse {
+                is_current: pid == Some(current_session.pid),
+                pid,
+                device_label: session.device_label,
+                ip: session.ip,
+                created_at: session.created_at,
+                last_used_at: session.last_used_at,
+                expires_at: session.expires_at,
+            }
+        })
+        .collect();
+
+    return Ok(Json(sessions));
+}
+
+pub async fn delete_session(
+    State(app_state): State<AppState>,
+    Extension(user): Extension<CacheUser>,
+    Path(session_pid): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let session = app_state.sessions.get_session_by_pid(&session_pid).await?;
+    let session_id = session.id.ok_or(AppError::InternalServerError)?;
+
+    // Do not leak the existence of sessions owned by other users
+    if session.user_id != Some(user.id as i64) {
+        return Err(AppError::NotFound);
+    }
+
+    app_state.sessions.revoke_session(session_id).await?;
+    app_state.session_cache.invalidate(&session_pid);
+
+    return Ok(Json(serde_json::json!({ "status": "success" })));
+}
+
+async fn evict_session_by_id(app_state: &AppState, session_id: i64) {
+    app_state
+        .session_cache
+        .invalidate_where(|_, session| session.id == session_id);
+}
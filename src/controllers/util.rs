@@ -1,8 +1,8 @@
 use serde_json::Value;
 use sqids::Sqids;
-use tracing::error;
+use uuid::Uuid;
 
-use crate::utils::app_error::AppError;
+use crate::utils::app_error::{AppError, ResultExt};
 
 pub fn i64_from_serde_object(key: &str, map: &Value) -> Option<i64> {
     match map.get(key) {
@@ -27,10 +27,7 @@ pub fn bool_from_serde_object(key: &str, map: &Value) -> Option<bool> {
 
 pub fn string_from_serde_object(key: &str, map: &Value) -> Option<String> {
     match map.get(key) {
-        Some(value) => match value.as_str() {
-            Some(value) => Some(value.to_string()),
-            None => None,
-        },
+        Some(value) => value.as_str().map(|value| value.to_string()),
         None => None,
     }
 }
@@ -44,10 +41,7 @@ pub fn str_from_serde_object(key: &str, map: &Value) -> Option<String> {
 
 pub fn vec_from_serde_object(key: &str, map: &Value) -> Option<Vec<Value>> {
     match map.get(key) {
-        Some(value) => match value.as_array() {
-            Some(value) => Some(value.to_owned()),
-            None => None,
-        },
+        Some(value) => value.as_array().map(|value| value.to_owned()),
         None => None,
     }
 }
@@ -75,33 +69,38 @@ pub fn bool_from_serde_array(index: usize, array: &Value) -> Option<bool> {
 
 pub fn string_from_serde_array(index: usize, array: &Value) -> Option<String> {
     match array.get(index) {
-        Some(value) => match value.as_str() {
-            Some(value) => Some(value.to_string()),
-            None => None,
-        },
+        Some(value) => value.as_str().map(|value| value.to_string()),
         None => None,
     }
 }
 
 pub fn vec_from_serde_array(index: usize, map: &Value) -> Option<Vec<Value>> {
     match map.get(index) {
-        Some(value) => match value.as_array() {
-            Some(value) => Some(value.to_owned()),
-            None => None,
-        },
+        Some(value) => value.as_array().map(|value| value.to_owned()),
         None => None,
     }
 }
 
-pub fn id_to_sqids(id: u64, sqids: Sqids) -> Result<String, AppError> {
-    let sqids = sqids.encode(&[id]).map_err(|err| {
-        error!("{:?}", err);
-        AppError::InternalServerError
-    })?;
+pub fn new_sqids(alphabet: &str) -> Result<Sqids, AppError> {
+    let sqids = Sqids::builder()
+        .alphabet(alphabet.chars().collect())
+        .build()
+        .internal("building sqids")?;
 
     Ok(sqids)
 }
 
-pub fn sqids_to_id(sqids_id: String, sqids: Sqids) -> Vec<u64> {
+pub fn id_to_sqids(id: u64, sqids: &Sqids) -> Result<String, AppError> {
+    let sqids = sqids.encode(&[id]).internal("encoding a sqids cursor")?;
+
+    Ok(sqids)
+}
+
+pub fn sqids_to_id(sqids_id: String, sqids: &Sqids) -> Vec<u64> {
     sqids.decode(sqids_id.as_str())
 }
+
+/// Reads a pid column, None when it is missing or malformed.
+pub fn to_uuid(pid: Option<Vec<u8>>) -> Option<Uuid> {
+    return pid.and_then(|pid| Uuid::from_slice(pid.as_slice()).ok());
+}
@@ -0,0 +1,384 @@
+use axum::{
+    body::Body,
+    extract::{multipart::Field, multipart::MultipartError, Multipart, Path, State},
+    http::{
+        header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE},
+        HeaderMap, StatusCode,
+    },
+    response::Response,
+    Extension, Json,
+};
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    models::{
+        block::is_blocked,
+        profile::ProfileUpdate,
+        user::CacheUser,
+        video::{create_video, delete_video, get_video_by_id, get_video_by_pid, VideoParams},
+    },
+    storage::VideoWriter,
+    utils::app_error::{AppError, ResultExt},
+    views::profile::VideoResponse,
+};
+
+const VIDEO_FIELD_NAME: &str = "video";
+
+// (mime type, file extension)
+const ALLOWED_VIDEO_TYPES: [(&str, &str); 3] = [
+    ("video/mp4", "mp4"),
+    ("video/quicktime", "mov"),
+    ("video/webm", "webm"),
+];
+
+// Enough bytes to hold the first ISO-BMFF box header or the EBML magic
+const SIGNATURE_LEN: usize = 12;
+
+pub async fn upload_profile_video(
+    State(app_state): State<AppState>,
+    Extension(user): Extension<CacheUser>,
+    mut multipart: Multipart,
+) -> Result<Json<VideoResponse>, AppError> {
+    let db_conn = &app_state.db_conn;
+    let profile = app_state
+        .profiles
+        .get_profile_by_user_id(user.id as i64)
+        .await?;
+    let profile_id = profile.id.ok_or(AppError::InternalServerError)?;
+
+    let mut field = loop {
+        let field = multipart
+            .next_field()
+            .await
+            .map_err(from_multipart_error)?
+            .ok_or_else(|| {
+                warn!("From missing video field condition");
+                AppError::WrongCredential
+            })?;
+
+        if field.name() == Some(VIDEO_FIELD_NAME) {
+            break field;
+        }
+    };
+
+    let mime_type = field.content_type().unwrap_or("").to_lowercase();
+
+    let (mime_type, extension) = ALLOWED_VIDEO_TYPES
+        .iter()
+        .find(|(allowed_type, _)| *allowed_type == mime_type)
+        .ok_or(AppError::UnsupportedMediaType)?;
+
+    let video_pid = Uuid::new_v4();
+    let storage_key = format!("{}.{}", video_pid, extension);
+    let video_store = &app_state.video_store;
+
+    let mut writer = video_store.writer(&storage_key).await?;
+    let max_bytes = app_state.config.video_max_bytes;
+
+    let size_bytes = match write_video_field(&mut field, &mut writer, mime_type, max_bytes).await {
+        Ok(size_bytes) => size_bytes,
+        Err(err) => {
+            drop(writer);
+            video_store.delete(&storage_key).await.ok();
+            return Err(err);
+        }
+    };
+
+    let video = match create_video(
+        db_conn,
+        VideoParams {
+            pid: video_pid,
+            profile_id,
+            storage_key: storage_key.to_owned(),
+            mime_type: mime_type.to_string(),
+            size_bytes: size_bytes as i64,
+        },
+    )
+    .await
+    {
+        Ok(video) => video,
+        Err(err) => {
+            video_store.delete(&storage_key).await.ok();
+            return Err(err);
+        }
+    };
+
+    let video_id = video.id.ok_or(AppError::InternalServerError)?;
+    let video_path = format!("/api/videos/{}", video_pid);
+
+    let update = ProfileUpdate {
+        profile_video_id: Some(Some(video_id)),
+        video_path: Some(Some(video_path.to_owned())),
+        ..Default::default()
+    };
+
+    app_state
+        .profiles
+        .update_profile(user.id as i64, &update, user.id as i64, None)
+        .await?;
+    app_state.invalidate_profile(user.id as i64);
+
+    // Only the latest upload is kept per profile
+    if let Some(old_video_id) = profile.profile_video_id {
+        if let Ok(old_video) = get_video_by_id(old_video_id, db_conn).await {
+            delete_video(old_video_id, db_conn).await?;
+
+            if let Some(old_storage_key) = old_video.storage_key {
+                video_store.delete(&old_storage_key).await.ok();
+            }
+        }
+    }
+
+    return Ok(Json(VideoResponse {
+        pid: video_pid,
+        mime_type: mime_type.to_string(),
+        size_bytes: size_bytes as i64,
+        video_path,
+    }));
+}
+
+pub async fn get_video(
+    State(app_state): State<AppState>,
+    Extension(user): Extension<CacheUser>,
+    Path(video_pid): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let db_conn = &app_state.db_conn;
+    let video = get_video_by_pid(&video_pid, db_conn).await?;
+    let video_profile_id = video.profile_id.ok_or(AppError::InternalServerError)?;
+    let my_profile_id = app_state
+        .profiles
+        .get_profile_id_by_user_id(user.id as i64)
+        .await?;
+
+    if is_blocked(my_profile_id, video_profile_id, db_conn).await? {
+        return Err(AppError::NotFound);
+    }
+
+    let storage_key = video.storage_key.ok_or(AppError::InternalServerError)?;
+    let mime_type = video.mime_type.ok_or(AppError::InternalServerError)?;
+
+    let size = app_state.video_store.size(&storage_key).await?;
+
+    let range = headers
+        .get(RANGE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| parse_byte_range(value, size));
+
+    let (status, start, end) = match range {
+        Some(Ok(Some((start, end)))) => (StatusCode::PARTIAL_CONTENT, start, end),
+        Some(Err(_)) => {
+            return build_response(
+                Response::builder()
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(CONTENT_RANGE, format!("bytes */{}", size)),
+                Body::empty(),
+            );
+        }
+        // No Range header, or one we don't support: serve the whole video
+        Some(Ok(None)) | None => (StatusCode::OK, 0, size.saturating_sub(1)),
+    };
+
+    let len = if size == 0 { 0 } else { end - start + 1 };
+    let reader = app_state
+        .video_store
+        .read_range(&storage_key, start, len)
+        .await?;
+
+    let mut response = Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, mime_type)
+        .header(ACCEPT_RANGES, "bytes")
+        .header(CONTENT_LENGTH, len);
+
+    if status == StatusCode::PARTIAL_CONTENT {
+        response = response.header(CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, size));
+    }
+
+    return build_response(response, Body::from_stream(ReaderStream::new(reader)));
+}
+
+async fn write_video_field(
+    field: &mut Field<'_>,
+    writer: &mut VideoWriter,
+    mime_type: &str,
+    max_bytes: u64,
+) -> Result<u64, AppError> {
+    let mut size_bytes: u64 = 0;
+    let mut header: Vec<u8> = Vec::with_capacity(SIGNATURE_LEN);
+
+    while let Some(chunk) = field.chunk().await.map_err(from_multipart_error)? {
+        size_bytes += chunk.len() as u64;
+
+        if size_bytes > max_bytes {
+            return Err(AppError::PayloadTooLarge);
+        }
+
+        if header.len() < SIGNATURE_LEN {
+            let missing = (SIGNATURE_LEN - header.len()).min(chunk.len());
+            header.extend_from_slice(&chunk[..missing]);
+
+            if header.len() == SIGNATURE_LEN && !has_container_signature(mime_type, &header) {
+                return Err(AppError::UnsupportedMediaType);
+            }
+        }
+
+        writer
+            .write_all(&chunk)
+            .await
+            .internal("writing a video upload")?;
+    }
+
+    if header.len() < SIGNATURE_LEN {
+        return Err(AppError::UnsupportedMediaType);
+    }
+
+    writer
+        .shutdown()
+        .await
+        .internal("finishing a video upload")?;
+
+    return Ok(size_bytes);
+}
+
+/// Checks the container magic bytes so the declared MIME type can't be spoofed.
+fn has_container_signature(mime_type: &str, header: &[u8]) -> bool {
+    match mime_type {
+        // ISO base media file: [box size: 4 bytes]["ftyp"]
+        "video/mp4" => &header[4..8] == b"ftyp",
+        // Older QuickTime files can start with other top level atoms
+        "video/quicktime" => matches!(
+            &header[4..8],
+            b"ftyp" | b"moov" | b"mdat" | b"wide" | b"free" | b"skip"
+        ),
+        // EBML magic
+        "video/webm" => header[..4] == [0x1A, 0x45, 0xDF, 0xA3],
+        _ => false,
+    }
+}
+
+/// Parses a single `bytes=` range into inclusive `(start, end)` offsets.
+/// Returns `Ok(None)` for ranges we ignore and `Err` when unsatisfiable.
+fn parse_byte_range(value: &str, size: u64) -> Result<Option<(u64, u64)>, ()> {
+    let spec = match value.trim().strip_prefix("bytes=") {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return Ok(None),
+    };
+
+    let (start, end) = match spec.split_once('-') {
+        Some(bounds) => bounds,
+        None => return Ok(None),
+    };
+
+    let range = match (start.trim(), end.trim()) {
+        ("", "") => return Ok(None),
+        // Suffix range: the last N bytes
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return Err(()),
+            Ok(suffix) => (size.saturating_sub(suffix), size.saturating_sub(1)),
+            Err(_) => return Ok(None),
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) => (start, size.saturating_sub(1)),
+            Err(_) => return Ok(None),
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) => (start, end.min(size.saturating_sub(1))),
+            _ => return Ok(None),
+        },
+    };
+
+    if size == 0 || range.0 >= size || range.0 > range.1 {
+        return Err(());
+    }
+
+    return Ok(Some(range));
+}
+
+fn build_response(
+    builder: axum::http::response::Builder,
+    body: Body,
+) -> Result<Response, AppError> {
+    return builder.body(body).internal("building the video response");
+}
+
+fn from_multipart_error(err: MultipartError) -> AppError {
+    warn!("{:?}", err);
+
+    if err.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        return AppError::PayloadTooLarge;
+    }
+
+    return AppError::WrongCredential;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: u64 = 1000;
+
+    #[test]
+    fn byte_range_reads_bounded_and_open_ranges() {
+        assert_eq!(parse_byte_range("bytes=0-99", SIZE), Ok(Some((0, 99))));
+        assert_eq!(
+            parse_byte_range(" bytes= 900- ", SIZE),
+            Ok(Some((900, 999)))
+        );
+        assert_eq!(
+            parse_byte_range("bytes=999-999", SIZE),
+            Ok(Some((999, 999)))
+        );
+    }
+
+    #[test]
+    fn byte_range_clamps_to_the_size() {
+        assert_eq!(
+            parse_byte_range("bytes=990-2000", SIZE),
+            Ok(Some((990, 999)))
+        );
+        assert_eq!(parse_byte_range("bytes=-100", SIZE), Ok(Some((900, 999))));
+        assert_eq!(parse_byte_range("bytes=-2000", SIZE), Ok(Some((0, 999))));
+    }
+
+    #[test]
+    fn byte_range_ignores_what_it_does_not_support() {
+        for value in [
+            "items=0-99",
+            "bytes=0-1,5-6",
+            "bytes=-",
+            "bytes=5",
+            "bytes=a-b",
+            "bytes=-1-2",
+        ] {
+            assert_eq!(parse_byte_range(value, SIZE), Ok(None), "{}", value);
+        }
+    }
+
+    #[test]
+    fn byte_range_past_the_end_is_unsatisfiable() {
+        for value in ["bytes=1000-", "bytes=1000-1001", "bytes=-0", "bytes=5-2"] {
+            assert_eq!(parse_byte_range(value, SIZE), Err(()), "{}", value);
+        }
+
+        // Nothing of an empty video can be served
+        assert_eq!(parse_byte_range("bytes=0-", 0), Err(()));
+        assert_eq!(parse_byte_range("bytes=-10", 0), Err(()));
+    }
+
+    #[test]
+    fn container_signature_has_to_match_the_mime_type() {
+        let mp4 = b"\x00\x00\x00\x18ftypmp42";
+        let webm = b"\x1A\x45\xDF\xA3\x00\x00\x00\x00\x00\x00\x00\x00";
+
+        assert!(has_container_signature("video/mp4", mp4));
+        assert!(has_container_signature("video/quicktime", mp4));
+        assert!(has_container_signature("video/webm", webm));
+        assert!(!has_container_signature("video/mp4", webm));
+        assert!(!has_container_signature("video/webm", mp4));
+    }
+}
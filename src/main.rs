@@ -1,36 +1,74 @@
+// Explicit `return` statements are the style of this code base, so the lint
+// against them is turned off once instead of at every function
+#![allow(clippy::needless_return)]
+
 pub mod app_state;
+pub mod cli;
+pub mod commands;
 pub mod config;
 pub mod controllers;
 pub mod middlewares;
 pub mod migrations;
 pub mod models;
+pub mod repositories;
+pub mod storage;
 pub mod utils;
 pub mod views;
 
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 
 use axum::{
-    extract::State,
+    extract::{DefaultBodyLimit, State},
     http::StatusCode,
     middleware,
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, patch, post},
     Extension, Json, Router,
 };
 use axum_macros::debug_handler;
+use clap::Parser;
+use cli::{Cli, Command};
 use config::initialize_database;
 use controllers::{
-    auth::login,
-    auth::register_user,
-    profile::{get_profile, update_profile},
+    account::{delete_account, export_account},
+    admin::{
+        get_cache_stats, get_user, list_audit_logs, list_profile_revisions, logout_user,
+        restore_account, search_users, set_role, suspend_account, update_user_profile,
+    },
+    auth::{login, start_registration, validate_registration_otp},
+    chat::{chat_socket, get_messages},
+    matches::{get_matches, like_profile, pass_profile},
+    moderation::{block_profile, list_reports, report_profile, resolve_report, triage_report},
+    password::{change_password, forgot_password, reset_password},
+    profile::{discover, get_profile, update_profile},
+    session::{delete_session, get_sessions, logout, refresh_token},
+    video::{get_video, upload_profile_video},
 };
-use models::{profile::CacheProfile, user::CacheUser};
+use libsql::Connection;
+use migrations::migrate_up;
+use models::{user::CacheUser, util::TransactionSource};
 use serde_json::json;
 use tokio::sync::Mutex;
-use uuid::Uuid;
+use tracing::info;
 
 use crate::{
-    app_state::AppState, config::Config, middlewares::jwt_auth::authenticate, models::user::User,
+    app_state::AppState,
+    config::{Config, RateLimitGroup},
+    middlewares::{
+        jwt_auth::authenticate,
+        rate_limit::{rate_limit, RouteRateLimit},
+        request_id::request_id,
+        role::{require_admin, require_moderator},
+    },
+    repositories::database::LibsqlRepository,
+    storage::local::LocalVideoStore,
+    utils::{
+        app_error::{error_chain, AppError},
+        cache::Cache,
+        login_throttle::init_login_throttle,
+        mailer::init_mailer,
+        rate_limiter::init_rate_limiter,
+    },
 };
 
 async fn check_server_health() -> impl IntoResponse {
@@ -45,7 +83,7 @@ async fn check_db_health(State(AppState { db_conn, .. }): State<AppState>) -> im
     {
         Ok(res) => (
             StatusCode::OK,
-            format!("Database exist {}", res.column_count().to_string()),
+            format!("Database exist {}", res.column_count()),
         ),
         Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
     }
@@ -57,49 +95,205 @@ async fn main() {
 
     tracing_subscriber::fmt::init();
 
-    let config = Config::init();
-    let mut can_use_local_db: bool = false;
-    let env_args = std::env::args().collect::<Vec<String>>();
+    let cli = Cli::parse();
+
+    let config = match Config::load(&cli.config.into_overrides()) {
+        Ok(config) => config,
+        Err(errors) => {
+            eprint!("{}", errors);
+            std::process::exit(1);
+        }
+    };
+
+    let (db_conn, transactions) = match initialize_database(&config) {
+        Ok(database) => database,
+        Err(errors) => {
+            eprint!("{}", errors);
+            std::process::exit(1);
+        }
+    };
+
+    transactions
+        .configure_connection(&db_conn)
+        .await
+        .expect("Should configure the database connection");
 
-    if env_args.len() > 1 {
-        if env_args[1] == "local_db" {
-            can_use_local_db = true;
+    let result = match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => {
+            serve(config, db_conn, transactions).await;
+            return;
         }
+        Command::Migrate { action } => {
+            commands::migrate::run(action, &db_conn, &transactions).await
+        }
+        Command::CreateAdmin(args) => commands::create_admin::run(args, &transactions).await,
+        Command::Seed(args) => commands::seed::run(args, &config, &db_conn, &transactions).await,
+        Command::User { action } => {
+            commands::user::run(action, &config, &db_conn, &transactions).await
+        }
+    };
+
+    if let Err(err) = result {
+        eprintln!("Error: {}", error_chain(&err));
+
+        if let AppError::Validation(fields) = err {
+            for field in fields {
+                eprintln!("  {}: {}", field.field, field.message);
+            }
+        }
+
+        std::process::exit(1);
     }
+}
 
-    let db_conn = initialize_database(&config, can_use_local_db);
-    let profile_cache: Arc<Mutex<HashMap<Uuid, CacheProfile>>> =
-        Arc::new(Mutex::new(HashMap::new()));
-    let user_cache: Arc<Mutex<HashMap<Uuid, CacheUser>>> = Arc::new(Mutex::new(HashMap::new()));
+async fn serve(config: Config, db_conn: Connection, transactions: TransactionSource) {
+    let applied = migrate_up(&db_conn, &transactions)
+        .await
+        .expect("Should apply pending migrations");
+    info!("Applied {} pending migration(s)", applied.len());
+
+    let cache_size = config.cache.max_entries as usize;
+    let cache_ttl = Duration::from_secs(config.cache.ttl_secs);
+    let profile_cache = Arc::new(Cache::new("profiles", cache_size, cache_ttl));
+    let user_cache = Arc::new(Cache::new("users", cache_size, cache_ttl));
+    let session_cache = Arc::new(Cache::new("sessions", cache_size, cache_ttl));
+    let chat_connections = Arc::new(Mutex::new(HashMap::new()));
+    let mailer = init_mailer(&config);
+    let video_store = Arc::new(LocalVideoStore {
+        root: PathBuf::from(&config.video_storage_path),
+    });
+    // Multipart framing adds a little on top of the video itself
+    let video_body_limit = config.video_max_bytes as usize + 1024 * 1024;
+
+    let bind_address = config.bind_address;
+
+    let repository = Arc::new(LibsqlRepository {
+        db_conn: db_conn.clone(),
+        transactions: transactions.clone(),
+    });
 
     let app_state = AppState {
         config,
         db_conn,
+        transactions,
+        users: repository.clone(),
+        profiles: repository.clone(),
+        registration_otps: repository.clone(),
+        sessions: repository,
         profile_cache,
         user_cache,
+        session_cache,
+        mailer,
+        video_store,
+        chat_connections,
+        login_throttle: init_login_throttle(),
+        rate_limiter: init_rate_limiter(),
+    };
+
+    let rate_limit_layer = |group: RateLimitGroup| {
+        middleware::from_fn_with_state(RouteRateLimit::new(&app_state, group), rate_limit)
     };
 
+    // INFO: A route_layer added later runs earlier, every rate limit layer must
+    // be added before authenticate so it can see the authenticated user!
+    let moderator_router = Router::new()
+        .route("/api/admin/reports", get(list_reports))
+        .route("/api/admin/reports/:pid/triage", post(triage_report))
+        .route("/api/admin/reports/:pid/resolve", post(resolve_report))
+        .route("/api/admin/users", get(search_users))
+        .route("/api/admin/users/:pid", get(get_user))
+        .route(
+            "/api/admin/users/:pid/profile/revisions",
+            get(list_profile_revisions),
+        )
+        .route_layer(middleware::from_fn(require_moderator))
+        .route_layer(rate_limit_layer(RateLimitGroup::Default));
+
+    let admin_router = Router::new()
+        .route("/api/admin/users/:pid/profile", post(update_user_profile))
+        .route("/api/admin/users/:pid/suspend", post(suspend_account))
+        .route("/api/admin/users/:pid/restore", post(restore_account))
+        .route("/api/admin/users/:pid/logout", post(logout_user))
+        .route("/api/admin/users/:pid/role", post(set_role))
+        .route("/api/admin/audit_logs", get(list_audit_logs))
+        .route("/api/admin/cache", get(get_cache_stats))
+        .route_layer(middleware::from_fn(require_admin))
+        .route_layer(rate_limit_layer(RateLimitGroup::Default));
+
+    let profile_router = Router::new()
+        .route("/api/me/profile", patch(update_profile))
+        .route(
+            "/api/profile/video",
+            post(upload_profile_video).layer(DefaultBodyLimit::max(video_body_limit)),
+        )
+        .route("/api/password/change", post(change_password))
+        .route_layer(rate_limit_layer(RateLimitGroup::Profile));
+
+    let swipe_router = Router::new()
+        .route("/api/profiles/:pid/like", post(like_profile))
+        .route("/api/profiles/:pid/pass", post(pass_profile))
+        .route("/api/profiles/:pid/block", post(block_profile))
+        .route("/api/profiles/:pid/report", post(report_profile))
+        .route_layer(rate_limit_layer(RateLimitGroup::Swipe));
+
+    // Messages sent over the chat socket take from the same bucket
+    let messaging_router = Router::new()
+        .route("/api/matches/:pid/messages", get(get_messages))
+        .route_layer(rate_limit_layer(RateLimitGroup::Messaging));
+
+    let public_router = Router::new()
+        // Authenticates itself, the token may come as a query parameter
+        .route("/api/chat/ws", get(chat_socket))
+        .route("/api/login", post(login))
+        .route("/api/token/refresh", post(refresh_token))
+        .route("/api/password/forgot", post(forgot_password))
+        .route("/api/password/reset", post(reset_password))
+        .route("/api/register/start", post(start_registration))
+        .route("/api/register/verify", post(validate_registration_otp))
+        .route_layer(rate_limit_layer(RateLimitGroup::Auth));
+
     let router = Router::new()
-        .route("/api/update_profile", post(update_profile))
+        .route("/api/account", delete(delete_account))
+        .route("/api/account/export", get(export_account))
         .route("/api/get_profile", get(get_profile))
+        .route("/api/discover", get(discover))
+        .route("/api/matches", get(get_matches))
         .route("/check_auth", get(check_auth_route))
+        .route("/api/logout", post(logout))
+        .route("/api/sessions", get(get_sessions))
+        .route("/api/sessions/:pid", delete(delete_session))
+        .route("/api/videos/:pid", get(get_video))
+        .route_layer(rate_limit_layer(RateLimitGroup::Default))
+        .merge(moderator_router)
+        .merge(admin_router)
+        .merge(profile_router)
+        .merge(swipe_router)
+        .merge(messaging_router)
         .route_layer(middleware::from_fn_with_state(
             app_state.clone(),
             authenticate,
         ))
-        .route("/api/login", post(login))
-        .route("/api/register", post(register_user))
+        .merge(public_router)
         .route("/server_health", get(check_server_health))
         .route("/db_health", get(check_db_health))
-        .with_state(app_state);
+        .with_state(app_state)
+        .layer(middleware::from_fn(request_id));
 
-    let listener = tokio::net::TcpListener::bind("[::]:8080").await.unwrap();
-    axum::serve(listener, router).await.unwrap();
+    let listener = tokio::net::TcpListener::bind(bind_address)
+        .await
+        .expect("Should bind the listen address");
+    info!("Listening on {}", bind_address);
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
 
 #[debug_handler]
 pub async fn check_auth_route(
-    Extension(user): Extension<User>,
+    Extension(user): Extension<CacheUser>,
 ) -> Result<impl IntoResponse, StatusCode> {
     let json_response = json!({
         "status": "success",
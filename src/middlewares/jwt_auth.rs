@@ -1,4 +1,10 @@
-use axum::{body::Body, extract::State, http::Request, middleware::Next, response::IntoResponse};
+use axum::{
+    body::Body,
+    extract::State,
+    http::{HeaderMap, Request},
+    middleware::Next,
+    response::IntoResponse,
+};
 use jsonwebtoken::{encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 use tracing::error;
@@ -7,8 +13,8 @@ use uuid::Uuid;
 use crate::{
     app_state::AppState,
     config::Config,
-    models::user::{get_user_by_pid, CacheUser},
-    utils::app_error::AppError,
+    models::{session::CacheSession, user::CacheUser},
+    utils::app_error::{AppError, ResultExt},
 };
 
 const AUTHORIZATION: &str = "Authorization";
@@ -19,31 +25,35 @@ pub async fn authenticate(
     mut request: Request<Body>,
     next: Next,
 ) -> Result<impl IntoResponse, AppError> {
-    // Get authorization header from the http request
-    let authorization_header = match request.headers().get(AUTHORIZATION) {
-        Some(auth_header) => auth_header,
-        None => return Err(AppError::Unauthorized),
-    };
+    let jwt_token = bearer_token(request.headers()).ok_or(AppError::Unauthorized)?;
+    let user_claims = decode_user_claims(&app_state.config, jwt_token)?;
+    let (user, session) = authorize_user_claims(&app_state, &user_claims).await?;
 
-    let authorization = authorization_header.to_str().map_err(|err| {
-        error!("{:?}", err);
-        return AppError::Unauthorized;
-    })?;
+    request.extensions_mut().insert(user);
+    request.extensions_mut().insert(session);
+    return Ok(next.run(request).await);
+}
+
+/// Gets the token from an `Authorization: Bearer <token>` header.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let authorization = headers.get(AUTHORIZATION)?.to_str().ok()?;
 
     if !authorization.starts_with(BEARER) {
-        return Err(AppError::Unauthorized);
+        return None;
     }
 
-    let jwt_token = authorization.trim_start_matches(BEARER).trim();
+    return Some(authorization.trim_start_matches(BEARER).trim());
+}
 
+pub fn decode_user_claims(config: &Config, jwt_token: &str) -> Result<UserClaims, AppError> {
     let token_header = jsonwebtoken::decode_header(jwt_token).map_err(|err| {
         error!("{:?}", err);
         return AppError::Unauthorized;
     })?;
 
-    let user_claims = jsonwebtoken::decode::<UserClaims>(
+    let token_data = jsonwebtoken::decode::<UserClaims>(
         jwt_token,
-        &DecodingKey::from_secret(app_state.config.jwt_secret.as_bytes()),
+        &DecodingKey::from_secret(config.jwt_secret.as_bytes()),
         &Validation::new(token_header.alg),
     )
     .map_err(|err| {
@@ -51,43 +61,93 @@ pub async fn authenticate(
         return AppError::Unauthorized;
     })?;
 
-    let user_ref = user_claims.claims.sub;
+    return Ok(token_data.claims);
+}
 
-    let user_pid = Uuid::parse_str(user_ref.as_str()).map_err(|err| {
-        error!("{:?}", err);
-        return AppError::InternalServerError;
-    })?;
+/// Resolves the user and session behind already decoded claims, rejecting
+/// revoked or expired sessions.
+pub async fn authorize_user_claims(
+    app_state: &AppState,
+    user_claims: &UserClaims,
+) -> Result<(CacheUser, CacheSession), AppError> {
+    let user_ref = user_claims.sub.to_owned();
+
+    let user_pid =
+        Uuid::parse_str(user_ref.as_str()).internal("reading the user pid of a token")?;
 
-    let user = if let Some(value) = app_state.user_cache.lock().await.get(&user_pid) {
-        value.to_owned()
+    let cached_user = app_state.user_cache.get(&user_pid);
+
+    let user = if let Some(value) = cached_user {
+        value
     } else {
-        let db_conn = app_state.db_conn;
-        let db_user = get_user_by_pid(&user_ref, &db_conn).await.map_err(|err| {
-            error!("{:?}", err);
-            return AppError::Unauthorized;
-        })?;
+        let db_user = app_state
+            .users
+            .get_user_by_pid(&user_ref)
+            .await
+            .map_err(|err| {
+                error!("{:?}", err);
+                return AppError::Unauthorized;
+            })?;
 
         let cache_user = CacheUser::from(&db_user)?;
 
         app_state
             .user_cache
-            .lock()
-            .await
             .insert(cache_user.pid, cache_user.to_owned());
 
         cache_user
     };
 
-    request.extensions_mut().insert(user);
-    return Ok(next.run(request).await);
+    let session_pid = Uuid::parse_str(user_claims.sid.as_str()).map_err(|err| {
+        error!("{:?}", err);
+        return AppError::Unauthorized;
+    })?;
+
+    let cached_session = app_state.session_cache.get(&session_pid);
+
+    let session = if let Some(value) = cached_session {
+        value
+    } else {
+        let db_session = app_state
+            .sessions
+            .get_session_by_pid(&session_pid)
+            .await
+            .map_err(|err| {
+                error!("{:?}", err);
+                return AppError::Unauthorized;
+            })?;
+
+        let cache_session = CacheSession::from(&db_session)?;
+
+        app_state
+            .session_cache
+            .insert(cache_session.pid, cache_session.to_owned());
+
+        cache_session
+    };
+
+    // Revoked or foreign sessions invalidate the access token before it expires
+    if session.is_revoked
+        || session.user_id != user.id as i64
+        || session.expires_at < chrono::Utc::now().timestamp()
+    {
+        return Err(AppError::Unauthorized);
+    }
+
+    return Ok((user, session));
 }
 
-pub fn create_jwt_token(config: &Config, user_pid: &String) -> Result<String, AppError> {
+pub fn create_jwt_token(
+    config: &Config,
+    user_pid: &str,
+    session_pid: &str,
+) -> Result<String, AppError> {
     let now = chrono::Utc::now();
     let jwt_expiry_minute = config.jwt_expiry_minute;
     let exp = (now + chrono::Duration::minutes(jwt_expiry_minute as i64)).timestamp() as u64;
     let claims = UserClaims {
         sub: user_pid.to_owned(),
+        sid: session_pid.to_owned(),
         exp,
     };
     let jwt_secret = &config.jwt_secret;
@@ -96,16 +156,13 @@ pub fn create_jwt_token(config: &Config, user_pid: &String) -> Result<String, Ap
     return Ok(jwt_token);
 }
 
-fn encode_user_claims(user_claims: &UserClaims, secret: &String) -> Result<String, AppError> {
+fn encode_user_claims(user_claims: &UserClaims, secret: &str) -> Result<String, AppError> {
     let token = encode(
         &Header::default(),
         user_claims,
-        &EncodingKey::from_secret(secret.as_ref()),
+        &EncodingKey::from_secret(secret.as_bytes()),
     )
-    .map_err(|err| {
-        error!("{:?}", err);
-        return AppError::InternalServerError;
-    })?;
+    .internal("encoding an access token")?;
 
     return Ok(token);
 }
@@ -113,5 +170,6 @@ fn encode_user_claims(user_claims: &UserClaims, secret: &String) -> Result<Strin
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserClaims {
     pub sub: String,
+    pub sid: String,
     pub exp: u64,
 }
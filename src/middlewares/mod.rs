@@ -1 +1,4 @@
 pub mod jwt_auth;
+pub mod rate_limit;
+pub mod request_id;
+pub mod role;
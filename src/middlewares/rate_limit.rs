@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{FromRef, State},
+    http::{HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tracing::warn;
+
+use crate::{
+    app_state::AppState,
+    config::{RateLimitGroup, RateLimitPolicy},
+    models::user::CacheUser,
+    utils::{
+        app_error::AppError,
+        client_info::{ClientInfo, TrustedProxies},
+        rate_limiter::{RateLimitDecision, RateLimiter},
+    },
+};
+
+/// The limit of one route group, used as the state of `rate_limit`.
+#[derive(Clone)]
+pub struct RouteRateLimit {
+    pub group: RateLimitGroup,
+    pub policy: RateLimitPolicy,
+    pub limiter: Arc<dyn RateLimiter>,
+    pub trusted_proxies: TrustedProxies,
+}
+
+impl FromRef<RouteRateLimit> for TrustedProxies {
+    fn from_ref(route_limit: &RouteRateLimit) -> Self {
+        return route_limit.trusted_proxies.clone();
+    }
+}
+
+impl RouteRateLimit {
+    pub fn new(app_state: &AppState, group: RateLimitGroup) -> Self {
+        return Self {
+            group,
+            policy: app_state.config.rate_limits.policy_for(group),
+            limiter: app_state.rate_limiter.clone(),
+            trusted_proxies: TrustedProxies::from_ref(app_state),
+        };
+    }
+}
+
+// Must run after `jwt_auth::authenticate` to limit by user, routes without a
+// `CacheUser` are limited by client IP.
+pub async fn rate_limit(
+    State(route_limit): State<RouteRateLimit>,
+    client_info: ClientInfo,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let subject = match request.extensions().get::<CacheUser>() {
+        Some(user) => format!("user:{}", user.pid),
+        None => format!("ip:{}", client_info.ip.as_deref().unwrap_or("unknown")),
+    };
+    let key = rate_limit_key(route_limit.group, &subject);
+
+    let decision = route_limit.limiter.acquire(&key, &route_limit.policy).await;
+
+    let mut response = if decision.is_allowed {
+        next.run(request).await
+    } else {
+        warn!("From rate limit condition");
+        AppError::TooManyRequests {
+            retry_after_secs: decision.retry_after_secs,
+        }
+        .into_response()
+    };
+
+    set_rate_limit_headers(&mut response, &route_limit.policy, &decision);
+
+    return response;
+}
+
+/// Buckets are per group, so hammering one group leaves the others usable.
+pub fn rate_limit_key(group: RateLimitGroup, subject: &str) -> String {
+    return format!("{}:{}", group.as_str(), subject);
+}
+
+/// Adds the `RateLimit-*` headers of the IETF rate limit headers draft.
+fn set_rate_limit_headers(
+    response: &mut Response,
+    policy: &RateLimitPolicy,
+    decision: &RateLimitDecision,
+) {
+    let headers = response.headers_mut();
+
+    headers.insert(
+        HeaderName::from_static("ratelimit-limit"),
+        HeaderValue::from(decision.limit),
+    );
+    headers.insert(
+        HeaderName::from_static("ratelimit-remaining"),
+        HeaderValue::from(decision.remaining),
+    );
+    headers.insert(
+        HeaderName::from_static("ratelimit-reset"),
+        HeaderValue::from(decision.reset_secs),
+    );
+
+    if let Ok(value) =
+        HeaderValue::from_str(&format!("{};w={}", policy.requests, policy.per_seconds))
+    {
+        headers.insert(HeaderName::from_static("ratelimit-policy"), value);
+    }
+}
@@ -0,0 +1,43 @@
+use axum::{
+    body::Body,
+    http::{HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use tracing::{error_span, Instrument};
+
+use crate::utils::request_id::{request_id_from_header, with_request_id};
+
+const X_REQUEST_ID: &str = "x-request-id";
+const FLY_REQUEST_ID: &str = "fly-request-id";
+
+// Must be the outermost layer so every log line and error body of the
+// request carries the id, including those of the other middlewares.
+pub async fn request_id(request: Request<Body>, next: Next) -> Response {
+    let headers = request.headers();
+    let header_value = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+
+    let request_id =
+        request_id_from_header(header_value(X_REQUEST_ID).or_else(|| header_value(FLY_REQUEST_ID)));
+
+    // At the error level so the span is kept whenever anything of the request
+    // is logged, the default filter only lets errors through
+    let span = error_span!(
+        "request",
+        request_id = %request_id,
+        method = %request.method(),
+        path = %request.uri().path(),
+    );
+
+    let mut response = with_request_id(request_id.to_owned(), next.run(request))
+        .instrument(span)
+        .await;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(X_REQUEST_ID), value);
+    }
+
+    return response;
+}
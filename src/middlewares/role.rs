@@ -0,0 +1,38 @@
+use axum::{body::Body, http::Request, middleware::Next, response::IntoResponse, Extension};
+use tracing::warn;
+
+use crate::{
+    models::user::{CacheUser, Role},
+    utils::app_error::AppError,
+};
+
+// Both must run after `jwt_auth::authenticate`, which provides the `CacheUser`.
+
+pub async fn require_moderator(
+    Extension(user): Extension<CacheUser>,
+    request: Request<Body>,
+    next: Next,
+) -> Result<impl IntoResponse, AppError> {
+    check_role(&user, Role::Moderator)?;
+
+    return Ok(next.run(request).await);
+}
+
+pub async fn require_admin(
+    Extension(user): Extension<CacheUser>,
+    request: Request<Body>,
+    next: Next,
+) -> Result<impl IntoResponse, AppError> {
+    check_role(&user, Role::Admin)?;
+
+    return Ok(next.run(request).await);
+}
+
+pub fn check_role(user: &CacheUser, min_role: Role) -> Result<(), AppError> {
+    if user.role < min_role {
+        warn!("From role condition");
+        return Err(AppError::Forbidden);
+    }
+
+    return Ok(());
+}
@@ -1,10 +1,4 @@
--- FILS SQLITE DB ATLAS MIGRATION COMMAND
--- atlas schema apply --url "sqlite://gsm.db"  --to file://src/migrations/000001_up.sql --dev-url "sqlite://atlas.db"
--- atlas schema apply --url "sqlite://gsm.db"  --to file://src/migrations/000001_down.sql --dev-url "sqlite://atlas.db"
-
--- TURSO DB ATLAS MIGRATION COMMAND
--- atlas schema apply --env turso  --to file://src/migrations/000001_up.sql --dev-url "sqlite://atlas.db"
--- atlas schema apply --env turso  --to file://src/migrations/000001_down.sql --dev-url "sqlite://atlas.db"
+-- Applied by the embedded runner in src/migrations/mod.rs (`gsm migrate up`).
 
 CREATE TABLE IF NOT EXISTS users (
     id INTEGER PRIMARY KEY,
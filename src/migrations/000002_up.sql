@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS registration_otps (
+    id INTEGER PRIMARY KEY,
+    email TEXT(255) UNIQUE NOT NULL,
+    code_hash TEXT NOT NULL,
+    password_hash TEXT NOT NULL,
+    first_name TEXT(255) NOT NULL,
+    last_name TEXT(255) NOT NULL,
+    birth_date INTEGER NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    expires_at INTEGER NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s','now'))
+);
@@ -0,0 +1,4 @@
+ALTER TABLE profiles DROP COLUMN profile_video_id;
+UPDATE profiles SET video_path = NULL;
+DROP INDEX IF EXISTS videos_profile_id_idx;
+DROP TABLE IF EXISTS videos;
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS videos (
+    id INTEGER PRIMARY KEY,
+    pid BLOB(16) UNIQUE NOT NULL CHECK(length(pid) = 16),
+    profile_id INTEGER NOT NULL,
+    storage_key TEXT(255) UNIQUE NOT NULL,
+    mime_type TEXT(64) NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    FOREIGN KEY (profile_id) REFERENCES profiles(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS videos_profile_id_idx ON videos(profile_id);
+
+-- Kept without a REFERENCES clause so the down migration can drop it
+ALTER TABLE profiles ADD COLUMN profile_video_id INTEGER;
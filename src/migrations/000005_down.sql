@@ -0,0 +1,5 @@
+DROP TRIGGER IF EXISTS likes_create_match;
+DROP INDEX IF EXISTS matches_profile_b_id_idx;
+DROP TABLE IF EXISTS matches;
+DROP INDEX IF EXISTS likes_likee_profile_id_idx;
+DROP TABLE IF EXISTS likes;
@@ -0,0 +1,46 @@
+-- One row per swipe; is_like = 0 is a pass
+CREATE TABLE IF NOT EXISTS likes (
+    id INTEGER PRIMARY KEY,
+    liker_profile_id INTEGER NOT NULL,
+    likee_profile_id INTEGER NOT NULL,
+    is_like BOOLEAN NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    UNIQUE (liker_profile_id, likee_profile_id),
+    CHECK (liker_profile_id != likee_profile_id),
+    FOREIGN KEY (liker_profile_id) REFERENCES profiles(id) ON DELETE CASCADE,
+    FOREIGN KEY (likee_profile_id) REFERENCES profiles(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS likes_likee_profile_id_idx ON likes(likee_profile_id);
+
+-- profile_a_id is always the smaller id so a pair can only match once
+CREATE TABLE IF NOT EXISTS matches (
+    id INTEGER PRIMARY KEY,
+    profile_a_id INTEGER NOT NULL,
+    profile_b_id INTEGER NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    UNIQUE (profile_a_id, profile_b_id),
+    CHECK (profile_a_id < profile_b_id),
+    FOREIGN KEY (profile_a_id) REFERENCES profiles(id) ON DELETE CASCADE,
+    FOREIGN KEY (profile_b_id) REFERENCES profiles(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS matches_profile_b_id_idx ON matches(profile_b_id);
+
+-- Runs inside the transaction of the INSERT that fired it, so a mutual like
+-- and its match are committed together. The HTTP client used for remote
+-- databases can't hold a transaction open across statements (see
+-- TransactionSource in models::util), which is why this lives in the database.
+CREATE TRIGGER IF NOT EXISTS likes_create_match AFTER INSERT ON likes
+WHEN NEW.is_like = 1 AND EXISTS (
+    SELECT 1 FROM likes
+    WHERE liker_profile_id = NEW.likee_profile_id
+    AND likee_profile_id = NEW.liker_profile_id
+    AND is_like = 1
+)
+BEGIN
+    INSERT OR IGNORE INTO matches (profile_a_id, profile_b_id) VALUES (
+        MIN(NEW.liker_profile_id, NEW.likee_profile_id),
+        MAX(NEW.liker_profile_id, NEW.likee_profile_id)
+    );
+END;
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS messages_match_id_idx;
+DROP TABLE IF EXISTS messages;
@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS messages (
+    id INTEGER PRIMARY KEY,
+    pid BLOB(16) UNIQUE NOT NULL CHECK(length(pid) = 16),
+    match_id INTEGER NOT NULL,
+    sender_profile_id INTEGER NOT NULL,
+    recipient_profile_id INTEGER NOT NULL,
+    body TEXT NOT NULL,
+    delivered_at INTEGER,
+    read_at INTEGER,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    FOREIGN KEY (match_id) REFERENCES matches(id) ON DELETE CASCADE,
+    FOREIGN KEY (sender_profile_id) REFERENCES profiles(id) ON DELETE CASCADE,
+    FOREIGN KEY (recipient_profile_id) REFERENCES profiles(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS messages_match_id_idx ON messages(match_id, id);
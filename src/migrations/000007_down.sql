@@ -0,0 +1,22 @@
+DROP TRIGGER IF EXISTS likes_create_match;
+
+CREATE TRIGGER IF NOT EXISTS likes_create_match AFTER INSERT ON likes
+WHEN NEW.is_like = 1 AND EXISTS (
+    SELECT 1 FROM likes
+    WHERE liker_profile_id = NEW.likee_profile_id
+    AND likee_profile_id = NEW.liker_profile_id
+    AND is_like = 1
+)
+BEGIN
+    INSERT OR IGNORE INTO matches (profile_a_id, profile_b_id) VALUES (
+        MIN(NEW.liker_profile_id, NEW.likee_profile_id),
+        MAX(NEW.liker_profile_id, NEW.likee_profile_id)
+    );
+END;
+
+ALTER TABLE profiles DROP COLUMN is_hidden_by_moderation;
+ALTER TABLE users DROP COLUMN suspended_at;
+DROP INDEX IF EXISTS reports_status_idx;
+DROP TABLE IF EXISTS reports;
+DROP INDEX IF EXISTS blocks_blocked_profile_id_idx;
+DROP TABLE IF EXISTS blocks;
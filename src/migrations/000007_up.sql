@@ -0,0 +1,59 @@
+CREATE TABLE IF NOT EXISTS blocks (
+    id INTEGER PRIMARY KEY,
+    blocker_profile_id INTEGER NOT NULL,
+    blocked_profile_id INTEGER NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    UNIQUE (blocker_profile_id, blocked_profile_id),
+    CHECK (blocker_profile_id != blocked_profile_id),
+    FOREIGN KEY (blocker_profile_id) REFERENCES profiles(id) ON DELETE CASCADE,
+    FOREIGN KEY (blocked_profile_id) REFERENCES profiles(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS blocks_blocked_profile_id_idx ON blocks(blocked_profile_id);
+
+CREATE TABLE IF NOT EXISTS reports (
+    id INTEGER PRIMARY KEY,
+    pid BLOB(16) UNIQUE NOT NULL CHECK(length(pid) = 16),
+    reporter_profile_id INTEGER NOT NULL,
+    reported_profile_id INTEGER NOT NULL,
+    reason TEXT NOT NULL CHECK (reason IN (
+        'spam', 'harassment', 'inappropriate_content', 'fake_profile', 'underage', 'other'
+    )),
+    details TEXT(2000),
+    status TEXT NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'in_review', 'resolved', 'dismissed')),
+    resolution_note TEXT(2000),
+    handled_by_user_id INTEGER,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    updated_at INTEGER,
+    FOREIGN KEY (reporter_profile_id) REFERENCES profiles(id) ON DELETE CASCADE,
+    FOREIGN KEY (reported_profile_id) REFERENCES profiles(id) ON DELETE CASCADE,
+    FOREIGN KEY (handled_by_user_id) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS reports_status_idx ON reports(status, id);
+
+ALTER TABLE users ADD COLUMN suspended_at INTEGER;
+
+-- Set by moderation, the owner can't make the profile visible again
+ALTER TABLE profiles ADD COLUMN is_hidden_by_moderation BOOLEAN NOT NULL DEFAULT 0;
+
+-- Blocked pairs never turn into a match
+DROP TRIGGER IF EXISTS likes_create_match;
+
+CREATE TRIGGER IF NOT EXISTS likes_create_match AFTER INSERT ON likes
+WHEN NEW.is_like = 1 AND EXISTS (
+    SELECT 1 FROM likes
+    WHERE liker_profile_id = NEW.likee_profile_id
+    AND likee_profile_id = NEW.liker_profile_id
+    AND is_like = 1
+) AND NOT EXISTS (
+    SELECT 1 FROM blocks
+    WHERE (blocker_profile_id = NEW.liker_profile_id AND blocked_profile_id = NEW.likee_profile_id)
+    OR (blocker_profile_id = NEW.likee_profile_id AND blocked_profile_id = NEW.liker_profile_id)
+)
+BEGIN
+    INSERT OR IGNORE INTO matches (profile_a_id, profile_b_id) VALUES (
+        MIN(NEW.liker_profile_id, NEW.likee_profile_id),
+        MAX(NEW.liker_profile_id, NEW.likee_profile_id)
+    );
+END;
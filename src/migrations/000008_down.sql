@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS audit_logs_target_user_id_idx;
+DROP TABLE IF EXISTS audit_logs;
+ALTER TABLE users DROP COLUMN role;
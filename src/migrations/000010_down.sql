@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS profile_revisions_profile_id_idx;
+DROP TABLE IF EXISTS profile_revisions;
@@ -0,0 +1,14 @@
+-- One row per changed field, values are stored as JSON
+CREATE TABLE IF NOT EXISTS profile_revisions (
+    id INTEGER PRIMARY KEY,
+    profile_id INTEGER NOT NULL,
+    editor_user_id INTEGER,
+    field TEXT NOT NULL,
+    old_value TEXT,
+    new_value TEXT,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    FOREIGN KEY (profile_id) REFERENCES profiles(id) ON DELETE CASCADE,
+    FOREIGN KEY (editor_user_id) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS profile_revisions_profile_id_idx ON profile_revisions(profile_id, id);
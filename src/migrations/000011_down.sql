@@ -0,0 +1 @@
+DROP TRIGGER IF EXISTS likes_update_match;
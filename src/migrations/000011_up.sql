@@ -0,0 +1,18 @@
+-- A pass can be changed into a like, which may complete a match as well
+CREATE TRIGGER IF NOT EXISTS likes_update_match AFTER UPDATE OF is_like ON likes
+WHEN NEW.is_like = 1 AND OLD.is_like = 0 AND EXISTS (
+    SELECT 1 FROM likes
+    WHERE liker_profile_id = NEW.likee_profile_id
+    AND likee_profile_id = NEW.liker_profile_id
+    AND is_like = 1
+) AND NOT EXISTS (
+    SELECT 1 FROM blocks
+    WHERE (blocker_profile_id = NEW.liker_profile_id AND blocked_profile_id = NEW.likee_profile_id)
+    OR (blocker_profile_id = NEW.likee_profile_id AND blocked_profile_id = NEW.liker_profile_id)
+)
+BEGIN
+    INSERT OR IGNORE INTO matches (profile_a_id, profile_b_id) VALUES (
+        MIN(NEW.liker_profile_id, NEW.likee_profile_id),
+        MAX(NEW.liker_profile_id, NEW.likee_profile_id)
+    );
+END;
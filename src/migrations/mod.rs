@@ -1 +1,365 @@
+use libsql::{Connection, Value as DBV};
+use tracing::{error, info};
 
+use crate::{
+    models::util::{self, begin_transaction, execute, query_get_many, TransactionSource},
+    utils::app_error::{AppError, ResultExt},
+};
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub up: &'static str,
+    pub down: &'static str,
+}
+
+// INFO: Add every new numbered migration pair here, in order!
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "users_and_profiles",
+        up: include_str!("000001_up.sql"),
+        down: include_str!("000001_down.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "registration_otps",
+        up: include_str!("000002_up.sql"),
+        down: include_str!("000002_down.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "sessions",
+        up: include_str!("000003_up.sql"),
+        down: include_str!("000003_down.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "videos",
+        up: include_str!("000004_up.sql"),
+        down: include_str!("000004_down.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "likes_and_matches",
+        up: include_str!("000005_up.sql"),
+        down: include_str!("000005_down.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "messages",
+        up: include_str!("000006_up.sql"),
+        down: include_str!("000006_down.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "blocks_and_reports",
+        up: include_str!("000007_up.sql"),
+        down: include_str!("000007_down.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "roles_and_audit_logs",
+        up: include_str!("000008_up.sql"),
+        down: include_str!("000008_down.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "password_reset_tokens",
+        up: include_str!("000009_up.sql"),
+        down: include_str!("000009_down.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "profile_revisions",
+        up: include_str!("000010_up.sql"),
+        down: include_str!("000010_down.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "likes_update_match",
+        up: include_str!("000011_up.sql"),
+        down: include_str!("000011_down.sql"),
+    },
+];
+
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: &'static str,
+    pub applied_at: Option<i64>,
+}
+
+async fn create_migrations_table(db_conn: &Connection) -> Result<(), AppError> {
+    let query_statement = "CREATE TABLE IF NOT EXISTS schema_migrations (
+        version INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        applied_at INTEGER NOT NULL DEFAULT (strftime('%s','now'))
+    )";
+
+    execute(query_statement, Vec::new(), db_conn).await?;
+
+    return Ok(());
+}
+
+/// Returns `(version, applied_at)` for every applied migration, oldest first.
+async fn get_applied_migrations(db_conn: &Connection) -> Result<Vec<(i64, i64)>, AppError> {
+    create_migrations_table(db_conn).await?;
+
+    let query_statement = "SELECT version, applied_at FROM schema_migrations ORDER BY version";
+    let mut rows = query_get_many(query_statement, Vec::new(), db_conn).await?;
+    let mut applied = Vec::new();
+
+    while let Some(row) = rows.next().internal("reading applied migrations")? {
+        applied.push((
+            util::column(&row, "version")?,
+            util::column(&row, "applied_at")?,
+        ));
+    }
+
+    return Ok(applied);
+}
+
+async fn run_script(script: &str, db_conn: &Connection) -> Result<(), AppError> {
+    // INFO: execute_batch is not implemented for remote (hrana) connections,
+    // so every statement is sent on its own.
+    for statement in split_statements(script) {
+        execute(statement.as_str(), Vec::new(), db_conn).await?;
+    }
+
+    return Ok(());
+}
+
+/// Applies every pending migration and returns the versions that were applied.
+pub async fn migrate_up(
+    db_conn: &Connection,
+    transactions: &TransactionSource,
+) -> Result<Vec<i64>, AppError> {
+    let applied = get_applied_migrations(db_conn).await?;
+    let mut newly_applied = Vec::new();
+
+    for migration in MIGRATIONS {
+        if applied
+            .iter()
+            .any(|(version, _)| *version == migration.version)
+        {
+            continue;
+        }
+
+        info!(
+            "Applying migration {:06}_{}",
+            migration.version, migration.name
+        );
+        // A migration that fails halfway leaves no trace and is retried as a whole
+        let tx = begin_transaction(transactions).await?;
+
+        run_script(migration.up, &tx).await?;
+
+        execute(
+            "INSERT INTO schema_migrations (version, name) VALUES (?, ?)",
+            vec![DBV::Integer(migration.version), DBV::from(migration.name)],
+            &tx,
+        )
+        .await?;
+
+        tx.commit().await?;
+
+        newly_applied.push(migration.version);
+    }
+
+    return Ok(newly_applied);
+}
+
+/// Reverts the last `steps` applied migrations and returns the reverted versions.
+pub async fn migrate_down(
+    db_conn: &Connection,
+    transactions: &TransactionSource,
+    steps: usize,
+) -> Result<Vec<i64>, AppError> {
+    let applied = get_applied_migrations(db_conn).await?;
+    let mut reverted = Vec::new();
+
+    for (version, _) in applied.iter().rev().take(steps) {
+        let migration = MIGRATIONS
+            .iter()
+            .find(|migration| migration.version == *version)
+            .ok_or_else(|| {
+                error!("Migration {:06} is applied but not embedded", version);
+                AppError::InternalServerError
+            })?;
+
+        info!(
+            "Reverting migration {:06}_{}",
+            migration.version, migration.name
+        );
+        let tx = begin_transaction(transactions).await?;
+
+        run_script(migration.down, &tx).await?;
+
+        execute(
+            "DELETE FROM schema_migrations WHERE version = ?",
+            vec![DBV::Integer(migration.version)],
+            &tx,
+        )
+        .await?;
+
+        tx.commit().await?;
+
+        reverted.push(migration.version);
+    }
+
+    return Ok(reverted);
+}
+
+pub async fn migrate_status(db_conn: &Connection) -> Result<Vec<MigrationStatus>, AppError> {
+    let applied = get_applied_migrations(db_conn).await?;
+
+    let status = MIGRATIONS
+        .iter()
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            name: migration.name,
+            applied_at: applied
+                .iter()
+                .find(|(version, _)| *version == migration.version)
+                .map(|(_, applied_at)| *applied_at),
+        })
+        .collect();
+
+    return Ok(status);
+}
+
+/// Splits a migration script into single statements, skipping `--` comments
+/// and keeping `CREATE TRIGGER ... BEGIN ... END;` bodies in one piece.
+fn split_statements(script: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut chars = script.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if let Some(open_quote) = quote {
+            current.push(c);
+            if c == open_quote {
+                quote = None;
+            }
+            continue;
+        }
+
+        match c {
+            '\'' | '"' => {
+                quote = Some(c);
+                current.push(c);
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                while let Some(&next) = chars.peek() {
+                    if next == '\n' {
+                        break;
+                    }
+                    chars.next();
+                }
+            }
+            ';' => {
+                let statement = current.trim().to_string();
+                let upper = statement.to_uppercase();
+                let words: Vec<&str> = upper.split_whitespace().take(3).collect();
+                let is_trigger = matches!(
+                    words.as_slice(),
+                    ["CREATE", "TRIGGER", ..] | ["CREATE", "TEMP" | "TEMPORARY", "TRIGGER"]
+                );
+                let is_open_trigger = is_trigger && !upper.ends_with("END");
+
+                if is_open_trigger {
+                    current.push(c);
+                    continue;
+                }
+
+                if !statement.is_empty() {
+                    statements.push(statement);
+                }
+                current.clear();
+            }
+            _ => current.push(c),
+        }
+    }
+
+    let statement = current.trim().to_string();
+    if !statement.is_empty() {
+        statements.push(statement);
+    }
+
+    return statements;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_statements_splits_on_semicolons() {
+        let script = "CREATE TABLE a (id INTEGER);\n\nCREATE TABLE b (id INTEGER);\n";
+
+        assert_eq!(
+            split_statements(script),
+            vec!["CREATE TABLE a (id INTEGER)", "CREATE TABLE b (id INTEGER)"]
+        );
+    }
+
+    #[test]
+    fn split_statements_keeps_quoted_semicolons() {
+        let script = "INSERT INTO a VALUES ('x;y', \"it's; fine\");\n\
+            INSERT INTO a VALUES ('it''s; quoted');";
+
+        assert_eq!(
+            split_statements(script),
+            vec![
+                "INSERT INTO a VALUES ('x;y', \"it's; fine\")",
+                "INSERT INTO a VALUES ('it''s; quoted')",
+            ]
+        );
+    }
+
+    #[test]
+    fn split_statements_skips_comments() {
+        let script = "-- Users; and their roles\n\
+            CREATE TABLE a (\n    id INTEGER -- the key; never reused\n);\n\
+            -- trailing comment;";
+
+        assert_eq!(
+            split_statements(script),
+            vec!["CREATE TABLE a (\n    id INTEGER \n)"]
+        );
+
+        // Inside a string it is not a comment
+        assert_eq!(
+            split_statements("INSERT INTO a VALUES ('--;');"),
+            vec!["INSERT INTO a VALUES ('--;')"]
+        );
+    }
+
+    #[test]
+    fn split_statements_keeps_trigger_bodies_together() {
+        let script = "CREATE TRIGGER IF NOT EXISTS t AFTER UPDATE ON a BEGIN\n\
+            UPDATE a SET n = 1;\n\
+            UPDATE a SET m = 2;\n\
+            END;\n\
+            DROP TABLE b;";
+
+        assert_eq!(
+            split_statements(script),
+            vec![
+                "CREATE TRIGGER IF NOT EXISTS t AFTER UPDATE ON a BEGIN\n\
+                UPDATE a SET n = 1;\nUPDATE a SET m = 2;\nEND",
+                "DROP TABLE b",
+            ]
+        );
+    }
+
+    #[test]
+    fn split_statements_keeps_a_last_statement_without_semicolon() {
+        assert_eq!(
+            split_statements("DROP TABLE a;\nDROP TABLE b\n"),
+            vec!["DROP TABLE a", "DROP TABLE b"]
+        );
+        assert!(split_statements("  \n-- nothing here\n;;").is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+use libsql::{Connection, Value as DBV};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    message::Message,
+    query::Select,
+    util::{self, FromRow},
+};
+use crate::utils::app_error::{AppError, ResultExt};
+
+// INFO: Read only queries backing the account export, the other profile of
+// every row is joined in by pid since internal ids never leave the server.
+// Matches and messages join both of their profiles and drop the caller's.
+
+/// A like, pass, match or block between the caller and another profile.
+#[derive(Serialize, Deserialize, Debug, FromRow)]
+pub struct ProfileRelation {
+    pub profile_pid: Option<Vec<u8>>,
+    // Only selected for likes and passes
+    #[row(default)]
+    pub is_like: Option<bool>,
+    pub created_at: Option<i64>,
+}
+
+pub struct ExportedMessage {
+    pub message: Message,
+    pub other_profile_pid: Option<Vec<u8>>,
+}
+
+async fn get_profile_relations(
+    query: Select,
+    db_conn: &Connection,
+) -> Result<Vec<ProfileRelation>, AppError> {
+    let mut rows = query.build()?.get_many(db_conn).await?;
+    let mut relations: Vec<ProfileRelation> = Vec::new();
+
+    while let Some(row) = rows.next().internal("reading profile relations")? {
+        relations.push(ProfileRelation::from_row(&row)?);
+    }
+
+    return Ok(relations);
+}
+
+/// Likes and passes given by the profile, oldest first.
+pub async fn get_swipes_by_profile_id(
+    profile_id: i64,
+    db_conn: &Connection,
+) -> Result<Vec<ProfileRelation>, AppError> {
+    let query = Select::from(
+        "SELECT profiles.pid AS profile_pid, likes.is_like, likes.created_at \
+        FROM likes JOIN profiles ON profiles.id = likes.likee_profile_id",
+    )
+    .filter("likes.liker_profile_id = ?", vec![DBV::Integer(profile_id)])
+    .order_by("likes.id ASC");
+
+    return get_profile_relations(query, db_conn).await;
+}
+
+/// Every match of the profile, including the ones hidden by a block.
+pub async fn get_matches_by_profile_id(
+    profile_id: i64,
+    db_conn: &Connection,
+) -> Result<Vec<ProfileRelation>, AppError> {
+    let query = Select::from(
+        "SELECT profiles.pid AS profile_pid, matches.created_at FROM matches \
+        JOIN profiles ON profiles.id IN (matches.profile_a_id, matches.profile_b_id)",
+    )
+    .filter(
+        "matches.profile_a_id = ? OR matches.profile_b_id = ?",
+        vec![DBV::Integer(profile_id), DBV::Integer(profile_id)],
+    )
+    .filter("profiles.id != ?", vec![DBV::Integer(profile_id)])
+    .order_by("matches.id ASC");
+
+    return get_profile_relations(query, db_conn).await;
+}
+
+/// Profiles blocked by the profile, oldest first.
+pub async fn get_blocks_by_profile_id(
+    profile_id: i64,
+    db_conn: &Connection,
+) -> Result<Vec<ProfileRelation>, AppError> {
+    let query = Select::from(
+        "SELECT profiles.pid AS profile_pid, blocks.created_at \
+        FROM blocks JOIN profiles ON profiles.id = blocks.blocked_profile_id",
+    )
+    .filter(
+        "blocks.blocker_profile_id = ?",
+        vec![DBV::Integer(profile_id)],
+    )
+    .order_by("blocks.id ASC");
+
+    return get_profile_relations(query, db_conn).await;
+}
+
+/// Messages sent or received by the profile, oldest first.
+pub async fn get_messages_by_profile_id(
+    profile_id: i64,
+    db_conn: &Connection,
+) -> Result<Vec<ExportedMessage>, AppError> {
+    let mut rows = Select::from(
+        "SELECT messages.*, profiles.pid AS other_profile_pid FROM messages \
+        JOIN profiles ON profiles.id \
+        IN (messages.sender_profile_id, messages.recipient_profile_id)",
+    )
+    .filter(
+        "messages.sender_profile_id = ? OR messages.recipient_profile_id = ?",
+        vec![DBV::Integer(profile_id), DBV::Integer(profile_id)],
+    )
+    .filter("profiles.id != ?", vec![DBV::Integer(profile_id)])
+    .order_by("messages.id ASC")
+    .build()?
+    .get_many(db_conn)
+    .await?;
+    let mut messages: Vec<ExportedMessage> = Vec::new();
+
+    while let Some(row) = rows.next().internal("reading messages")? {
+        messages.push(ExportedMessage {
+            message: Message::from_row(&row)?,
+            other_profile_pid: util::column(&row, "other_profile_pid")?,
+        });
+    }
+
+    return Ok(messages);
+}
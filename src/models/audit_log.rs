@@ -0,0 +1,116 @@
+use libsql::{Connection, Value as DBV};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    query::{Insert, Select, Table},
+    util::FromRow,
+};
+use crate::utils::app_error::{AppError, ResultExt};
+
+const AUDIT_LOGS: Table = Table {
+    name: "audit_logs",
+    columns: &[
+        "id",
+        "actor_user_id",
+        "action",
+        "target_user_id",
+        "target_profile_id",
+        "details",
+        "created_at",
+    ],
+};
+
+#[derive(Serialize, Deserialize, Debug, FromRow)]
+pub struct AuditLog {
+    pub id: Option<i64>,
+    pub actor_user_id: Option<i64>,
+    pub action: Option<String>,
+    pub target_user_id: Option<i64>,
+    pub target_profile_id: Option<i64>,
+    pub details: Option<String>,
+    pub created_at: Option<i64>,
+    // Joined from users when listing
+    pub actor_user_pid: Option<Vec<u8>>,
+    pub target_user_pid: Option<Vec<u8>>,
+}
+
+pub struct AuditLogParams {
+    // None for events raised by the server itself
+    pub actor_user_id: Option<i64>,
+    pub action: &'static str,
+    pub target_user_id: Option<i64>,
+    pub target_profile_id: Option<i64>,
+    pub details: Option<serde_json::Value>,
+}
+
+pub async fn create_audit_log(
+    db_conn: &Connection,
+    params: AuditLogParams,
+) -> Result<u64, AppError> {
+    let details = params
+        .details
+        .map(|details| DBV::from(details.to_string().as_str()))
+        .unwrap_or(DBV::Null);
+
+    return Insert::into(&AUDIT_LOGS)
+        .value(
+            "actor_user_id",
+            params.actor_user_id.map(DBV::Integer).unwrap_or(DBV::Null),
+        )
+        .value("action", DBV::from(params.action))
+        .value(
+            "target_user_id",
+            params.target_user_id.map(DBV::Integer).unwrap_or(DBV::Null),
+        )
+        .value(
+            "target_profile_id",
+            params
+                .target_profile_id
+                .map(DBV::Integer)
+                .unwrap_or(DBV::Null),
+        )
+        .value("details", details)
+        .build()?
+        .execute(db_conn)
+        .await;
+}
+
+/// Newest entries first, optionally only those about one user.
+pub async fn get_audit_logs(
+    target_user_id: Option<i64>,
+    before_id: Option<i64>,
+    limit: i64,
+    db_conn: &Connection,
+) -> Result<Vec<AuditLog>, AppError> {
+    let mut query = Select::from(
+        "SELECT audit_logs.*, \
+        actor.pid AS actor_user_pid, target.pid AS target_user_pid FROM audit_logs \
+        LEFT JOIN users AS actor ON actor.id = audit_logs.actor_user_id \
+        LEFT JOIN users AS target ON target.id = audit_logs.target_user_id",
+    )
+    .filter(
+        "audit_logs.id < ?",
+        vec![DBV::Integer(before_id.unwrap_or(i64::MAX))],
+    );
+
+    if let Some(target_user_id) = target_user_id {
+        query = query.filter(
+            "audit_logs.target_user_id = ?",
+            vec![DBV::Integer(target_user_id)],
+        );
+    }
+
+    let mut rows = query
+        .order_by("audit_logs.id DESC")
+        .limit(limit)
+        .build()?
+        .get_many(db_conn)
+        .await?;
+    let mut audit_logs: Vec<AuditLog> = Vec::new();
+
+    while let Some(row) = rows.next().internal("reading audit logs")? {
+        audit_logs.push(AuditLog::from_row(&row)?);
+    }
+
+    return Ok(audit_logs);
+}
@@ -0,0 +1,56 @@
+use libsql::{Connection, Value as DBV};
+
+use super::query::{Insert, Select, Table};
+use crate::utils::app_error::AppError;
+
+const BLOCKS: Table = Table {
+    name: "blocks",
+    columns: &[
+        "id",
+        "blocker_profile_id",
+        "blocked_profile_id",
+        "created_at",
+    ],
+};
+
+/// Blocking is idempotent, blocking twice keeps the first block.
+pub async fn create_block(
+    blocker_profile_id: i64,
+    blocked_profile_id: i64,
+    db_conn: &Connection,
+) -> Result<u64, AppError> {
+    return Insert::into(&BLOCKS)
+        .value("blocker_profile_id", DBV::Integer(blocker_profile_id))
+        .value("blocked_profile_id", DBV::Integer(blocked_profile_id))
+        .on_conflict(&["blocker_profile_id", "blocked_profile_id"])
+        .build()?
+        .execute(db_conn)
+        .await;
+}
+
+/// Whether either profile has blocked the other.
+pub async fn is_blocked(
+    profile_id: i64,
+    other_profile_id: i64,
+    db_conn: &Connection,
+) -> Result<bool, AppError> {
+    let query = Select::from("SELECT id FROM blocks")
+        .filter(
+            "(blocker_profile_id = ? AND blocked_profile_id = ?) \
+            OR (blocker_profile_id = ? AND blocked_profile_id = ?)",
+            vec![
+                DBV::Integer(profile_id),
+                DBV::Integer(other_profile_id),
+                DBV::Integer(other_profile_id),
+                DBV::Integer(profile_id),
+            ],
+        )
+        .limit(1)
+        .build()?;
+
+    match query.get_one(db_conn).await {
+        Ok(_) => return Ok(true),
+        Err(AppError::NotFound) => return Ok(false),
+        Err(err) => return Err(err),
+    }
+}
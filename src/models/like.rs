@@ -0,0 +1,60 @@
+use libsql::{Connection, Value as DBV};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    query::{Insert, Select, Table},
+    util::FromRow,
+};
+use crate::utils::app_error::AppError;
+
+const LIKES: Table = Table {
+    name: "likes",
+    columns: &[
+        "id",
+        "liker_profile_id",
+        "likee_profile_id",
+        "is_like",
+        "created_at",
+    ],
+};
+
+#[derive(Serialize, Deserialize, Debug, FromRow)]
+pub struct Like {
+    pub id: Option<i64>,
+    pub liker_profile_id: Option<i64>,
+    pub likee_profile_id: Option<i64>,
+    pub is_like: Option<bool>,
+    pub created_at: Option<i64>,
+}
+
+/// Records a like or a pass, replacing an earlier swipe on the same profile.
+///
+/// A mutual like is turned into a match within the same statement, by the
+/// `likes_create_match` trigger or by `likes_update_match` once a pass
+/// becomes a like. Taking a like back does not undo an existing match.
+pub async fn create_like(
+    liker_profile_id: i64,
+    likee_profile_id: i64,
+    is_like: bool,
+    db_conn: &Connection,
+) -> Result<Like, AppError> {
+    Insert::into(&LIKES)
+        .value("liker_profile_id", DBV::Integer(liker_profile_id))
+        .value("likee_profile_id", DBV::Integer(likee_profile_id))
+        .value("is_like", DBV::from(is_like as i32))
+        .on_conflict(&["liker_profile_id", "likee_profile_id"])
+        .update_excluded(&["is_like"])
+        .build()?
+        .execute(db_conn)
+        .await?;
+
+    let row = Select::from("SELECT * FROM likes")
+        .filter("liker_profile_id = ?", vec![DBV::Integer(liker_profile_id)])
+        .filter("likee_profile_id = ?", vec![DBV::Integer(likee_profile_id)])
+        .limit(1)
+        .build()?
+        .get_one(db_conn)
+        .await?;
+
+    return Like::from_row(&row);
+}
@@ -0,0 +1,85 @@
+use libsql::{Connection, Value as DBV};
+
+use super::{
+    profile::NOT_MODERATED,
+    query::Select,
+    util::{self, FromRow},
+};
+use crate::utils::app_error::{AppError, ResultExt};
+
+#[derive(FromRow)]
+pub struct MatchedProfile {
+    pub user_id: i64,
+    pub matched_at: Option<i64>,
+}
+
+// A block hides an existing match from both sides without deleting it
+const NOT_BLOCKED: &str = "NOT EXISTS (SELECT 1 FROM blocks \
+    WHERE (blocker_profile_id = matches.profile_a_id AND blocked_profile_id = matches.profile_b_id) \
+    OR (blocker_profile_id = matches.profile_b_id AND blocked_profile_id = matches.profile_a_id))";
+
+/// Returns `AppError::NotFound` when the two profiles haven't matched.
+pub async fn get_match_id(
+    profile_id: i64,
+    other_profile_id: i64,
+    db_conn: &Connection,
+) -> Result<i64, AppError> {
+    let row = Select::from("SELECT id FROM matches")
+        .filter(
+            "profile_a_id = ? AND profile_b_id = ?",
+            vec![
+                DBV::Integer(profile_id.min(other_profile_id)),
+                DBV::Integer(profile_id.max(other_profile_id)),
+            ],
+        )
+        .filter(NOT_BLOCKED, Vec::new())
+        .limit(1)
+        .build()?
+        .get_one(db_conn)
+        .await?;
+
+    return util::column(&row, "id");
+}
+
+pub async fn is_matched(
+    profile_id: i64,
+    other_profile_id: i64,
+    db_conn: &Connection,
+) -> Result<bool, AppError> {
+    match get_match_id(profile_id, other_profile_id, db_conn).await {
+        Ok(_) => return Ok(true),
+        Err(AppError::NotFound) => return Ok(false),
+        Err(err) => return Err(err),
+    }
+}
+
+/// Owners of the other side of every match of `profile_id`, newest match
+/// first. Matches with a moderated profile or a suspended user are left out.
+pub async fn get_matched_profiles(
+    profile_id: i64,
+    db_conn: &Connection,
+) -> Result<Vec<MatchedProfile>, AppError> {
+    // Joins the profile on the other side, a profile never matches itself
+    let mut rows = Select::from(
+        "SELECT profiles.user_id, matches.created_at AS matched_at FROM matches \
+        JOIN profiles ON profiles.id IN (matches.profile_a_id, matches.profile_b_id)",
+    )
+    .filter(
+        "matches.profile_a_id = ? OR matches.profile_b_id = ?",
+        vec![DBV::Integer(profile_id), DBV::Integer(profile_id)],
+    )
+    .filter("profiles.id != ?", vec![DBV::Integer(profile_id)])
+    .filter(NOT_BLOCKED, Vec::new())
+    .filter(NOT_MODERATED, Vec::new())
+    .order_by("matches.created_at DESC, matches.id DESC")
+    .build()?
+    .get_many(db_conn)
+    .await?;
+    let mut matched_profiles: Vec<MatchedProfile> = Vec::new();
+
+    while let Some(row) = rows.next().internal("reading matches")? {
+        matched_profiles.push(MatchedProfile::from_row(&row)?);
+    }
+
+    return Ok(matched_profiles);
+}
@@ -0,0 +1,164 @@
+use chrono::Utc;
+use libsql::{Connection, Value as DBV};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{
+    query::{Insert, Select, Table, Update},
+    util::FromRow,
+};
+use crate::utils::app_error::{AppError, ResultExt};
+
+const MESSAGES: Table = Table {
+    name: "messages",
+    columns: &[
+        "id",
+        "pid",
+        "match_id",
+        "sender_profile_id",
+        "recipient_profile_id",
+        "body",
+        "delivered_at",
+        "read_at",
+        "created_at",
+    ],
+};
+
+#[derive(Serialize, Deserialize, Debug, FromRow)]
+pub struct Message {
+    pub id: Option<i64>,
+    pub pid: Option<Vec<u8>>,
+    pub match_id: Option<i64>,
+    pub sender_profile_id: Option<i64>,
+    pub recipient_profile_id: Option<i64>,
+    pub body: Option<String>,
+    pub delivered_at: Option<i64>,
+    pub read_at: Option<i64>,
+    pub created_at: Option<i64>,
+}
+
+pub struct MessageParams {
+    pub match_id: i64,
+    pub sender_profile_id: i64,
+    pub recipient_profile_id: i64,
+    pub body: String,
+}
+
+pub async fn create_message(
+    db_conn: &Connection,
+    params: MessageParams,
+) -> Result<Message, AppError> {
+    let pid = Uuid::new_v4().as_bytes().to_vec();
+
+    let row = Insert::into(&MESSAGES)
+        .value("pid", DBV::from(pid))
+        .value("match_id", DBV::Integer(params.match_id))
+        .value("sender_profile_id", DBV::Integer(params.sender_profile_id))
+        .value(
+            "recipient_profile_id",
+            DBV::Integer(params.recipient_profile_id),
+        )
+        .value("body", DBV::from(params.body.as_str()))
+        .returning_all()
+        .build()?
+        .get_one(db_conn)
+        .await?;
+
+    return Message::from_row(&row);
+}
+
+pub async fn get_message_by_pid(pid: &Uuid, db_conn: &Connection) -> Result<Message, AppError> {
+    let row = Select::from("SELECT * FROM messages")
+        .filter("pid = ?", vec![DBV::from(pid.as_bytes().to_vec())])
+        .limit(1)
+        .build()?
+        .get_one(db_conn)
+        .await?;
+
+    return Message::from_row(&row);
+}
+
+/// Messages of a match older than `before_id`, newest first.
+pub async fn get_messages_by_match_id(
+    match_id: i64,
+    before_id: Option<i64>,
+    limit: i64,
+    db_conn: &Connection,
+) -> Result<Vec<Message>, AppError> {
+    let mut rows = Select::from("SELECT * FROM messages")
+        .filter("match_id = ?", vec![DBV::Integer(match_id)])
+        .filter("id < ?", vec![DBV::Integer(before_id.unwrap_or(i64::MAX))])
+        .order_by("id DESC")
+        .limit(limit)
+        .build()?
+        .get_many(db_conn)
+        .await?;
+    let mut messages: Vec<Message> = Vec::new();
+
+    while let Some(row) = rows.next().internal("reading messages")? {
+        messages.push(Message::from_row(&row)?);
+    }
+
+    return Ok(messages);
+}
+
+pub async fn mark_message_delivered(id: i64, db_conn: &Connection) -> Result<u64, AppError> {
+    return Update::table(&MESSAGES)
+        .set("delivered_at", DBV::Integer(Utc::now().timestamp()))
+        .filter("id = ?", vec![DBV::Integer(id)])
+        .filter("delivered_at IS NULL", Vec::new())
+        .build()?
+        .execute(db_conn)
+        .await;
+}
+
+/// Marks every message the recipient got in a match, up to `up_to_id`, as
+/// delivered.
+pub async fn mark_messages_delivered(
+    match_id: i64,
+    recipient_profile_id: i64,
+    up_to_id: i64,
+    db_conn: &Connection,
+) -> Result<u64, AppError> {
+    return Update::table(&MESSAGES)
+        .set("delivered_at", DBV::Integer(Utc::now().timestamp()))
+        .filter("match_id = ?", vec![DBV::Integer(match_id)])
+        .filter(
+            "recipient_profile_id = ?",
+            vec![DBV::Integer(recipient_profile_id)],
+        )
+        .filter("id <= ?", vec![DBV::Integer(up_to_id)])
+        .filter("delivered_at IS NULL", Vec::new())
+        .build()?
+        .execute(db_conn)
+        .await;
+}
+
+/// Marks every message the recipient got in a match, up to `up_to_id`, as
+/// read. Read implies delivered.
+pub async fn mark_messages_read(
+    match_id: i64,
+    recipient_profile_id: i64,
+    up_to_id: i64,
+    db_conn: &Connection,
+) -> Result<u64, AppError> {
+    let now = Utc::now().timestamp();
+
+    return Update::table(&MESSAGES)
+        .set("read_at", DBV::Integer(now))
+        .set_sql(
+            "delivered_at",
+            "COALESCE(delivered_at, ?)",
+            vec![DBV::Integer(now)],
+        )
+        .filter("match_id = ?", vec![DBV::Integer(match_id)])
+        .filter(
+            "recipient_profile_id = ?",
+            vec![DBV::Integer(recipient_profile_id)],
+        )
+        .filter("id <= ?", vec![DBV::Integer(up_to_id)])
+        .filter("read_at IS NULL", Vec::new())
+        .build()?
+        .execute(db_conn)
+        .await;
+}
@@ -1,3 +1,16 @@
+pub mod account;
+pub mod audit_log;
+pub mod block;
+pub mod like;
+pub mod matches;
+pub mod message;
+pub mod password_reset;
 pub mod profile;
+pub mod profile_revision;
+pub mod query;
+pub mod registration_otp;
+pub mod report;
+pub mod session;
 pub mod user;
 pub mod util;
+pub mod video;
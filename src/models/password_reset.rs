@@ -0,0 +1,84 @@
+use libsql::{Connection, Value as DBV};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    query::{Insert, Select, Table, Update, NOW},
+    util::FromRow,
+};
+use crate::utils::app_error::AppError;
+
+const PASSWORD_RESET_TOKENS: Table = Table {
+    name: "password_reset_tokens",
+    columns: &[
+        "id",
+        "user_id",
+        "token_hash",
+        "expires_at",
+        "used_at",
+        "created_at",
+    ],
+};
+
+#[derive(Serialize, Deserialize, Debug, FromRow)]
+pub struct PasswordResetToken {
+    pub id: Option<i64>,
+    pub user_id: Option<i64>,
+    pub token_hash: Option<String>,
+    pub expires_at: Option<i64>,
+    pub used_at: Option<i64>,
+    pub created_at: Option<i64>,
+}
+
+/// Issues a new reset token and voids every earlier unused one of the user,
+/// so only the most recently mailed token works.
+pub async fn create_password_reset_token(
+    user_id: i64,
+    token_hash: &str,
+    expires_at: i64,
+    db_conn: &Connection,
+) -> Result<u64, AppError> {
+    Update::table(&PASSWORD_RESET_TOKENS)
+        .set_sql("used_at", NOW, Vec::new())
+        .filter("user_id = ?", vec![DBV::Integer(user_id)])
+        .filter("used_at IS NULL", Vec::new())
+        .build()?
+        .execute(db_conn)
+        .await?;
+
+    return Insert::into(&PASSWORD_RESET_TOKENS)
+        .value("user_id", DBV::Integer(user_id))
+        .value("token_hash", DBV::from(token_hash))
+        .value("expires_at", DBV::Integer(expires_at))
+        .build()?
+        .execute(db_conn)
+        .await;
+}
+
+pub async fn get_password_reset_token_by_hash(
+    token_hash: &str,
+    db_conn: &Connection,
+) -> Result<PasswordResetToken, AppError> {
+    let row = Select::from("SELECT * FROM password_reset_tokens")
+        .filter("token_hash = ?", vec![DBV::from(token_hash)])
+        .limit(1)
+        .build()?
+        .get_one(db_conn)
+        .await?;
+
+    return PasswordResetToken::from_row(&row);
+}
+
+/// Marks the token as used. Returns false when it was already used or has
+/// expired, which also settles two concurrent resets with the same token.
+pub async fn consume_password_reset_token(id: i64, db_conn: &Connection) -> Result<bool, AppError> {
+    let rows_affected = Update::table(&PASSWORD_RESET_TOKENS)
+        .set_sql("used_at", NOW, Vec::new())
+        .filter("id = ?", vec![DBV::Integer(id)])
+        .filter("used_at IS NULL", Vec::new())
+        .filter("expires_at > strftime('%s','now')", Vec::new())
+        .build()?
+        .execute(db_conn)
+        .await?;
+
+    return Ok(rows_affected == 1);
+}
@@ -1,16 +1,42 @@
 use crate::{
-    utils::app_error::AppError,
+    utils::app_error::{AppError, ResultExt},
     views::profile::{ProfileParams, ProfileResponse},
 };
 
-use super::util::{self, i64_from_value, query_get_many, query_get_one, row_to_value_map};
+use super::{
+    query::{Insert, Select, Table, Update},
+    util::{self, FromRow},
+};
 use libsql::{Connection, Value as DBV};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use tracing::error;
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug)]
+const PROFILES: Table = Table {
+    name: "profiles",
+    columns: &[
+        "id",
+        "pid",
+        "user_id",
+        "birth_date",
+        "first_name",
+        "last_name",
+        "profile_video_id",
+        "video_path",
+        "location",
+        "is_visible",
+        "is_hidden_by_moderation",
+        "created_at",
+        "updated_at",
+    ],
+};
+
+/// Keeps out profiles hidden by moderation and profiles of suspended users,
+/// whatever their owner set `is_visible` to.
+pub const NOT_MODERATED: &str = "profiles.is_hidden_by_moderation = 0 \
+    AND profiles.user_id NOT IN (SELECT id FROM users WHERE suspended_at IS NOT NULL)";
+
+#[derive(Clone, Serialize, Deserialize, Debug, FromRow)]
 pub struct Profile {
     pub id: Option<i64>,
     pub pid: Option<Vec<u8>>,
@@ -19,36 +45,29 @@ pub struct Profile {
     pub first_name: Option<String>,
     pub last_name: Option<String>,
     pub profile_video_id: Option<i64>,
+    pub video_path: Option<String>,
     pub location: Option<String>,
     pub is_visible: Option<bool>,
+    pub is_hidden_by_moderation: Option<bool>,
     pub created_at: Option<i64>,
     pub updated_at: Option<i64>,
 }
 
-impl From<HashMap<String, libsql::Value>> for Profile {
-    fn from(value_map: HashMap<String, libsql::Value>) -> Self {
-        Self {
-            id: util::i64_from_value("id", &value_map),
-            pid: util::byte_from_value("pid", &value_map),
-            user_id: util::i64_from_value("user_id", &value_map),
-            birth_date: util::i64_from_value("birth_date", &value_map),
-            first_name: util::string_from_value("first_name", &value_map),
-            last_name: util::string_from_value("last_name", &value_map),
-            profile_video_id: util::i64_from_value("profile_video_id", &value_map),
-            location: util::string_from_value("location", &value_map),
-            is_visible: util::bool_from_value("is_visible", &value_map),
-            created_at: util::i64_from_value("created_at", &value_map),
-            updated_at: util::i64_from_value("updated_at", &value_map),
-        }
-    }
-}
-
+/// Everything a profile view needs, cached by `user_id` since that is what an
+/// authenticated request knows about its own profile.
+#[derive(Clone, Debug)]
 pub struct CacheProfile {
     pub id: i32,
+    pub pid: Uuid,
     pub user_id: i32,
     pub birth_date: i64,
     pub first_name: String,
     pub last_name: String,
+    pub location: Option<String>,
+    pub is_visible: bool,
+    pub is_hidden_by_moderation: bool,
+    pub profile_video_id: Option<i64>,
+    pub video_path: Option<String>,
 }
 
 impl CacheProfile {
@@ -57,6 +76,12 @@ impl CacheProfile {
         let user_id = db_profile.user_id.ok_or(AppError::InternalServerError)? as i32;
         let birth_date = db_profile.birth_date.ok_or(AppError::InternalServerError)?;
 
+        let pid_vec = db_profile
+            .pid
+            .as_ref()
+            .ok_or(AppError::InternalServerError)?;
+        let pid = Uuid::from_slice(pid_vec.as_slice()).internal("reading a profile pid")?;
+
         let first_name = db_profile
             .first_name
             .as_ref()
@@ -71,10 +96,16 @@ impl CacheProfile {
 
         Ok(CacheProfile {
             id,
+            pid,
             user_id,
             birth_date,
             first_name,
             last_name,
+            location: db_profile.location.to_owned(),
+            is_visible: db_profile.is_visible.unwrap_or(false),
+            is_hidden_by_moderation: db_profile.is_hidden_by_moderation.unwrap_or(false),
+            profile_video_id: db_profile.profile_video_id,
+            video_path: db_profile.video_path.to_owned(),
         })
     }
 }
@@ -86,45 +117,38 @@ pub async fn create_profile(
 ) -> Result<Profile, AppError> {
     let pid = Uuid::new_v4().as_bytes().to_vec();
 
-    let query_statement =
-        "INSERT INTO profiles (pid, user_id, birth_date, first_name, last_name) values (?, ?, ?, ?, ?) RETURNING *";
-
-    let query_args = vec![
-        DBV::from(pid),
-        DBV::from(params.user_id as i32),
-        DBV::from(params.birth_date as i32),
-        DBV::from(params.first_name.as_str()),
-        DBV::from(params.last_name.as_str()),
-    ];
-
-    let row = query_get_one(query_statement, query_args, db_conn)
-        .await
-        .map_err(|err| err)?;
-
-    let value_map = row_to_value_map(row);
-    let profile = Profile::from(value_map);
-
-    Ok(profile)
+    let row = Insert::into(&PROFILES)
+        .value("pid", DBV::from(pid))
+        .value("user_id", DBV::Integer(params.user_id))
+        .value("birth_date", DBV::Integer(params.birth_date))
+        .value("first_name", DBV::from(params.first_name.as_str()))
+        .value("last_name", DBV::from(params.last_name.as_str()))
+        .value("location", DBV::from(params.location.as_str()))
+        .value("is_visible", DBV::from(params.is_visible as i32))
+        .returning_all()
+        .build()?
+        .get_one(db_conn)
+        .await?;
+
+    Profile::from_row(&row)
 }
 
 pub async fn get_profile_id_by_user_id(
     user_id: i64,
     db_conn: &Connection,
 ) -> Result<i64, AppError> {
-    let query_statement = "SELECT id FROM profiles WHERE user_id = ? LIMIT 1";
-    let query_args = vec![DBV::from(user_id as i32)];
-
-    let row = query_get_one(query_statement, query_args, db_conn).await?;
-
-    let value_map = row_to_value_map(row);
-
-    let id = i64_from_value("id", &value_map).ok_or(AppError::InternalServerError)?;
-
-    return Ok(id);
+    let row = Select::from("SELECT id FROM profiles")
+        .filter("user_id = ?", vec![DBV::Integer(user_id)])
+        .limit(1)
+        .build()?
+        .get_one(db_conn)
+        .await?;
+
+    return util::column(&row, "id");
 }
 
 pub async fn get_profile_by_pid_string(
-    pid: &String,
+    pid: &str,
     db_conn: &Connection,
 ) -> Result<Profile, AppError> {
     // TODO: Abstract this into a function!
@@ -136,103 +160,261 @@ pub async fn get_profile_by_pid_string(
         .as_bytes()
         .to_vec();
 
-    let query_statement = "SELECT * FROM profiles WHERE pid = ? LIMIT 1";
-    let query_args = vec![DBV::from(pid)];
-
-    return get_profile(query_statement, query_args, db_conn).await;
+    return get_profile("pid = ?", DBV::from(pid), db_conn).await;
 }
 
 pub async fn get_profile_by_pid_vec(
     pid: Vec<u8>,
     db_conn: &Connection,
 ) -> Result<Profile, AppError> {
-    let query_statement = "SELECT * FROM profiles WHERE pid = ? LIMIT 1";
-    let query_args = vec![DBV::from(pid)];
+    return get_profile("pid = ?", DBV::from(pid), db_conn).await;
+}
 
-    return get_profile(query_statement, query_args, db_conn).await;
+/// Columns of a partial profile update, `None` leaves a column unchanged.
+#[derive(Clone, Debug, Default)]
+pub struct ProfileUpdate {
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub birth_date: Option<i64>,
+    // Some(None) clears the location
+    pub location: Option<Option<String>>,
+    pub is_visible: Option<bool>,
+    pub is_hidden_by_moderation: Option<bool>,
+    // `video_path` is duplicated on the profile row so profile reads don't
+    // need to join videos, both are set together
+    pub profile_video_id: Option<Option<i64>>,
+    pub video_path: Option<Option<String>>,
 }
 
-pub async fn get_profile_by_user_id(
+impl ProfileUpdate {
+    pub fn is_empty(&self) -> bool {
+        return self.first_name.is_none()
+            && self.last_name.is_none()
+            && self.birth_date.is_none()
+            && self.location.is_none()
+            && self.is_visible.is_none()
+            && self.is_hidden_by_moderation.is_none()
+            && self.profile_video_id.is_none()
+            && self.video_path.is_none();
+    }
+}
+
+/// Applies `update` to the profile of `user_id` and returns the updated row.
+pub async fn update_profile(
     user_id: i64,
+    update: &ProfileUpdate,
     db_conn: &Connection,
 ) -> Result<Profile, AppError> {
-    let query_statement = "SELECT * FROM profiles WHERE user_id = ? LIMIT 1";
-    let query_args = vec![DBV::from(user_id as i32)];
+    let mut query = Update::table(&PROFILES);
+
+    if let Some(first_name) = &update.first_name {
+        query = query.set("first_name", DBV::from(first_name.as_str()));
+    }
+
+    if let Some(last_name) = &update.last_name {
+        query = query.set("last_name", DBV::from(last_name.as_str()));
+    }
+
+    if let Some(birth_date) = update.birth_date {
+        query = query.set("birth_date", DBV::Integer(birth_date));
+    }
+
+    if let Some(location) = &update.location {
+        let location = location.as_deref().map(DBV::from).unwrap_or(DBV::Null);
 
-    return get_profile(query_statement, query_args, db_conn).await;
+        query = query.set("location", location);
+    }
+
+    if let Some(is_visible) = update.is_visible {
+        query = query.set("is_visible", DBV::from(is_visible as i32));
+    }
+
+    if let Some(is_hidden_by_moderation) = update.is_hidden_by_moderation {
+        query = query.set(
+            "is_hidden_by_moderation",
+            DBV::from(is_hidden_by_moderation as i32),
+        );
+    }
+
+    if let Some(profile_video_id) = update.profile_video_id {
+        let profile_video_id = profile_video_id.map(DBV::Integer).unwrap_or(DBV::Null);
+
+        query = query.set("profile_video_id", profile_video_id);
+    }
+
+    if let Some(video_path) = &update.video_path {
+        let video_path = video_path.as_deref().map(DBV::from).unwrap_or(DBV::Null);
+
+        query = query.set("video_path", video_path);
+    }
+
+    let row = query
+        .filter("user_id = ?", vec![DBV::Integer(user_id)])
+        .returning_all()
+        .build()?
+        .get_one(db_conn)
+        .await?;
+
+    return Profile::from_row(&row);
 }
 
-async fn get_profile(
-    query_statement: &str,
-    query_args: Vec<DBV>,
+pub async fn get_profile_by_user_id(
+    user_id: i64,
     db_conn: &Connection,
 ) -> Result<Profile, AppError> {
-    let row = query_get_one(query_statement, query_args, db_conn).await?;
+    return get_profile("user_id = ?", DBV::Integer(user_id), db_conn).await;
+}
 
-    let value_map = row_to_value_map(row);
-    let profile = Profile::from(value_map);
+async fn get_profile(
+    condition: &'static str,
+    arg: DBV,
+    db_conn: &Connection,
+) -> Result<Profile, AppError> {
+    let row = Select::from("SELECT * FROM profiles")
+        .filter(condition, vec![arg])
+        .limit(1)
+        .build()?
+        .get_one(db_conn)
+        .await?;
+
+    return Profile::from_row(&row);
+}
 
-    return Ok(profile);
+pub struct DiscoverFilter {
+    pub location: String,
+    pub exclude_profile_id: i64,
+    pub min_birth_date: Option<i64>,
+    pub max_birth_date: Option<i64>,
+    pub after_id: i64,
+    pub limit: i64,
 }
 
-pub async fn get_profiles_by_location(
-    my_location: String,
-    exclude_profile_id: i64,
+/// Profiles of `user_ids` in no particular order, ids without a profile are
+/// skipped.
+pub async fn get_profiles_by_user_ids(
+    user_ids: &[i64],
     db_conn: &Connection,
-) -> Result<Vec<ProfileResponse>, AppError> {
-    // TODO: Attempt to get profiles from cache before querying the database!
-    let query_statement = "SELECT * FROM profiles WHERE location = ? LIMIT 100";
-    let query_args = vec![DBV::from(my_location.as_str())];
+) -> Result<Vec<Profile>, AppError> {
+    if user_ids.is_empty() {
+        return Ok(Vec::new());
+    }
 
-    let rows = query_get_many(query_statement, query_args, &db_conn).await?;
+    let user_ids = user_ids
+        .iter()
+        .map(|user_id| DBV::Integer(*user_id))
+        .collect();
 
-    return Ok(get_profiles_as_view(rows, exclude_profile_id));
-}
+    let mut rows = Select::from("SELECT * FROM profiles")
+        .filter_in("user_id", user_ids)
+        .build()?
+        .get_many(db_conn)
+        .await?;
+    let mut profiles: Vec<Profile> = Vec::new();
 
-pub fn get_profiles_as_view(
-    mut rows: libsql::Rows,
-    exclude_profile_id: i64,
-) -> Vec<ProfileResponse> {
-    let mut profiles: Vec<ProfileResponse> = Vec::new();
+    while let Some(row) = rows.next().internal("reading profiles")? {
+        profiles.push(Profile::from_row(&row)?);
+    }
 
-    loop {
-        let next_row = rows.next();
+    return Ok(profiles);
+}
 
-        if next_row.is_err() {
-            break;
-        }
+/// Whether the profile can be discovered and swiped on: it is visible, not
+/// hidden by moderation and its owner isn't suspended.
+pub async fn is_profile_discoverable(
+    profile_id: i64,
+    db_conn: &Connection,
+) -> Result<bool, AppError> {
+    let query = Select::from("SELECT id FROM profiles")
+        .filter("id = ?", vec![DBV::Integer(profile_id)])
+        .filter("is_visible = 1", Vec::new())
+        .filter(NOT_MODERATED, Vec::new())
+        .limit(1)
+        .build()?;
+
+    match query.get_one(db_conn).await {
+        Ok(_) => return Ok(true),
+        Err(AppError::NotFound) => return Ok(false),
+        Err(err) => return Err(err),
+    }
+}
 
-        // INFO: Can safely unwrap here because Ok() is guaranteed above!
-        let row = if let Some(row) = next_row.unwrap() {
-            row
-        } else {
-            break;
-        };
+/// Owners of the visible, unmoderated profiles in a location that the
+/// excluded profile hasn't swiped on or blocked (either way), ordered by profile id so
+/// `after_id` works as a cursor. Only the filtering runs here, the profiles
+/// themselves are read through the profile cache.
+pub async fn get_user_ids_by_location(
+    filter: &DiscoverFilter,
+    db_conn: &Connection,
+) -> Result<Vec<i64>, AppError> {
+    let exclude_profile_id = DBV::Integer(filter.exclude_profile_id);
+
+    let mut query = Select::from("SELECT user_id FROM profiles")
+        .filter("location = ?", vec![DBV::from(filter.location.as_str())])
+        .filter("is_visible = 1", Vec::new())
+        .filter(NOT_MODERATED, Vec::new())
+        .filter("id > ?", vec![DBV::Integer(filter.after_id)])
+        .filter("id != ?", vec![exclude_profile_id.clone()])
+        .filter(
+            "id NOT IN (SELECT likee_profile_id FROM likes WHERE liker_profile_id = ?)",
+            vec![exclude_profile_id.clone()],
+        )
+        .filter(
+            "id NOT IN (SELECT blocked_profile_id FROM blocks WHERE blocker_profile_id = ?)",
+            vec![exclude_profile_id.clone()],
+        )
+        .filter(
+            "id NOT IN (SELECT blocker_profile_id FROM blocks WHERE blocked_profile_id = ?)",
+            vec![exclude_profile_id],
+        );
+
+    if let Some(min_birth_date) = filter.min_birth_date {
+        query = query.filter("birth_date >= ?", vec![DBV::Integer(min_birth_date)]);
+    }
 
-        let value_map = row_to_value_map(row);
-        let profile = Profile::from(value_map);
+    if let Some(max_birth_date) = filter.max_birth_date {
+        query = query.filter("birth_date <= ?", vec![DBV::Integer(max_birth_date)]);
+    }
 
-        // INFO: can safely unwrap because id can't be none if profiles are from db!
-        if exclude_profile_id == profile.id.unwrap_or(0) {
-            continue;
-        }
+    let mut rows = query
+        .order_by("id ASC")
+        .limit(filter.limit)
+        .build()?
+        .get_many(db_conn)
+        .await?;
+    let mut user_ids: Vec<i64> = Vec::new();
 
-        let pid: Option<Uuid> = match profile.pid {
-            Some(value) => Uuid::from_slice(value.as_slice()).ok(),
-            _ => None,
-        };
+    while let Some(row) = rows.next().internal("reading profiles")? {
+        user_ids.push(util::column(&row, "user_id")?);
+    }
 
-        let profile_response = ProfileResponse {
-            pid,
-            first_name: profile.first_name,
-            last_name: profile.last_name,
-            location: profile.location,
-            birth_date: profile.birth_date,
-            is_visible: profile.is_visible,
-        };
+    return Ok(user_ids);
+}
 
-        profiles.push(profile_response);
-    }
+pub fn get_profile_as_view(profile: Profile) -> ProfileResponse {
+    let pid: Option<Uuid> = match profile.pid {
+        Some(value) => Uuid::from_slice(value.as_slice()).ok(),
+        _ => None,
+    };
+
+    return ProfileResponse {
+        pid,
+        first_name: profile.first_name,
+        last_name: profile.last_name,
+        location: profile.location,
+        birth_date: profile.birth_date,
+        is_visible: profile.is_visible,
+        video_path: profile.video_path,
+    };
+}
 
-    return profiles;
+pub fn get_cache_profile_as_view(profile: CacheProfile) -> ProfileResponse {
+    return ProfileResponse {
+        pid: Some(profile.pid),
+        first_name: Some(profile.first_name),
+        last_name: Some(profile.last_name),
+        location: profile.location,
+        birth_date: Some(profile.birth_date),
+        is_visible: Some(profile.is_visible),
+        video_path: profile.video_path,
+    };
 }
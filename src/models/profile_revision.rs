@@ -0,0 +1,192 @@
+use libsql::{Connection, Value as DBV};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{
+    profile::{get_profile_by_user_id, update_profile, Profile, ProfileUpdate},
+    query::{Insert, Select, Table},
+    util::FromRow,
+};
+use crate::utils::app_error::{AppError, ResultExt};
+
+const PROFILE_REVISIONS: Table = Table {
+    name: "profile_revisions",
+    columns: &[
+        "id",
+        "profile_id",
+        "editor_user_id",
+        "field",
+        "old_value",
+        "new_value",
+        "created_at",
+    ],
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, FromRow)]
+pub struct ProfileRevision {
+    pub id: Option<i64>,
+    pub profile_id: Option<i64>,
+    pub editor_user_id: Option<i64>,
+    pub field: Option<String>,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub created_at: Option<i64>,
+    // Joined from users when listing
+    pub editor_user_pid: Option<Vec<u8>>,
+}
+
+/// A field whose value differs between two versions of a profile.
+#[derive(Debug)]
+pub struct ProfileChange {
+    pub field: &'static str,
+    pub old_value: serde_json::Value,
+    pub new_value: serde_json::Value,
+}
+
+/// Fields an edit can change that differ between `before` and `after`.
+pub fn get_profile_changes(before: &Profile, after: &Profile) -> Vec<ProfileChange> {
+    let mut changes: Vec<ProfileChange> = Vec::new();
+
+    let mut compare = |field: &'static str, old_value: serde_json::Value, new_value| {
+        if old_value != new_value {
+            changes.push(ProfileChange {
+                field,
+                old_value,
+                new_value,
+            });
+        }
+    };
+
+    compare(
+        "first_name",
+        json!(before.first_name),
+        json!(after.first_name),
+    );
+    compare("last_name", json!(before.last_name), json!(after.last_name));
+    compare(
+        "birth_date",
+        json!(before.birth_date),
+        json!(after.birth_date),
+    );
+    compare("location", json!(before.location), json!(after.location));
+    compare(
+        "is_visible",
+        json!(before.is_visible),
+        json!(after.is_visible),
+    );
+    compare(
+        "is_hidden_by_moderation",
+        json!(before.is_hidden_by_moderation),
+        json!(after.is_hidden_by_moderation),
+    );
+    compare(
+        "video_path",
+        json!(before.video_path),
+        json!(after.video_path),
+    );
+
+    return changes;
+}
+
+fn json_to_db_value(value: &serde_json::Value) -> DBV {
+    if value.is_null() {
+        return DBV::Null;
+    }
+
+    return DBV::from(value.to_string().as_str());
+}
+
+/// Records `changes` to the profile as made by `editor_user_id`.
+pub async fn create_profile_revisions(
+    profile_id: i64,
+    editor_user_id: i64,
+    changes: &[ProfileChange],
+    db_conn: &Connection,
+) -> Result<(), AppError> {
+    for change in changes.iter() {
+        Insert::into(&PROFILE_REVISIONS)
+            .value("profile_id", DBV::Integer(profile_id))
+            .value("editor_user_id", DBV::Integer(editor_user_id))
+            .value("field", DBV::from(change.field))
+            .value("old_value", json_to_db_value(&change.old_value))
+            .value("new_value", json_to_db_value(&change.new_value))
+            .build()?
+            .execute(db_conn)
+            .await?;
+    }
+
+    return Ok(());
+}
+
+/// Applies `update` to the profile of `user_id` and records every field it
+/// changed as made by `editor_user_id`. An empty update writes nothing and
+/// returns the profile as it is. Run it in a transaction.
+pub async fn update_profile_with_revisions(
+    user_id: i64,
+    update: &ProfileUpdate,
+    editor_user_id: i64,
+    db_conn: &Connection,
+) -> Result<Profile, AppError> {
+    let before = get_profile_by_user_id(user_id, db_conn).await?;
+
+    if update.is_empty() {
+        return Ok(before);
+    }
+    let after = update_profile(user_id, update, db_conn).await?;
+    let profile_id = after.id.ok_or(AppError::InternalServerError)?;
+
+    let changes = get_profile_changes(&before, &after);
+    create_profile_revisions(profile_id, editor_user_id, &changes, db_conn).await?;
+
+    return Ok(after);
+}
+
+/// Hides the profile of `user_id` and stops its owner from making it visible
+/// again. Run it in a transaction.
+pub async fn hide_profile_by_moderation(
+    user_id: i64,
+    editor_user_id: i64,
+    db_conn: &Connection,
+) -> Result<Profile, AppError> {
+    let update = ProfileUpdate {
+        is_visible: Some(false),
+        is_hidden_by_moderation: Some(true),
+        ..Default::default()
+    };
+
+    return update_profile_with_revisions(user_id, &update, editor_user_id, db_conn).await;
+}
+
+/// Newest revisions of the profile first.
+pub async fn get_profile_revisions(
+    profile_id: i64,
+    before_id: Option<i64>,
+    limit: i64,
+    db_conn: &Connection,
+) -> Result<Vec<ProfileRevision>, AppError> {
+    let mut rows = Select::from(
+        "SELECT profile_revisions.*, editor.pid AS editor_user_pid FROM profile_revisions \
+        LEFT JOIN users AS editor ON editor.id = profile_revisions.editor_user_id",
+    )
+    .filter(
+        "profile_revisions.profile_id = ?",
+        vec![DBV::Integer(profile_id)],
+    )
+    .filter(
+        "profile_revisions.id < ?",
+        vec![DBV::Integer(before_id.unwrap_or(i64::MAX))],
+    )
+    .order_by("profile_revisions.id DESC")
+    .limit(limit)
+    .build()?
+    .get_many(db_conn)
+    .await?;
+
+    let mut revisions: Vec<ProfileRevision> = Vec::new();
+
+    while let Some(row) = rows.next().internal("reading profile revisions")? {
+        revisions.push(ProfileRevision::from_row(&row)?);
+    }
+
+    return Ok(revisions);
+}
@@ -0,0 +1,702 @@
+use libsql::{Connection, Value as DBV};
+
+use super::util;
+use crate::utils::app_error::AppError;
+
+// INFO: Every statement of the models is composed here. Column names are
+// checked against the table they belong to, values always go through
+// placeholders and SQL fragments are only taken as `&'static str`, so nothing
+// a request sends can become part of a statement.
+
+/// The current unix time in SQL, for timestamp columns.
+pub const NOW: &str = "strftime('%s','now')";
+
+/// A table and the columns statements built for it may name.
+pub struct Table {
+    pub name: &'static str,
+    pub columns: &'static [&'static str],
+}
+
+impl Table {
+    fn check_column(&self, column: &str) -> Result<(), String> {
+        if self.columns.contains(&column) {
+            return Ok(());
+        }
+
+        return Err(format!("`{}` is not a column of {}", column, self.name));
+    }
+}
+
+/// A built statement with its arguments, in placeholder order.
+pub struct Query {
+    pub statement: String,
+    pub args: Vec<DBV>,
+}
+
+impl Query {
+    pub async fn execute(self, db_conn: &Connection) -> Result<u64, AppError> {
+        return util::execute(self.statement.as_str(), self.args, db_conn).await;
+    }
+
+    pub async fn get_one(self, db_conn: &Connection) -> Result<libsql::Row, AppError> {
+        return util::query_get_one(self.statement.as_str(), self.args, db_conn).await;
+    }
+
+    pub async fn get_many(self, db_conn: &Connection) -> Result<libsql::Rows, AppError> {
+        return util::query_get_many(self.statement.as_str(), self.args, db_conn).await;
+    }
+}
+
+fn check_placeholders(sql: &str, args: &[DBV]) -> Result<(), String> {
+    let placeholders = sql.matches('?').count();
+
+    if placeholders == args.len() {
+        return Ok(());
+    }
+
+    return Err(format!(
+        "`{}` has {} placeholder(s) but {} argument(s)",
+        sql,
+        placeholders,
+        args.len()
+    ));
+}
+
+fn build_error(statement: &str, table: &Table, reason: String) -> AppError {
+    return AppError::internal(
+        format!("building {} statement for {}", statement, table.name),
+        reason,
+    );
+}
+
+// `column = sql` pairs of a SET clause or the columns and values of an INSERT
+#[derive(Default)]
+struct Assignments {
+    columns: Vec<&'static str>,
+    sql: Vec<String>,
+    args: Vec<DBV>,
+}
+
+impl Assignments {
+    fn push(
+        &mut self,
+        table: &Table,
+        column: &'static str,
+        sql: String,
+        args: Vec<DBV>,
+    ) -> Result<(), String> {
+        table.check_column(column)?;
+        check_placeholders(sql.as_str(), &args)?;
+
+        if self.columns.contains(&column) {
+            return Err(format!("`{}` is assigned twice", column));
+        }
+
+        self.columns.push(column);
+        self.sql.push(sql);
+        self.args.extend(args);
+
+        return Ok(());
+    }
+
+    fn set_clause(&self) -> String {
+        return self
+            .columns
+            .iter()
+            .zip(self.sql.iter())
+            .map(|(column, sql)| format!("{} = {}", column, sql))
+            .collect::<Vec<String>>()
+            .join(", ");
+    }
+}
+
+#[derive(Default)]
+struct Conditions {
+    sql: Vec<String>,
+    args: Vec<DBV>,
+}
+
+impl Conditions {
+    fn push(&mut self, condition: &'static str, args: Vec<DBV>) -> Result<(), String> {
+        check_placeholders(condition, &args)?;
+
+        self.sql.push(condition.to_string());
+        self.args.extend(args);
+
+        return Ok(());
+    }
+
+    fn push_in(&mut self, column: &'static str, values: Vec<DBV>) {
+        // `IN ()` is a syntax error in most databases, an empty list matches nothing
+        if values.is_empty() {
+            self.sql.push("0".to_string());
+            return;
+        }
+
+        let placeholders = vec!["?"; values.len()].join(", ");
+
+        self.sql.push(format!("{} IN ({})", column, placeholders));
+        self.args.extend(values);
+    }
+
+    fn where_clause(&self) -> String {
+        if self.sql.is_empty() {
+            return String::new();
+        }
+
+        let conditions: Vec<String> = self
+            .sql
+            .iter()
+            .map(|condition| format!("({})", condition))
+            .collect();
+
+        return format!(" WHERE {}", conditions.join(" AND "));
+    }
+}
+
+fn returning_clause(
+    table: &Table,
+    returning: &Option<Vec<&'static str>>,
+) -> Result<String, String> {
+    let Some(columns) = returning else {
+        return Ok(String::new());
+    };
+
+    if columns.is_empty() {
+        return Ok(" RETURNING *".to_string());
+    }
+
+    for column in columns.iter() {
+        table.check_column(column)?;
+    }
+
+    return Ok(format!(" RETURNING {}", columns.join(", ")));
+}
+
+/// An `INSERT`, optionally turned into an upsert with `on_conflict`.
+///
+/// Mistakes like an unknown column are collected while chaining and reported
+/// by `build`, as they are bugs of the calling model function.
+pub struct Insert {
+    table: &'static Table,
+    values: Assignments,
+    conflict_target: Option<&'static [&'static str]>,
+    conflict_updates: Assignments,
+    returning: Option<Vec<&'static str>>,
+    error: Option<String>,
+}
+
+impl Insert {
+    pub fn into(table: &'static Table) -> Self {
+        return Self {
+            table,
+            values: Assignments::default(),
+            conflict_target: None,
+            conflict_updates: Assignments::default(),
+            returning: None,
+            error: None,
+        };
+    }
+
+    pub fn value(self, column: &'static str, value: DBV) -> Self {
+        return self.value_sql(column, "?", vec![value]);
+    }
+
+    /// Inserts the result of `sql` into `column`, like `NOW`.
+    pub fn value_sql(mut self, column: &'static str, sql: &'static str, args: Vec<DBV>) -> Self {
+        if let Err(err) = self.values.push(self.table, column, sql.to_string(), args) {
+            self.error.get_or_insert(err);
+        }
+
+        return self;
+    }
+
+    /// Handles a row that already exists with the same `target` columns. It
+    /// is left alone unless `update_excluded` or `update_sql` follow.
+    pub fn on_conflict(mut self, target: &'static [&'static str]) -> Self {
+        for column in target.iter() {
+            if let Err(err) = self.table.check_column(column) {
+                self.error.get_or_insert(err);
+            }
+        }
+
+        self.conflict_target = Some(target);
+
+        return self;
+    }
+
+    /// On conflict, overwrites `columns` of the existing row with the values
+    /// that were about to be inserted.
+    pub fn update_excluded(mut self, columns: &[&'static str]) -> Self {
+        for column in columns.iter() {
+            let sql = format!("excluded.{}", column);
+
+            if let Err(err) = self
+                .conflict_updates
+                .push(self.table, column, sql, Vec::new())
+            {
+                self.error.get_or_insert(err);
+            }
+        }
+
+        return self;
+    }
+
+    /// On conflict, sets `column` of the existing row to `sql`.
+    pub fn update_sql(mut self, column: &'static str, sql: &'static str) -> Self {
+        if let Err(err) =
+            self.conflict_updates
+                .push(self.table, column, sql.to_string(), Vec::new())
+        {
+            self.error.get_or_insert(err);
+        }
+
+        return self;
+    }
+
+    pub fn returning(mut self, columns: &[&'static str]) -> Self {
+        self.returning = Some(columns.to_vec());
+        return self;
+    }
+
+    pub fn returning_all(mut self) -> Self {
+        self.returning = Some(Vec::new());
+        return self;
+    }
+
+    pub fn build(self) -> Result<Query, AppError> {
+        let table = self.table;
+        let fail = |reason: String| build_error("an INSERT", table, reason);
+
+        if let Some(err) = self.error {
+            return Err(fail(err));
+        }
+
+        if self.values.columns.is_empty() {
+            return Err(fail("no values to insert".to_string()));
+        }
+
+        if self.conflict_target.is_none() && !self.conflict_updates.columns.is_empty() {
+            return Err(fail(
+                "conflict updates without a conflict target".to_string(),
+            ));
+        }
+
+        let mut statement = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            table.name,
+            self.values.columns.join(", "),
+            self.values.sql.join(", ")
+        );
+
+        if let Some(target) = self.conflict_target {
+            statement.push_str(&format!(" ON CONFLICT ({})", target.join(", ")));
+
+            if self.conflict_updates.columns.is_empty() {
+                statement.push_str(" DO NOTHING");
+            } else {
+                statement.push_str(&format!(
+                    " DO UPDATE SET {}",
+                    self.conflict_updates.set_clause()
+                ));
+            }
+        }
+
+        statement.push_str(&returning_clause(table, &self.returning).map_err(fail)?);
+
+        return Ok(Query {
+            statement,
+            args: self.values.args,
+        });
+    }
+}
+
+/// An `UPDATE` of the columns that were `set`, in the order they were set.
+/// Building one without a column or without a condition fails, so a partial
+/// update with nothing in it can't turn into a statement touching every row.
+///
+/// Tables with an `updated_at` column get it set to `NOW`, unless it was set
+/// explicitly. This is done here rather than by a trigger so `RETURNING` sees
+/// the new value.
+pub struct Update {
+    table: &'static Table,
+    assignments: Assignments,
+    conditions: Conditions,
+    returning: Option<Vec<&'static str>>,
+    error: Option<String>,
+}
+
+impl Update {
+    pub fn table(table: &'static Table) -> Self {
+        return Self {
+            table,
+            assignments: Assignments::default(),
+            conditions: Conditions::default(),
+            returning: None,
+            error: None,
+        };
+    }
+
+    pub fn set(self, column: &'static str, value: DBV) -> Self {
+        return self.set_sql(column, "?", vec![value]);
+    }
+
+    /// Sets `column` to `sql`, like `NOW` or `COALESCE(?, ip)`.
+    pub fn set_sql(mut self, column: &'static str, sql: &'static str, args: Vec<DBV>) -> Self {
+        if let Err(err) = self
+            .assignments
+            .push(self.table, column, sql.to_string(), args)
+        {
+            self.error.get_or_insert(err);
+        }
+
+        return self;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.assignments.columns.is_empty();
+    }
+
+    /// Adds a condition, every condition has to hold for a row to be updated.
+    pub fn filter(mut self, condition: &'static str, args: Vec<DBV>) -> Self {
+        if let Err(err) = self.conditions.push(condition, args) {
+            self.error.get_or_insert(err);
+        }
+
+        return self;
+    }
+
+    pub fn returning(mut self, columns: &[&'static str]) -> Self {
+        self.returning = Some(columns.to_vec());
+        return self;
+    }
+
+    pub fn returning_all(mut self) -> Self {
+        self.returning = Some(Vec::new());
+        return self;
+    }
+
+    pub fn build(mut self) -> Result<Query, AppError> {
+        let table = self.table;
+        let fail = |reason: String| build_error("an UPDATE", table, reason);
+
+        if let Some(err) = self.error {
+            return Err(fail(err));
+        }
+
+        if self.assignments.columns.is_empty() {
+            return Err(fail("no columns to update".to_string()));
+        }
+
+        if table.columns.contains(&"updated_at")
+            && !self.assignments.columns.contains(&"updated_at")
+        {
+            self.assignments
+                .push(table, "updated_at", NOW.to_string(), Vec::new())
+                .map_err(fail)?;
+        }
+
+        if self.conditions.sql.is_empty() {
+            return Err(fail("no condition, every row would be updated".to_string()));
+        }
+
+        let statement = format!(
+            "UPDATE {} SET {}{}{}",
+            table.name,
+            self.assignments.set_clause(),
+            self.conditions.where_clause(),
+            returning_clause(table, &self.returning).map_err(fail)?
+        );
+
+        let mut args = self.assignments.args;
+        args.extend(self.conditions.args);
+
+        return Ok(Query { statement, args });
+    }
+}
+
+/// A `DELETE` of the rows matching every condition. Like `Update` it fails
+/// to build without a condition.
+pub struct Delete {
+    table: &'static Table,
+    conditions: Conditions,
+    error: Option<String>,
+}
+
+impl Delete {
+    pub fn from(table: &'static Table) -> Self {
+        return Self {
+            table,
+            conditions: Conditions::default(),
+            error: None,
+        };
+    }
+
+    /// Adds a condition, every condition has to hold for a row to be deleted.
+    pub fn filter(mut self, condition: &'static str, args: Vec<DBV>) -> Self {
+        if let Err(err) = self.conditions.push(condition, args) {
+            self.error.get_or_insert(err);
+        }
+
+        return self;
+    }
+
+    pub fn build(self) -> Result<Query, AppError> {
+        let table = self.table;
+        let fail = |reason: String| build_error("a DELETE", table, reason);
+
+        if let Some(err) = self.error {
+            return Err(fail(err));
+        }
+
+        if self.conditions.sql.is_empty() {
+            return Err(fail("no condition, every row would be deleted".to_string()));
+        }
+
+        return Ok(Query {
+            statement: format!(
+                "DELETE FROM {}{}",
+                table.name,
+                self.conditions.where_clause()
+            ),
+            args: self.conditions.args,
+        });
+    }
+}
+
+/// A `SELECT` where `from` is everything up to the `WHERE` clause, joins
+/// included. Conditions and paging are added by the caller.
+pub struct Select {
+    from: &'static str,
+    conditions: Conditions,
+    order_by: Option<&'static str>,
+    limit: Option<i64>,
+    error: Option<String>,
+}
+
+impl Select {
+    pub fn from(from: &'static str) -> Self {
+        return Self {
+            from,
+            conditions: Conditions::default(),
+            order_by: None,
+            limit: None,
+            error: None,
+        };
+    }
+
+    /// Adds a condition, every condition has to hold for a row to be selected.
+    pub fn filter(mut self, condition: &'static str, args: Vec<DBV>) -> Self {
+        if let Err(err) = self.conditions.push(condition, args) {
+            self.error.get_or_insert(err);
+        }
+
+        return self;
+    }
+
+    /// Adds a condition that `column` is one of `values`, with a placeholder
+    /// per value.
+    pub fn filter_in(mut self, column: &'static str, values: Vec<DBV>) -> Self {
+        self.conditions.push_in(column, values);
+        return self;
+    }
+
+    pub fn order_by(mut self, order_by: &'static str) -> Self {
+        self.order_by = Some(order_by);
+        return self;
+    }
+
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = Some(limit);
+        return self;
+    }
+
+    pub fn build(self) -> Result<Query, AppError> {
+        if let Some(err) = self.error {
+            return Err(AppError::internal(
+                format!("building a SELECT statement from `{}`", self.from),
+                err,
+            ));
+        }
+
+        let mut statement = format!("{}{}", self.from, self.conditions.where_clause());
+        let mut args = self.conditions.args;
+
+        if let Some(order_by) = self.order_by {
+            statement.push_str(&format!(" ORDER BY {}", order_by));
+        }
+
+        if let Some(limit) = self.limit {
+            statement.push_str(" LIMIT ?");
+            args.push(DBV::Integer(limit));
+        }
+
+        return Ok(Query { statement, args });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ITEMS: Table = Table {
+        name: "items",
+        columns: &["id", "name", "updated_at"],
+    };
+
+    const TAGS: Table = Table {
+        name: "tags",
+        columns: &["id", "name"],
+    };
+
+    fn get_reason(result: Result<Query, AppError>) -> String {
+        let Err(AppError::Internal { source, .. }) = result else {
+            panic!("Expected the statement to fail building");
+        };
+
+        return source.to_string();
+    }
+
+    #[test]
+    fn insert_builds_an_upsert() {
+        let query = Insert::into(&ITEMS)
+            .value("id", DBV::Integer(1))
+            .value_sql("name", "lower(?)", vec![DBV::from("Aiko")])
+            .on_conflict(&["id"])
+            .update_excluded(&["name"])
+            .returning_all()
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            query.statement,
+            "INSERT INTO items (id, name) VALUES (?, lower(?)) \
+            ON CONFLICT (id) DO UPDATE SET name = excluded.name RETURNING *"
+        );
+        assert_eq!(query.args.len(), 2);
+    }
+
+    #[test]
+    fn insert_rejects_unknown_and_repeated_columns() {
+        let result = Insert::into(&ITEMS).value("email", DBV::Null).build();
+        assert_eq!(get_reason(result), "`email` is not a column of items");
+
+        let result = Insert::into(&ITEMS)
+            .value("name", DBV::Null)
+            .value("name", DBV::Null)
+            .build();
+        assert_eq!(get_reason(result), "`name` is assigned twice");
+
+        let result = Insert::into(&ITEMS)
+            .value("id", DBV::Integer(1))
+            .returning(&["email"])
+            .build();
+        assert_eq!(get_reason(result), "`email` is not a column of items");
+    }
+
+    #[test]
+    fn placeholders_have_to_match_the_arguments() {
+        let result = Insert::into(&ITEMS)
+            .value_sql("name", "? || ?", vec![DBV::from("Aiko")])
+            .build();
+        assert_eq!(
+            get_reason(result),
+            "`? || ?` has 2 placeholder(s) but 1 argument(s)"
+        );
+
+        let result = Select::from("SELECT * FROM items")
+            .filter("id = ?", Vec::new())
+            .build();
+        assert_eq!(
+            get_reason(result),
+            "`id = ?` has 1 placeholder(s) but 0 argument(s)"
+        );
+    }
+
+    #[test]
+    fn update_sets_updated_at_and_orders_arguments() {
+        let query = Update::table(&ITEMS)
+            .set("name", DBV::from("Aiko"))
+            .filter("id = ?", vec![DBV::Integer(1)])
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            query.statement,
+            "UPDATE items SET name = ?, updated_at = strftime('%s','now') WHERE (id = ?)"
+        );
+        assert!(matches!(
+            query.args.as_slice(),
+            [DBV::Text(_), DBV::Integer(1)]
+        ));
+
+        // Tables without the column are left alone
+        let query = Update::table(&TAGS)
+            .set("name", DBV::from("new"))
+            .filter("id = ?", vec![DBV::Integer(1)])
+            .build()
+            .unwrap();
+        assert_eq!(query.statement, "UPDATE tags SET name = ? WHERE (id = ?)");
+    }
+
+    #[test]
+    fn update_and_delete_need_a_condition() {
+        let result = Update::table(&ITEMS).set("name", DBV::Null).build();
+        assert_eq!(
+            get_reason(result),
+            "no condition, every row would be updated"
+        );
+
+        let result = Update::table(&ITEMS)
+            .filter("id = ?", vec![DBV::Integer(1)])
+            .build();
+        assert_eq!(get_reason(result), "no columns to update");
+
+        let result = Delete::from(&ITEMS).build();
+        assert_eq!(
+            get_reason(result),
+            "no condition, every row would be deleted"
+        );
+    }
+
+    #[test]
+    fn select_joins_conditions_and_pages() {
+        let query = Select::from("SELECT * FROM items")
+            .filter("name = ? OR name = ?", vec![DBV::from("a"), DBV::from("b")])
+            .filter_in("id", vec![DBV::Integer(1), DBV::Integer(2)])
+            .order_by("id DESC")
+            .limit(10)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            query.statement,
+            "SELECT * FROM items WHERE (name = ? OR name = ?) AND (id IN (?, ?)) \
+            ORDER BY id DESC LIMIT ?"
+        );
+        assert_eq!(query.args.len(), 5);
+        assert!(matches!(query.args.last(), Some(DBV::Integer(10))));
+    }
+
+    #[test]
+    fn select_in_an_empty_list_matches_nothing() {
+        let query = Select::from("SELECT * FROM items")
+            .filter_in("id", Vec::new())
+            .build()
+            .unwrap();
+
+        assert_eq!(query.statement, "SELECT * FROM items WHERE (0)");
+        assert!(query.args.is_empty());
+    }
+
+    #[test]
+    fn delete_builds_with_its_conditions() {
+        let query = Delete::from(&ITEMS)
+            .filter("id = ?", vec![DBV::Integer(1)])
+            .build()
+            .unwrap();
+
+        assert_eq!(query.statement, "DELETE FROM items WHERE (id = ?)");
+        assert_eq!(query.args.len(), 1);
+    }
+}
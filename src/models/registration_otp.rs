@@ -0,0 +1,135 @@
+use libsql::{Connection, Value as DBV};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    query::{Delete, Insert, Select, Table, Update, NOW},
+    util::FromRow,
+};
+use crate::utils::app_error::AppError;
+
+const REGISTRATION_OTPS: Table = Table {
+    name: "registration_otps",
+    columns: &[
+        "id",
+        "email",
+        "code_hash",
+        "password_hash",
+        "first_name",
+        "last_name",
+        "birth_date",
+        "attempts",
+        "expires_at",
+        "created_at",
+    ],
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, FromRow)]
+pub struct RegistrationOtp {
+    pub id: Option<i64>,
+    pub email: Option<String>,
+    pub code_hash: Option<String>,
+    pub password_hash: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub birth_date: Option<i64>,
+    pub attempts: Option<i64>,
+    pub expires_at: Option<i64>,
+    pub created_at: Option<i64>,
+}
+
+pub struct RegistrationOtpParams {
+    pub email: String,
+    pub code_hash: String,
+    pub password_hash: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub birth_date: i64,
+    pub expires_at: i64,
+}
+
+/// Stores a pending registration, replacing any earlier code for the same email.
+pub async fn upsert_registration_otp(
+    db_conn: &Connection,
+    params: RegistrationOtpParams,
+) -> Result<RegistrationOtp, AppError> {
+    let row = Insert::into(&REGISTRATION_OTPS)
+        .value("email", DBV::from(params.email.as_str()))
+        .value("code_hash", DBV::from(params.code_hash.as_str()))
+        .value("password_hash", DBV::from(params.password_hash.as_str()))
+        .value("first_name", DBV::from(params.first_name.as_str()))
+        .value("last_name", DBV::from(params.last_name.as_str()))
+        .value("birth_date", DBV::Integer(params.birth_date))
+        .value("expires_at", DBV::Integer(params.expires_at))
+        .on_conflict(&["email"])
+        .update_excluded(&[
+            "code_hash",
+            "password_hash",
+            "first_name",
+            "last_name",
+            "birth_date",
+            "expires_at",
+        ])
+        .update_sql("attempts", "0")
+        .update_sql("created_at", NOW)
+        .returning_all()
+        .build()?
+        .get_one(db_conn)
+        .await?;
+
+    return RegistrationOtp::from_row(&row);
+}
+
+pub async fn get_registration_otp_by_email(
+    email: &str,
+    db_conn: &Connection,
+) -> Result<RegistrationOtp, AppError> {
+    let row = Select::from("SELECT * FROM registration_otps")
+        .filter("email = ?", vec![DBV::from(email)])
+        .limit(1)
+        .build()?
+        .get_one(db_conn)
+        .await?;
+
+    return RegistrationOtp::from_row(&row);
+}
+
+/// Counts a guess of the code unless `max_attempts` were already made.
+/// Returns false when no guess is left. Checking and counting in one
+/// statement keeps concurrent guesses within the limit.
+pub async fn increment_registration_otp_attempts(
+    id: i64,
+    max_attempts: i64,
+    db_conn: &Connection,
+) -> Result<bool, AppError> {
+    let query = Update::table(&REGISTRATION_OTPS)
+        .set_sql("attempts", "attempts + 1", Vec::new())
+        .filter("id = ?", vec![DBV::Integer(id)])
+        .filter("attempts < ?", vec![DBV::Integer(max_attempts)])
+        .returning(&["attempts"])
+        .build()?;
+
+    match query.get_one(db_conn).await {
+        Ok(_) => return Ok(true),
+        Err(AppError::NotFound) => return Ok(false),
+        Err(err) => return Err(err),
+    }
+}
+
+pub async fn delete_registration_otp(id: i64, db_conn: &Connection) -> Result<u64, AppError> {
+    return Delete::from(&REGISTRATION_OTPS)
+        .filter("id = ?", vec![DBV::Integer(id)])
+        .build()?
+        .execute(db_conn)
+        .await;
+}
+
+pub async fn delete_registration_otp_by_email(
+    email: &str,
+    db_conn: &Connection,
+) -> Result<u64, AppError> {
+    return Delete::from(&REGISTRATION_OTPS)
+        .filter("email = ?", vec![DBV::from(email)])
+        .build()?
+        .execute(db_conn)
+        .await;
+}
@@ -0,0 +1,175 @@
+use libsql::{Connection, Value as DBV};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{
+    query::{Insert, Select, Table, Update},
+    util::FromRow,
+};
+use crate::utils::app_error::{AppError, ResultExt};
+
+const REPORTS: Table = Table {
+    name: "reports",
+    columns: &[
+        "id",
+        "pid",
+        "reporter_profile_id",
+        "reported_profile_id",
+        "reason",
+        "details",
+        "status",
+        "resolution_note",
+        "handled_by_user_id",
+        "created_at",
+        "updated_at",
+    ],
+};
+
+#[derive(Serialize, Deserialize, Debug, FromRow)]
+pub struct Report {
+    pub id: Option<i64>,
+    pub pid: Option<Vec<u8>>,
+    pub reporter_profile_id: Option<i64>,
+    pub reported_profile_id: Option<i64>,
+    pub reason: Option<String>,
+    pub details: Option<String>,
+    pub status: Option<String>,
+    pub resolution_note: Option<String>,
+    pub handled_by_user_id: Option<i64>,
+    pub created_at: Option<i64>,
+    pub updated_at: Option<i64>,
+    // Joined from profiles when listing reports
+    pub reporter_profile_pid: Option<Vec<u8>>,
+    pub reported_profile_pid: Option<Vec<u8>>,
+    pub reported_user_id: Option<i64>,
+}
+
+pub struct ReportParams {
+    pub reporter_profile_id: i64,
+    pub reported_profile_id: i64,
+    pub reason: String,
+    pub details: Option<String>,
+}
+
+const REPORT_SELECT: &str = "SELECT reports.*, \
+    reporter.pid AS reporter_profile_pid, \
+    reported.pid AS reported_profile_pid, \
+    reported.user_id AS reported_user_id \
+    FROM reports \
+    JOIN profiles AS reporter ON reporter.id = reports.reporter_profile_id \
+    JOIN profiles AS reported ON reported.id = reports.reported_profile_id";
+
+pub async fn create_report(db_conn: &Connection, params: ReportParams) -> Result<Uuid, AppError> {
+    let pid = Uuid::new_v4();
+
+    let details = params
+        .details
+        .map(|details| DBV::from(details.as_str()))
+        .unwrap_or(DBV::Null);
+
+    Insert::into(&REPORTS)
+        .value("pid", DBV::from(pid.as_bytes().to_vec()))
+        .value(
+            "reporter_profile_id",
+            DBV::Integer(params.reporter_profile_id),
+        )
+        .value(
+            "reported_profile_id",
+            DBV::Integer(params.reported_profile_id),
+        )
+        .value("reason", DBV::from(params.reason.as_str()))
+        .value("details", details)
+        .build()?
+        .execute(db_conn)
+        .await?;
+
+    return Ok(pid);
+}
+
+pub async fn get_report_by_pid(pid: &Uuid, db_conn: &Connection) -> Result<Report, AppError> {
+    let row = Select::from(REPORT_SELECT)
+        .filter("reports.pid = ?", vec![DBV::from(pid.as_bytes().to_vec())])
+        .limit(1)
+        .build()?
+        .get_one(db_conn)
+        .await?;
+
+    return Report::from_row(&row);
+}
+
+/// Oldest reports first, so the queue is worked through in order.
+pub async fn get_reports(
+    status: Option<&str>,
+    after_id: i64,
+    limit: i64,
+    db_conn: &Connection,
+) -> Result<Vec<Report>, AppError> {
+    let mut query =
+        Select::from(REPORT_SELECT).filter("reports.id > ?", vec![DBV::Integer(after_id)]);
+
+    if let Some(status) = status {
+        query = query.filter("reports.status = ?", vec![DBV::from(status)]);
+    }
+
+    let mut rows = query
+        .order_by("reports.id ASC")
+        .limit(limit)
+        .build()?
+        .get_many(db_conn)
+        .await?;
+    let mut reports: Vec<Report> = Vec::new();
+
+    while let Some(row) = rows.next().internal("reading reports")? {
+        reports.push(Report::from_row(&row)?);
+    }
+
+    return Ok(reports);
+}
+
+pub async fn update_report_status(
+    id: i64,
+    status: &str,
+    resolution_note: Option<String>,
+    handled_by_user_id: i64,
+    db_conn: &Connection,
+) -> Result<u64, AppError> {
+    let resolution_note = resolution_note
+        .map(|note| DBV::from(note.as_str()))
+        .unwrap_or(DBV::Null);
+
+    return Update::table(&REPORTS)
+        .set("status", DBV::from(status))
+        .set_sql(
+            "resolution_note",
+            "COALESCE(?, resolution_note)",
+            vec![resolution_note],
+        )
+        .set("handled_by_user_id", DBV::Integer(handled_by_user_id))
+        .filter("id = ?", vec![DBV::Integer(id)])
+        .build()?
+        .execute(db_conn)
+        .await;
+}
+
+/// Reports filed by the profile, oldest first.
+pub async fn get_reports_by_reporter_profile_id(
+    reporter_profile_id: i64,
+    db_conn: &Connection,
+) -> Result<Vec<Report>, AppError> {
+    let mut rows = Select::from(REPORT_SELECT)
+        .filter(
+            "reports.reporter_profile_id = ?",
+            vec![DBV::Integer(reporter_profile_id)],
+        )
+        .order_by("reports.id ASC")
+        .build()?
+        .get_many(db_conn)
+        .await?;
+    let mut reports: Vec<Report> = Vec::new();
+
+    while let Some(row) = rows.next().internal("reading reports")? {
+        reports.push(Report::from_row(&row)?);
+    }
+
+    return Ok(reports);
+}
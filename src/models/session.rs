@@ -0,0 +1,271 @@
+use libsql::{Connection, Value as DBV};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{
+    query::{Insert, Select, Table, Update, NOW},
+    util::{self, FromRow},
+};
+use crate::utils::app_error::{AppError, ResultExt};
+
+const SESSIONS: Table = Table {
+    name: "sessions",
+    columns: &[
+        "id",
+        "pid",
+        "user_id",
+        "refresh_token_hash",
+        "device_label",
+        "ip",
+        "expires_at",
+        "last_used_at",
+        "revoked_at",
+        "created_at",
+    ],
+};
+
+const ROTATED_REFRESH_TOKENS: Table = Table {
+    name: "rotated_refresh_tokens",
+    columns: &["token_hash", "session_id", "created_at"],
+};
+
+#[derive(Clone, Serialize, Deserialize, Debug, FromRow)]
+pub struct Session {
+    pub id: Option<i64>,
+    pub pid: Option<Vec<u8>>,
+    pub user_id: Option<i64>,
+    pub refresh_token_hash: Option<String>,
+    pub device_label: Option<String>,
+    pub ip: Option<String>,
+    pub expires_at: Option<i64>,
+    pub last_used_at: Option<i64>,
+    pub revoked_at: Option<i64>,
+    pub created_at: Option<i64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CacheSession {
+    pub id: i64,
+    pub pid: Uuid,
+    pub user_id: i64,
+    pub expires_at: i64,
+    pub is_revoked: bool,
+}
+
+impl CacheSession {
+    pub fn from(db_session: &Session) -> Result<Self, AppError> {
+        let id = db_session.id.ok_or(AppError::InternalServerError)?;
+        let user_id = db_session.user_id.ok_or(AppError::InternalServerError)?;
+        let expires_at = db_session.expires_at.ok_or(AppError::InternalServerError)?;
+        let pid_vec = db_session
+            .pid
+            .as_ref()
+            .ok_or(AppError::InternalServerError)?;
+
+        let pid = Uuid::from_slice(pid_vec.as_slice()).internal("reading a session pid")?;
+
+        Ok(CacheSession {
+            id,
+            pid,
+            user_id,
+            expires_at,
+            is_revoked: db_session.revoked_at.is_some(),
+        })
+    }
+}
+
+pub struct SessionParams {
+    pub user_id: i64,
+    pub refresh_token_hash: String,
+    pub device_label: Option<String>,
+    pub ip: Option<String>,
+    pub expires_at: i64,
+}
+
+fn optional_text(value: &Option<String>) -> DBV {
+    match value {
+        Some(value) => DBV::from(value.as_str()),
+        None => DBV::Null,
+    }
+}
+
+pub async fn create_session(
+    db_conn: &Connection,
+    params: SessionParams,
+) -> Result<Session, AppError> {
+    let pid = Uuid::new_v4().as_bytes().to_vec();
+
+    let row = Insert::into(&SESSIONS)
+        .value("pid", DBV::from(pid))
+        .value("user_id", DBV::Integer(params.user_id))
+        .value(
+            "refresh_token_hash",
+            DBV::from(params.refresh_token_hash.as_str()),
+        )
+        .value("device_label", optional_text(&params.device_label))
+        .value("ip", optional_text(&params.ip))
+        .value("expires_at", DBV::Integer(params.expires_at))
+        .value_sql("last_used_at", NOW, Vec::new())
+        .returning_all()
+        .build()?
+        .get_one(db_conn)
+        .await?;
+
+    return Session::from_row(&row);
+}
+
+pub async fn get_session_by_pid(pid: &Uuid, db_conn: &Connection) -> Result<Session, AppError> {
+    return get_session("pid = ?", DBV::from(pid.as_bytes().to_vec()), db_conn).await;
+}
+
+pub async fn get_session_by_refresh_token_hash(
+    refresh_token_hash: &str,
+    db_conn: &Connection,
+) -> Result<Session, AppError> {
+    let refresh_token_hash = DBV::from(refresh_token_hash);
+
+    return get_session("refresh_token_hash = ?", refresh_token_hash, db_conn).await;
+}
+
+async fn get_session(
+    condition: &'static str,
+    arg: DBV,
+    db_conn: &Connection,
+) -> Result<Session, AppError> {
+    let row = Select::from("SELECT * FROM sessions")
+        .filter(condition, vec![arg])
+        .limit(1)
+        .build()?
+        .get_one(db_conn)
+        .await?;
+
+    return Session::from_row(&row);
+}
+
+/// Returns the id of the session a rotated out refresh token belonged to.
+pub async fn get_session_id_by_rotated_token_hash(
+    refresh_token_hash: &str,
+    db_conn: &Connection,
+) -> Result<i64, AppError> {
+    let row = Select::from("SELECT session_id FROM rotated_refresh_tokens")
+        .filter("token_hash = ?", vec![DBV::from(refresh_token_hash)])
+        .limit(1)
+        .build()?
+        .get_one(db_conn)
+        .await?;
+
+    return util::column(&row, "session_id");
+}
+
+/// Swaps the session refresh token, but only if `old_hash` is still current.
+/// Returns false when another request rotated the token first. Run it in a
+/// transaction so the old token is never current and unrecorded at once.
+pub async fn rotate_session_refresh_token(
+    session_id: i64,
+    old_hash: &str,
+    new_hash: &str,
+    ip: &Option<String>,
+    db_conn: &Connection,
+) -> Result<bool, AppError> {
+    let rows_affected = Update::table(&SESSIONS)
+        .set("refresh_token_hash", DBV::from(new_hash))
+        .set_sql("ip", "COALESCE(?, ip)", vec![optional_text(ip)])
+        .set_sql("last_used_at", NOW, Vec::new())
+        .filter("id = ?", vec![DBV::Integer(session_id)])
+        .filter("refresh_token_hash = ?", vec![DBV::from(old_hash)])
+        .filter("revoked_at IS NULL", Vec::new())
+        .build()?
+        .execute(db_conn)
+        .await?;
+
+    if rows_affected == 0 {
+        return Ok(false);
+    }
+
+    Insert::into(&ROTATED_REFRESH_TOKENS)
+        .value("token_hash", DBV::from(old_hash))
+        .value("session_id", DBV::Integer(session_id))
+        .on_conflict(&["token_hash"])
+        .build()?
+        .execute(db_conn)
+        .await?;
+
+    return Ok(true);
+}
+
+pub async fn get_active_sessions_by_user_id(
+    user_id: i64,
+    db_conn: &Connection,
+) -> Result<Vec<Session>, AppError> {
+    let mut rows = Select::from("SELECT * FROM sessions")
+        .filter("user_id = ?", vec![DBV::Integer(user_id)])
+        .filter("revoked_at IS NULL", Vec::new())
+        .filter("expires_at > strftime('%s','now')", Vec::new())
+        .order_by("last_used_at DESC")
+        .build()?
+        .get_many(db_conn)
+        .await?;
+    let mut sessions = Vec::new();
+
+    while let Some(row) = rows.next().internal("reading sessions")? {
+        sessions.push(Session::from_row(&row)?);
+    }
+
+    return Ok(sessions);
+}
+
+pub async fn revoke_session(session_id: i64, db_conn: &Connection) -> Result<u64, AppError> {
+    return Update::table(&SESSIONS)
+        .set_sql("revoked_at", NOW, Vec::new())
+        .filter("id = ?", vec![DBV::Integer(session_id)])
+        .filter("revoked_at IS NULL", Vec::new())
+        .build()?
+        .execute(db_conn)
+        .await;
+}
+
+pub async fn revoke_user_sessions(user_id: i64, db_conn: &Connection) -> Result<u64, AppError> {
+    return Update::table(&SESSIONS)
+        .set_sql("revoked_at", NOW, Vec::new())
+        .filter("user_id = ?", vec![DBV::Integer(user_id)])
+        .filter("revoked_at IS NULL", Vec::new())
+        .build()?
+        .execute(db_conn)
+        .await;
+}
+
+/// Every session of the user including revoked and expired ones, newest first.
+pub async fn get_sessions_by_user_id(
+    user_id: i64,
+    db_conn: &Connection,
+) -> Result<Vec<Session>, AppError> {
+    let mut rows = Select::from("SELECT * FROM sessions")
+        .filter("user_id = ?", vec![DBV::Integer(user_id)])
+        .order_by("id DESC")
+        .build()?
+        .get_many(db_conn)
+        .await?;
+    let mut sessions = Vec::new();
+
+    while let Some(row) = rows.next().internal("reading sessions")? {
+        sessions.push(Session::from_row(&row)?);
+    }
+
+    return Ok(sessions);
+}
+
+/// Revokes every session of the user except `keep_session_id`.
+pub async fn revoke_other_user_sessions(
+    user_id: i64,
+    keep_session_id: i64,
+    db_conn: &Connection,
+) -> Result<u64, AppError> {
+    return Update::table(&SESSIONS)
+        .set_sql("revoked_at", NOW, Vec::new())
+        .filter("user_id = ?", vec![DBV::Integer(user_id)])
+        .filter("id != ?", vec![DBV::Integer(keep_session_id)])
+        .filter("revoked_at IS NULL", Vec::new())
+        .build()?
+        .execute(db_conn)
+        .await;
+}
@@ -1,35 +1,67 @@
-use crate::{
-    utils::{app_error::AppError, password::hash_password},
-    views::user::RegisterParams,
-};
+use crate::utils::app_error::{AppError, ResultExt};
 
-use super::util::{self, byte_from_value, i64_from_value, query_get_one, row_to_value_map};
+use super::{
+    query::{Delete, Insert, Select, Table, Update, NOW},
+    util::{self, FromRow},
+};
 use libsql::{Connection, Value as DBV};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use tracing::error;
 use uuid::Uuid;
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+const USERS: Table = Table {
+    name: "users",
+    columns: &[
+        "id",
+        "pid",
+        "email",
+        "password",
+        "role",
+        "suspended_at",
+        "created_at",
+        "updated_at",
+    ],
+};
+
+const USER_SELECT: &str = "SELECT * FROM users";
+
+#[derive(Clone, Debug, Deserialize, Serialize, FromRow)]
 pub struct User {
     pub id: Option<i64>,
     pub pid: Option<Vec<u8>>,
     pub email: Option<String>,
     pub password: Option<String>,
+    pub role: Option<String>,
+    pub suspended_at: Option<i64>,
     pub created_at: Option<i64>,
     pub updated_at: Option<i64>,
 }
 
-impl From<HashMap<String, libsql::Value>> for User {
-    fn from(value_map: HashMap<String, libsql::Value>) -> Self {
-        Self {
-            id: util::i64_from_value("id", &value_map),
-            pid: util::byte_from_value("pid", &value_map),
-            email: util::string_from_value("email", &value_map),
-            password: util::string_from_value("password", &value_map),
-            created_at: util::i64_from_value("created_at", &value_map),
-            updated_at: util::i64_from_value("updated_at", &value_map),
-        }
+/// Ordered by privilege, so `role >= Role::Moderator` reads as "at least a moderator".
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    User,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            Self::User => "user",
+            Self::Moderator => "moderator",
+            Self::Admin => "admin",
+        };
+    }
+
+    pub fn parse(role: &str) -> Option<Self> {
+        return match role {
+            "user" => Some(Self::User),
+            "moderator" => Some(Self::Moderator),
+            "admin" => Some(Self::Admin),
+            _ => None,
+        };
     }
 }
 
@@ -38,6 +70,7 @@ pub struct CacheUser {
     pub id: i32,
     pub pid: Uuid,
     pub email: String,
+    pub role: Role,
 }
 
 impl CacheUser {
@@ -56,104 +89,194 @@ impl CacheUser {
             return AppError::Unauthorized;
         })?;
 
-        Ok(CacheUser { id, pid, email })
+        let role = db_user
+            .role
+            .as_deref()
+            .and_then(Role::parse)
+            .ok_or(AppError::InternalServerError)?;
+
+        Ok(CacheUser {
+            id,
+            pid,
+            email,
+            role,
+        })
     }
 }
 
+//Production: The password must already be hashed, see utils::password::hash_password
 pub async fn create_user(
-    params: &RegisterParams,
+    email: &str,
+    hashed_password: &str,
     db_conn: &Connection,
 ) -> Result<(i64, Uuid), AppError> {
     let pid = Uuid::new_v4().as_bytes().to_vec();
-    let hashed_password = hash_password(&params.password)?;
-
-    let query_statement = "INSERT INTO users (email, password, pid) values (?, ?, ?) RETURNING id";
-    let query_args = vec![
-        DBV::from(params.email.as_str()),
-        DBV::from(hashed_password.as_str()),
-        DBV::from(pid.to_owned()),
-    ];
 
-    let row = query_get_one(query_statement, query_args, &db_conn).await?;
-    let value_map = row_to_value_map(row);
-    let id = i64_from_value("id", &value_map).ok_or(AppError::NotFound)?;
+    let row = Insert::into(&USERS)
+        .value("email", DBV::from(email))
+        .value("password", DBV::from(hashed_password))
+        .value("pid", DBV::from(pid.to_owned()))
+        .returning(&["id"])
+        .build()?
+        .get_one(db_conn)
+        .await?;
+    let id = util::column(&row, "id")?;
 
     // TODO: Abstract this into a function!
-    let uuid = Uuid::from_slice(pid.as_slice()).map_err(|err| {
-        error!("{:?}", err);
-        return AppError::InternalServerError;
-    })?;
+    let uuid = Uuid::from_slice(pid.as_slice()).internal("reading a user pid")?;
 
     Ok((id, uuid))
 }
 
-pub async fn get_user_ids_by_email(
-    email: &String,
+/// The first row matching `condition`, `AppError::UserDoesNotExist` when there is none.
+async fn get_user_row(
+    from: &'static str,
+    condition: &'static str,
+    arg: DBV,
     db_conn: &Connection,
-) -> Result<(i64, Uuid), AppError> {
-    let query_statement = "SELECT id, pid FROM users WHERE email = ? LIMIT 1";
-    let query_args = vec![DBV::from(email.as_str())];
-
-    let row = match query_get_one(query_statement, query_args, db_conn).await {
-        Ok(value) => value,
-        Err(err) => match err {
-            AppError::NotFound => return Err(AppError::UserDoesNotExist),
-            err => return Err(err),
-        },
-    };
+) -> Result<libsql::Row, AppError> {
+    let query = Select::from(from)
+        .filter(condition, vec![arg])
+        .limit(1)
+        .build()?;
 
-    let value_map = row_to_value_map(row);
+    return match query.get_one(db_conn).await {
+        Ok(row) => Ok(row),
+        Err(AppError::NotFound) => Err(AppError::UserDoesNotExist),
+        Err(err) => Err(err),
+    };
+}
 
-    let id = i64_from_value("id", &value_map).ok_or(AppError::NotFound)?;
-    let pid = byte_from_value("pid", &value_map).ok_or(AppError::NotFound)?;
+pub async fn get_user_ids_by_email(
+    email: &str,
+    db_conn: &Connection,
+) -> Result<(i64, Uuid), AppError> {
+    let row = get_user_row(
+        "SELECT id, pid FROM users",
+        "email = ?",
+        DBV::from(email),
+        db_conn,
+    )
+    .await?;
 
-    let uuid = Uuid::from_slice(pid.as_slice()).map_err(|err| {
-        error!("{:?}", err);
-        return AppError::InternalServerError;
-    })?;
+    let id = util::column(&row, "id")?;
+    let pid = util::column(&row, "pid")?;
 
-    Ok((id, uuid))
+    Ok((id, pid))
 }
 
-pub async fn get_user_by_email(email: &String, db_conn: &Connection) -> Result<User, AppError> {
-    let query_statement = "SELECT * FROM users WHERE email = ? LIMIT 1";
-    let query_args = vec![DBV::from(email.as_str())];
+pub async fn get_user_by_email(email: &str, db_conn: &Connection) -> Result<User, AppError> {
+    let row = get_user_row(USER_SELECT, "email = ?", DBV::from(email), db_conn).await?;
 
-    let row = match query_get_one(query_statement, query_args, db_conn).await {
-        Ok(value) => value,
-        Err(err) => match err {
-            AppError::NotFound => return Err(AppError::UserDoesNotExist),
-            err => return Err(err),
-        },
-    };
+    User::from_row(&row)
+}
 
-    let value_map = row_to_value_map(row);
+pub async fn get_user_by_id(id: i64, db_conn: &Connection) -> Result<User, AppError> {
+    let row = get_user_row(USER_SELECT, "id = ?", DBV::Integer(id), db_conn).await?;
 
-    Ok(User::from(value_map))
+    User::from_row(&row)
 }
 
-pub async fn get_user_by_pid(pid: &String, db_conn: &Connection) -> Result<User, AppError> {
+pub async fn get_user_by_pid(pid: &str, db_conn: &Connection) -> Result<User, AppError> {
     // TODO: Abstract this into a function!
     let pid = Uuid::try_parse(pid)
-        .map_err(|err| {
-            error!("{:?}", err);
-            return AppError::InternalServerError;
-        })?
+        .internal("parsing a user pid")?
         .as_bytes()
         .to_vec();
 
-    let query_statement = "SELECT * FROM users WHERE pid = ? LIMIT 1";
-    let query_args = vec![DBV::from(pid)];
+    let row = get_user_row(USER_SELECT, "pid = ?", DBV::from(pid), db_conn).await?;
+
+    User::from_row(&row)
+}
+
+/// Suspends the user when `is_suspended`, otherwise lifts the suspension.
+pub async fn set_user_suspended(
+    user_id: i64,
+    is_suspended: bool,
+    db_conn: &Connection,
+) -> Result<u64, AppError> {
+    let query = Update::table(&USERS).filter("id = ?", vec![DBV::Integer(user_id)]);
 
-    let row = match query_get_one(query_statement, query_args, db_conn).await {
-        Ok(value) => value,
-        Err(err) => match err {
-            AppError::NotFound => return Err(AppError::UserDoesNotExist),
-            err => return Err(err),
-        },
+    let query = if is_suspended {
+        query
+            .set_sql("suspended_at", NOW, Vec::new())
+            .filter("suspended_at IS NULL", Vec::new())
+    } else {
+        query.set("suspended_at", DBV::Null)
     };
 
-    let value_map = row_to_value_map(row);
+    return query.build()?.execute(db_conn).await;
+}
+
+pub async fn set_user_role(
+    user_id: i64,
+    role: Role,
+    db_conn: &Connection,
+) -> Result<u64, AppError> {
+    return Update::table(&USERS)
+        .set("role", DBV::from(role.as_str()))
+        .filter("id = ?", vec![DBV::Integer(user_id)])
+        .build()?
+        .execute(db_conn)
+        .await;
+}
+
+/// Users whose email contains `email_query`, oldest first.
+pub async fn search_users_by_email(
+    email_query: &str,
+    after_id: i64,
+    limit: i64,
+    db_conn: &Connection,
+) -> Result<Vec<User>, AppError> {
+    // Escape LIKE wildcards so the query is matched literally
+    let pattern = format!(
+        "%{}%",
+        email_query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    );
+
+    let mut rows = Select::from(USER_SELECT)
+        .filter(
+            "email LIKE ? ESCAPE '\\'",
+            vec![DBV::from(pattern.as_str())],
+        )
+        .filter("id > ?", vec![DBV::Integer(after_id)])
+        .order_by("id ASC")
+        .limit(limit)
+        .build()?
+        .get_many(db_conn)
+        .await?;
+    let mut users: Vec<User> = Vec::new();
+
+    while let Some(row) = rows.next().internal("reading users")? {
+        users.push(User::from_row(&row)?);
+    }
+
+    return Ok(users);
+}
 
-    Ok(User::from(value_map))
+/// Deletes the user, the profile and everything hanging off it go with it
+/// through the `ON DELETE CASCADE` foreign keys.
+pub async fn delete_user(user_id: i64, db_conn: &Connection) -> Result<u64, AppError> {
+    return Delete::from(&USERS)
+        .filter("id = ?", vec![DBV::Integer(user_id)])
+        .build()?
+        .execute(db_conn)
+        .await;
+}
+
+//Production: The password must already be hashed, see utils::password::hash_password
+pub async fn set_user_password(
+    user_id: i64,
+    hashed_password: &str,
+    db_conn: &Connection,
+) -> Result<u64, AppError> {
+    return Update::table(&USERS)
+        .set("password", DBV::from(hashed_password))
+        .filter("id = ?", vec![DBV::Integer(user_id)])
+        .build()?
+        .execute(db_conn)
+        .await;
 }
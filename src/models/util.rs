@@ -1,75 +1,330 @@
-use std::collections::HashMap;
-use tracing::{error, warn};
+use std::{ops::Deref, sync::Arc};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tracing::warn;
 
-use crate::utils::app_error::AppError;
-use libsql::{Connection, Value};
+use crate::utils::app_error::{AppError, ResultExt};
+pub use gsm_macros::FromRow;
+use libsql::{Connection, Database, Row, TransactionBehavior, Value};
+use uuid::Uuid;
 
-pub fn i64_from_value(column_name: &str, value_map: &HashMap<String, Value>) -> Option<i64> {
-    match value_map.get(column_name) {
-        Some(value_type) => match value_type {
-            Value::Integer(value) => Some(value.to_owned()),
+// How long a statement waits for the write lock of another connection, like
+// the one of an open transaction, before failing as busy
+const BUSY_TIMEOUT_MS: i64 = 5000;
+
+/// Where transactions get their connection from. A transaction on a database
+/// file runs on a connection of its own, so statements of other requests
+/// can't end up inside it and only wait for it when they write.
+#[derive(Clone)]
+pub struct TransactionSource {
+    kind: SourceKind,
+}
+
+#[derive(Clone)]
+enum SourceKind {
+    // The path of the database file
+    File(String),
+    // An in-memory database only exists on the connection that created it,
+    // so its transactions take turns on the shared connection. Statements of
+    // other requests can still run inside them, which is fine for development.
+    Memory(Connection, Arc<Mutex<()>>),
+    // The HTTP client of libsql sends every statement on a stream of its own
+    // and can't keep a transaction open across them
+    Remote(Connection),
+}
+
+impl TransactionSource {
+    pub fn file(path: &str) -> Self {
+        return Self {
+            kind: SourceKind::File(path.to_string()),
+        };
+    }
+
+    pub fn memory(db_conn: &Connection) -> Self {
+        return Self {
+            kind: SourceKind::Memory(db_conn.clone(), Arc::new(Mutex::new(()))),
+        };
+    }
+
+    pub fn remote(db_conn: &Connection) -> Self {
+        warn!("Remote databases can't hold a transaction, multi-step writes are not atomic!");
+
+        return Self {
+            kind: SourceKind::Remote(db_conn.clone()),
+        };
+    }
+
+    /// Settings SQLite keeps per connection, for every connection opened to
+    /// the database.
+    pub async fn configure_connection(&self, db_conn: &Connection) -> Result<(), AppError> {
+        // Off by default, account deletion relies on ON DELETE CASCADE
+        execute("PRAGMA foreign_keys = ON", Vec::new(), db_conn).await?;
+
+        if let SourceKind::File(_) = self.kind {
+            // The pragma answers with the new timeout, so it has to be a query
+            query_get_many(
+                format!("PRAGMA busy_timeout = {}", BUSY_TIMEOUT_MS).as_str(),
+                Vec::new(),
+                db_conn,
+            )
+            .await?;
+        }
+
+        return Ok(());
+    }
+}
+
+/// An open transaction. Model functions run inside it when they are given
+/// `&tx` as their connection. Dropping it without `commit`, like on an early
+/// `?` return, rolls it back.
+///
+/// While it is open, writes through any other connection, including the
+/// repositories of `AppState`, wait for it to end, so a flow writes through
+/// `&tx` only.
+pub struct Transaction {
+    db_conn: Connection,
+    // Declared before the turn so the rollback on drop runs first
+    tx: Option<libsql::Transaction>,
+    _turn: Option<OwnedMutexGuard<()>>,
+}
+
+pub async fn begin_transaction(source: &TransactionSource) -> Result<Transaction, AppError> {
+    let (db_conn, turn) = match &source.kind {
+        SourceKind::File(path) => {
+            let db_conn = Database::open(path.as_str())
+                .and_then(|database| database.connect())
+                .internal("opening a transaction connection")?;
+            source.configure_connection(&db_conn).await?;
+
+            (db_conn, None)
+        }
+        SourceKind::Memory(db_conn, turns) => {
+            (db_conn.clone(), Some(turns.clone().lock_owned().await))
+        }
+        SourceKind::Remote(db_conn) => {
+            return Ok(Transaction {
+                db_conn: db_conn.clone(),
+                tx: None,
+                _turn: None,
+            });
+        }
+    };
+
+    // IMMEDIATE takes the write lock up front, so a transaction never fails
+    // halfway through because another connection started writing first
+    let tx = db_conn
+        .transaction_with_behavior(TransactionBehavior::Immediate)
+        .await
+        .internal("beginning a transaction")?;
+
+    return Ok(Transaction {
+        db_conn,
+        tx: Some(tx),
+        _turn: turn,
+    });
+}
+
+impl Transaction {
+    pub async fn commit(mut self) -> Result<(), AppError> {
+        if let Some(tx) = self.tx.take() {
+            tx.commit().await.internal("committing a transaction")?;
+        }
+
+        return Ok(());
+    }
+}
+
+impl Deref for Transaction {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        return &self.db_conn;
+    }
+}
+
+/// Maps a row into a struct by column name, usually through
+/// `#[derive(FromRow)]`. A missing column or a value of the wrong type is an
+/// error rather than a silently empty field.
+pub trait FromRow: Sized {
+    fn from_row(row: &Row) -> Result<Self, AppError>;
+}
+
+/// Conversion of a single column value. `NULL` only converts into an `Option`.
+pub trait FromColumn: Sized {
+    /// The column type expected, for error messages
+    const EXPECTED: &'static str;
+
+    /// Returns `None` when the value has another type.
+    fn from_value(value: Value) -> Option<Self>;
+}
+
+impl FromColumn for i64 {
+    const EXPECTED: &'static str = "INTEGER";
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Integer(value) => Some(value),
             _ => None,
-        },
-        None => None,
+        }
     }
 }
 
-pub fn f64_from_value(column_name: &str, value_map: &HashMap<String, Value>) -> Option<f64> {
-    match value_map.get(column_name) {
-        Some(value_type) => match value_type {
-            Value::Real(value) => Some(value.to_owned()),
+impl FromColumn for f64 {
+    const EXPECTED: &'static str = "REAL";
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Real(value) => Some(value),
             _ => None,
-        },
-        None => None,
+        }
     }
 }
 
-pub fn bool_from_value(column_name: &str, value_map: &HashMap<String, Value>) -> Option<bool> {
-    match value_map.get(column_name) {
-        Some(value_type) => match value_type {
-            Value::Integer(value) => {
-                if value.to_owned() == 0 {
-                    Some(false)
-                } else {
-                    Some(true)
-                }
-            }
+// SQLite has no boolean type, flags like is_visible are stored as 0 or 1
+impl FromColumn for bool {
+    const EXPECTED: &'static str = "INTEGER (0 or 1)";
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Integer(0) => Some(false),
+            Value::Integer(1) => Some(true),
             _ => None,
-        },
-        None => None,
+        }
     }
 }
 
-pub fn string_from_value(column_name: &str, value_map: &HashMap<String, Value>) -> Option<String> {
-    match value_map.get(column_name) {
-        Some(value_type) => match value_type {
-            Value::Text(value) => Some(value.to_owned()),
+impl FromColumn for String {
+    const EXPECTED: &'static str = "TEXT";
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Text(value) => Some(value),
             _ => None,
-        },
-        None => None,
+        }
     }
 }
 
-pub fn byte_from_value(column_name: &str, value_map: &HashMap<String, Value>) -> Option<Vec<u8>> {
-    match value_map.get(column_name) {
-        Some(value_type) => match value_type {
-            Value::Blob(value) => Some(value.to_owned()),
+impl FromColumn for Vec<u8> {
+    const EXPECTED: &'static str = "BLOB";
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Blob(value) => Some(value),
             _ => None,
-        },
-        None => None,
+        }
+    }
+}
+
+// Public ids are stored in their 16 byte form, see the pid columns
+impl FromColumn for Uuid {
+    const EXPECTED: &'static str = "BLOB(16)";
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Blob(value) => Uuid::from_slice(value.as_slice()).ok(),
+            _ => None,
+        }
+    }
+}
+
+impl<T: FromColumn> FromColumn for Option<T> {
+    const EXPECTED: &'static str = T::EXPECTED;
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Null => Some(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RowError {
+    MissingColumn,
+    WrongType {
+        expected: &'static str,
+        found: &'static str,
+    },
+}
+
+impl std::fmt::Display for RowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingColumn => write!(f, "the column is not part of the row"),
+            Self::WrongType { expected, found } => {
+                write!(f, "expected {} but found {}", expected, found)
+            }
+        }
     }
 }
 
+impl std::error::Error for RowError {}
+
+fn value_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "NULL",
+        Value::Integer(_) => "INTEGER",
+        Value::Real(_) => "REAL",
+        Value::Text(_) => "TEXT",
+        Value::Blob(_) => "BLOB",
+    }
+}
+
+fn column_index(row: &Row, column_name: &str) -> Option<i32> {
+    // A row doesn't know its column count, the names simply run out
+    let mut index = 0;
+
+    while let Some(name) = row.column_name(index) {
+        if name == column_name {
+            return Some(index);
+        }
+
+        index += 1;
+    }
+
+    return None;
+}
+
+/// Reads `column_name` of the row as `T`.
+pub fn column<T: FromColumn>(row: &Row, column_name: &str) -> Result<T, AppError> {
+    let context = format!("reading column `{}`", column_name);
+
+    let index = column_index(row, column_name)
+        .ok_or_else(|| AppError::internal(context.to_owned(), RowError::MissingColumn))?;
+    let value = row.get_value(index).internal(context.to_owned())?;
+    let found = value_type_name(&value);
+
+    return T::from_value(value).ok_or_else(|| {
+        AppError::internal(
+            context,
+            RowError::WrongType {
+                expected: T::EXPECTED,
+                found,
+            },
+        )
+    });
+}
+
+/// Like `column`, but a column the query didn't select reads as `T::default()`.
+pub fn column_or_default<T: FromColumn + Default>(
+    row: &Row,
+    column_name: &str,
+) -> Result<T, AppError> {
+    if column_index(row, column_name).is_none() {
+        return Ok(T::default());
+    }
+
+    return column(row, column_name);
+}
+
 pub async fn execute(
     statement: &str,
     args: Vec<libsql::Value>,
     db_conn: &Connection,
 ) -> Result<u64, AppError> {
-    let rows_affected = db_conn.execute(statement, args).await.map_err(|err| {
-        /* Network layer error */
-        error!("{:?}", err);
-        AppError::InternalServerError
-    })?;
+    let rows_affected = db_conn
+        .execute(statement, args)
+        .await
+        .internal(format!("executing `{}`", statement))?;
 
     return Ok(rows_affected);
 }
@@ -82,17 +337,9 @@ pub async fn query_get_one(
     let row = db_conn
         .query(statement, args)
         .await
-        .map_err(|err| {
-            /* Network layer error */
-            error!("{:?}", err);
-            AppError::InternalServerError
-        })?
+        .internal(format!("querying `{}`", statement))?
         .next()
-        .map_err(|err| {
-            /* LIBSQL sever layer error */
-            error!("{:?}", err);
-            AppError::InternalServerError
-        })?
+        .internal(format!("reading a row of `{}`", statement))?
         .ok_or_else(|| {
             /* SQL query layer error */
             warn!(
@@ -113,28 +360,98 @@ pub async fn query_get_many(
     args: Vec<libsql::Value>,
     db_conn: &Connection,
 ) -> Result<libsql::Rows, AppError> {
-    let rows = db_conn.query(statement, args).await.map_err(|err| {
-        /* Network layer error */
-        error!("{:?}", err);
-        AppError::InternalServerError
-    })?;
+    let rows = db_conn
+        .query(statement, args)
+        .await
+        .internal(format!("querying `{}`", statement))?;
 
     return Ok(rows);
 }
 
-pub fn row_to_value_map(row: libsql::Row) -> HashMap<String, libsql::Value> {
-    let mut value_map = HashMap::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, FromRow)]
+    struct Item {
+        id: i64,
+        name: Option<String>,
+        #[row(default)]
+        score: Option<i64>,
+    }
+
+    async fn get_row(statement: &str) -> Row {
+        let db_conn = Database::open_in_memory().unwrap().connect().unwrap();
+
+        return query_get_one(statement, Vec::new(), &db_conn)
+            .await
+            .unwrap();
+    }
 
-    //A database row should not have up to a 100 columns...
-    //keeping the range in case not to loop forever!!!
-    for i in 0..100 {
-        if let Some(column_name) = row.column_name(i) {
-            let column_value = row.get_value(i).unwrap_or(libsql::Value::Null);
-            value_map.insert(column_name.to_string(), column_value)
-        } else {
-            break;
+    fn get_row_error(result: Result<Item, AppError>) -> (String, String) {
+        let Err(AppError::Internal { context, source }) = result else {
+            panic!("Expected an internal error, got {:?}", result);
         };
+
+        return (context.to_string(), source.to_string());
+    }
+
+    #[tokio::test]
+    async fn from_row_reads_every_field() {
+        let row = get_row("SELECT 1 AS id, 'Aiko' AS name, 7 AS score").await;
+        let item = Item::from_row(&row).unwrap();
+
+        assert_eq!(item.id, 1);
+        assert_eq!(item.name.as_deref(), Some("Aiko"));
+        assert_eq!(item.score, Some(7));
+    }
+
+    #[tokio::test]
+    async fn from_row_reads_null_as_none() {
+        let row = get_row("SELECT 1 AS id, NULL AS name, NULL AS score").await;
+        let item = Item::from_row(&row).unwrap();
+
+        assert_eq!(item.name, None);
+        assert_eq!(item.score, None);
     }
 
-    return value_map;
+    #[tokio::test]
+    async fn from_row_defaults_a_missing_default_column() {
+        let row = get_row("SELECT 1 AS id, 'Aiko' AS name").await;
+        let item = Item::from_row(&row).unwrap();
+
+        assert_eq!(item.score, None);
+    }
+
+    #[tokio::test]
+    async fn from_row_fails_on_a_missing_column() {
+        let row = get_row("SELECT 'Aiko' AS name").await;
+        let (context, source) = get_row_error(Item::from_row(&row));
+
+        assert_eq!(context, "reading column `id`");
+        assert_eq!(source, "the column is not part of the row");
+    }
+
+    #[tokio::test]
+    async fn from_row_fails_on_a_mistyped_column() {
+        let row = get_row("SELECT 'one' AS id, 'Aiko' AS name").await;
+        let (context, source) = get_row_error(Item::from_row(&row));
+
+        assert_eq!(context, "reading column `id`");
+        assert_eq!(source, "expected INTEGER but found TEXT");
+
+        // NULL only fits an Option
+        let row = get_row("SELECT NULL AS id, 'Aiko' AS name").await;
+        let (_, source) = get_row_error(Item::from_row(&row));
+
+        assert_eq!(source, "expected INTEGER but found NULL");
+    }
+
+    #[tokio::test]
+    async fn from_row_checks_the_type_of_a_default_column() {
+        let row = get_row("SELECT 1 AS id, 'Aiko' AS name, 'high' AS score").await;
+        let (context, _) = get_row_error(Item::from_row(&row));
+
+        assert_eq!(context, "reading column `score`");
+    }
 }
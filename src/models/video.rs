@@ -0,0 +1,105 @@
+use libsql::{Connection, Value as DBV};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{
+    query::{Delete, Insert, Select, Table},
+    util::FromRow,
+};
+use crate::utils::app_error::{AppError, ResultExt};
+
+const VIDEOS: Table = Table {
+    name: "videos",
+    columns: &[
+        "id",
+        "pid",
+        "profile_id",
+        "storage_key",
+        "mime_type",
+        "size_bytes",
+        "created_at",
+    ],
+};
+
+#[derive(Serialize, Deserialize, Debug, FromRow)]
+pub struct Video {
+    pub id: Option<i64>,
+    pub pid: Option<Vec<u8>>,
+    pub profile_id: Option<i64>,
+    pub storage_key: Option<String>,
+    pub mime_type: Option<String>,
+    pub size_bytes: Option<i64>,
+    pub created_at: Option<i64>,
+}
+
+pub struct VideoParams {
+    pub pid: Uuid,
+    pub profile_id: i64,
+    pub storage_key: String,
+    pub mime_type: String,
+    pub size_bytes: i64,
+}
+
+pub async fn create_video(db_conn: &Connection, params: VideoParams) -> Result<Video, AppError> {
+    let row = Insert::into(&VIDEOS)
+        .value("pid", DBV::from(params.pid.as_bytes().to_vec()))
+        .value("profile_id", DBV::Integer(params.profile_id))
+        .value("storage_key", DBV::from(params.storage_key.as_str()))
+        .value("mime_type", DBV::from(params.mime_type.as_str()))
+        .value("size_bytes", DBV::Integer(params.size_bytes))
+        .returning_all()
+        .build()?
+        .get_one(db_conn)
+        .await?;
+
+    return Video::from_row(&row);
+}
+
+pub async fn get_video_by_pid(pid: &Uuid, db_conn: &Connection) -> Result<Video, AppError> {
+    let row = Select::from("SELECT * FROM videos")
+        .filter("pid = ?", vec![DBV::from(pid.as_bytes().to_vec())])
+        .limit(1)
+        .build()?
+        .get_one(db_conn)
+        .await?;
+
+    return Video::from_row(&row);
+}
+
+pub async fn get_video_by_id(id: i64, db_conn: &Connection) -> Result<Video, AppError> {
+    let row = Select::from("SELECT * FROM videos")
+        .filter("id = ?", vec![DBV::Integer(id)])
+        .limit(1)
+        .build()?
+        .get_one(db_conn)
+        .await?;
+
+    return Video::from_row(&row);
+}
+
+pub async fn delete_video(id: i64, db_conn: &Connection) -> Result<u64, AppError> {
+    return Delete::from(&VIDEOS)
+        .filter("id = ?", vec![DBV::Integer(id)])
+        .build()?
+        .execute(db_conn)
+        .await;
+}
+
+pub async fn get_videos_by_profile_id(
+    profile_id: i64,
+    db_conn: &Connection,
+) -> Result<Vec<Video>, AppError> {
+    let mut rows = Select::from("SELECT * FROM videos")
+        .filter("profile_id = ?", vec![DBV::Integer(profile_id)])
+        .order_by("id ASC")
+        .build()?
+        .get_many(db_conn)
+        .await?;
+    let mut videos = Vec::new();
+
+    while let Some(row) = rows.next().internal("reading videos")? {
+        videos.push(Video::from_row(&row)?);
+    }
+
+    return Ok(videos);
+}
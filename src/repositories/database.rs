@@ -0,0 +1,282 @@
+use async_trait::async_trait;
+use libsql::Connection;
+use uuid::Uuid;
+
+use super::{ProfileRepository, RegistrationOtpRepository, SessionRepository, UserRepository};
+use crate::{
+    models::{
+        audit_log::{self, AuditLogParams},
+        profile::{self, DiscoverFilter, Profile, ProfileUpdate},
+        profile_revision::{self, ProfileRevision},
+        registration_otp::{self, RegistrationOtp, RegistrationOtpParams},
+        session::{self, Session, SessionParams},
+        user::{self, User},
+        util::{begin_transaction, TransactionSource},
+    },
+    utils::app_error::AppError,
+    views::profile::ProfileParams,
+};
+
+/// Users, profiles, pending registrations and sessions stored in the libsql
+/// database through `models`.
+pub struct LibsqlRepository {
+    pub db_conn: Connection,
+    pub transactions: TransactionSource,
+}
+
+#[async_trait]
+impl UserRepository for LibsqlRepository {
+    async fn create_user(
+        &self,
+        email: &str,
+        hashed_password: &str,
+    ) -> Result<(i64, Uuid), AppError> {
+        return user::create_user(email, hashed_password, &self.db_conn).await;
+    }
+
+    async fn create_user_with_profile(
+        &self,
+        email: &str,
+        hashed_password: &str,
+        profile: ProfileParams,
+    ) -> Result<(i64, Uuid, Profile), AppError> {
+        let tx = begin_transaction(&self.transactions).await?;
+
+        let (user_id, user_pid) = user::create_user(email, hashed_password, &tx).await?;
+        let profile = profile::create_profile(&tx, ProfileParams { user_id, ..profile }).await?;
+
+        tx.commit().await?;
+
+        return Ok((user_id, user_pid, profile));
+    }
+
+    async fn get_user_ids_by_email(&self, email: &str) -> Result<(i64, Uuid), AppError> {
+        return user::get_user_ids_by_email(email, &self.db_conn).await;
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<User, AppError> {
+        return user::get_user_by_email(email, &self.db_conn).await;
+    }
+
+    async fn get_user_by_id(&self, id: i64) -> Result<User, AppError> {
+        return user::get_user_by_id(id, &self.db_conn).await;
+    }
+
+    async fn get_user_by_pid(&self, pid: &str) -> Result<User, AppError> {
+        return user::get_user_by_pid(pid, &self.db_conn).await;
+    }
+
+    async fn search_users_by_email(
+        &self,
+        email_query: &str,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<User>, AppError> {
+        return user::search_users_by_email(email_query, after_id, limit, &self.db_conn).await;
+    }
+
+    async fn delete_user(&self, user_id: i64) -> Result<u64, AppError> {
+        let tx = begin_transaction(&self.transactions).await?;
+
+        let email = user::get_user_by_id(user_id, &tx).await?.email;
+        let deleted = user::delete_user(user_id, &tx).await?;
+
+        if let Some(email) = email {
+            registration_otp::delete_registration_otp_by_email(&email, &tx).await?;
+        }
+
+        tx.commit().await?;
+
+        return Ok(deleted);
+    }
+}
+
+#[async_trait]
+impl ProfileRepository for LibsqlRepository {
+    async fn create_profile(&self, params: ProfileParams) -> Result<Profile, AppError> {
+        return profile::create_profile(&self.db_conn, params).await;
+    }
+
+    async fn get_profile_id_by_user_id(&self, user_id: i64) -> Result<i64, AppError> {
+        return profile::get_profile_id_by_user_id(user_id, &self.db_conn).await;
+    }
+
+    async fn get_profile_by_user_id(&self, user_id: i64) -> Result<Profile, AppError> {
+        return profile::get_profile_by_user_id(user_id, &self.db_conn).await;
+    }
+
+    async fn get_profile_by_pid(&self, pid: &Uuid) -> Result<Profile, AppError> {
+        return profile::get_profile_by_pid_vec(pid.as_bytes().to_vec(), &self.db_conn).await;
+    }
+
+    async fn get_profiles_by_user_ids(&self, user_ids: &[i64]) -> Result<Vec<Profile>, AppError> {
+        return profile::get_profiles_by_user_ids(user_ids, &self.db_conn).await;
+    }
+
+    async fn get_user_ids_by_location(
+        &self,
+        filter: &DiscoverFilter,
+    ) -> Result<Vec<i64>, AppError> {
+        return profile::get_user_ids_by_location(filter, &self.db_conn).await;
+    }
+
+    async fn update_profile(
+        &self,
+        user_id: i64,
+        update: &ProfileUpdate,
+        editor_user_id: i64,
+        audit: Option<AuditLogParams>,
+    ) -> Result<Profile, AppError> {
+        if update.is_empty() {
+            return profile::get_profile_by_user_id(user_id, &self.db_conn).await;
+        }
+
+        let tx = begin_transaction(&self.transactions).await?;
+
+        let profile =
+            profile_revision::update_profile_with_revisions(user_id, update, editor_user_id, &tx)
+                .await?;
+
+        if let Some(audit) = audit {
+            let audit = AuditLogParams {
+                target_profile_id: profile.id,
+                ..audit
+            };
+            audit_log::create_audit_log(&tx, audit).await?;
+        }
+
+        tx.commit().await?;
+
+        return Ok(profile);
+    }
+
+    async fn get_profile_revisions(
+        &self,
+        profile_id: i64,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<ProfileRevision>, AppError> {
+        return profile_revision::get_profile_revisions(
+            profile_id,
+            before_id,
+            limit,
+            &self.db_conn,
+        )
+        .await;
+    }
+}
+
+#[async_trait]
+impl RegistrationOtpRepository for LibsqlRepository {
+    async fn upsert_registration_otp(
+        &self,
+        params: RegistrationOtpParams,
+    ) -> Result<RegistrationOtp, AppError> {
+        return registration_otp::upsert_registration_otp(&self.db_conn, params).await;
+    }
+
+    async fn get_registration_otp_by_email(
+        &self,
+        email: &str,
+    ) -> Result<RegistrationOtp, AppError> {
+        return registration_otp::get_registration_otp_by_email(email, &self.db_conn).await;
+    }
+
+    async fn increment_registration_otp_attempts(
+        &self,
+        id: i64,
+        max_attempts: i64,
+    ) -> Result<bool, AppError> {
+        return registration_otp::increment_registration_otp_attempts(
+            id,
+            max_attempts,
+            &self.db_conn,
+        )
+        .await;
+    }
+
+    async fn delete_registration_otp(&self, id: i64) -> Result<u64, AppError> {
+        return registration_otp::delete_registration_otp(id, &self.db_conn).await;
+    }
+
+    async fn complete_registration(
+        &self,
+        id: i64,
+        email: &str,
+        hashed_password: &str,
+        profile: ProfileParams,
+    ) -> Result<(i64, Uuid, Profile), AppError> {
+        let tx = begin_transaction(&self.transactions).await?;
+
+        let (user_id, user_pid) = match user::get_user_ids_by_email(email, &tx).await {
+            Ok((user_id, user_pid)) => match profile::get_profile_id_by_user_id(user_id, &tx).await
+            {
+                Ok(_) => return Err(AppError::UserAlreadyExist),
+                Err(AppError::NotFound) => (user_id, user_pid),
+                Err(err) => return Err(err),
+            },
+            Err(AppError::UserDoesNotExist) => {
+                user::create_user(email, hashed_password, &tx).await?
+            }
+            Err(err) => return Err(err),
+        };
+
+        let profile = profile::create_profile(&tx, ProfileParams { user_id, ..profile }).await?;
+        registration_otp::delete_registration_otp(id, &tx).await?;
+
+        tx.commit().await?;
+
+        return Ok((user_id, user_pid, profile));
+    }
+}
+
+#[async_trait]
+impl SessionRepository for LibsqlRepository {
+    async fn create_session(&self, params: SessionParams) -> Result<Session, AppError> {
+        return session::create_session(&self.db_conn, params).await;
+    }
+
+    async fn get_session_by_pid(&self, pid: &Uuid) -> Result<Session, AppError> {
+        return session::get_session_by_pid(pid, &self.db_conn).await;
+    }
+
+    async fn get_session_by_refresh_token_hash(
+        &self,
+        refresh_token_hash: &str,
+    ) -> Result<Session, AppError> {
+        return session::get_session_by_refresh_token_hash(refresh_token_hash, &self.db_conn).await;
+    }
+
+    async fn get_session_id_by_rotated_token_hash(
+        &self,
+        refresh_token_hash: &str,
+    ) -> Result<i64, AppError> {
+        return session::get_session_id_by_rotated_token_hash(refresh_token_hash, &self.db_conn)
+            .await;
+    }
+
+    async fn rotate_session_refresh_token(
+        &self,
+        session_id: i64,
+        old_hash: &str,
+        new_hash: &str,
+        ip: &Option<String>,
+    ) -> Result<bool, AppError> {
+        let tx = begin_transaction(&self.transactions).await?;
+
+        let is_rotated =
+            session::rotate_session_refresh_token(session_id, old_hash, new_hash, ip, &tx).await?;
+
+        tx.commit().await?;
+
+        return Ok(is_rotated);
+    }
+
+    async fn get_active_sessions_by_user_id(&self, user_id: i64) -> Result<Vec<Session>, AppError> {
+        return session::get_active_sessions_by_user_id(user_id, &self.db_conn).await;
+    }
+
+    async fn revoke_session(&self, session_id: i64) -> Result<u64, AppError> {
+        return session::revoke_session(session_id, &self.db_conn).await;
+    }
+}
@@ -0,0 +1,713 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use super::{ProfileRepository, RegistrationOtpRepository, SessionRepository, UserRepository};
+use crate::{
+    models::{
+        audit_log::{AuditLog, AuditLogParams},
+        profile::{DiscoverFilter, Profile, ProfileUpdate},
+        profile_revision::{get_profile_changes, ProfileRevision},
+        registration_otp::{RegistrationOtp, RegistrationOtpParams},
+        session::{Session, SessionParams},
+        user::{Role, User},
+    },
+    utils::app_error::{AppError, ResultExt},
+    views::profile::ProfileParams,
+};
+
+#[derive(Default)]
+struct Tables {
+    users: Vec<User>,
+    profiles: Vec<Profile>,
+    profile_revisions: Vec<ProfileRevision>,
+    audit_logs: Vec<AuditLog>,
+    registration_otps: Vec<RegistrationOtp>,
+    sessions: Vec<Session>,
+    // Token hash and session id, like the rotated_refresh_tokens table
+    rotated_refresh_tokens: Vec<(String, i64)>,
+    last_user_id: i64,
+    last_profile_id: i64,
+    last_profile_revision_id: i64,
+    last_audit_log_id: i64,
+    last_registration_otp_id: i64,
+    last_session_id: i64,
+}
+
+/// Users, profiles, pending registrations and sessions kept in process, for
+/// running handlers without a database. Mirrors the constraints and defaults of the SQL schema that
+/// handlers rely on.
+#[derive(Default)]
+pub struct MemoryRepository {
+    tables: Mutex<Tables>,
+}
+
+impl MemoryRepository {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+}
+
+fn find_user(
+    tables: &mut Tables,
+    predicate: impl Fn(&User) -> bool,
+) -> Result<&mut User, AppError> {
+    return tables
+        .users
+        .iter_mut()
+        .find(|user| predicate(user))
+        .ok_or(AppError::UserDoesNotExist);
+}
+
+fn find_profile(
+    tables: &mut Tables,
+    predicate: impl Fn(&Profile) -> bool,
+) -> Result<&mut Profile, AppError> {
+    return tables
+        .profiles
+        .iter_mut()
+        .find(|profile| predicate(profile))
+        .ok_or(AppError::NotFound);
+}
+
+#[async_trait]
+impl UserRepository for MemoryRepository {
+    async fn create_user(
+        &self,
+        email: &str,
+        hashed_password: &str,
+    ) -> Result<(i64, Uuid), AppError> {
+        let mut tables = self.tables.lock().await;
+
+        if tables
+            .users
+            .iter()
+            .any(|user| user.email.as_deref() == Some(email))
+        {
+            return Err(AppError::UserAlreadyExist);
+        }
+
+        tables.last_user_id += 1;
+
+        let id = tables.last_user_id;
+        let pid = Uuid::new_v4();
+
+        tables.users.push(User {
+            id: Some(id),
+            pid: Some(pid.as_bytes().to_vec()),
+            email: Some(email.to_owned()),
+            password: Some(hashed_password.to_owned()),
+            role: Some(Role::User.as_str().to_string()),
+            suspended_at: None,
+            created_at: Some(Utc::now().timestamp()),
+            updated_at: None,
+        });
+
+        return Ok((id, pid));
+    }
+
+    async fn create_user_with_profile(
+        &self,
+        email: &str,
+        hashed_password: &str,
+        profile: ProfileParams,
+    ) -> Result<(i64, Uuid, Profile), AppError> {
+        let (user_id, user_pid) = self.create_user(email, hashed_password).await?;
+
+        match self
+            .create_profile(ProfileParams { user_id, ..profile })
+            .await
+        {
+            Ok(profile) => return Ok((user_id, user_pid, profile)),
+            Err(err) => {
+                let mut tables = self.tables.lock().await;
+                tables.users.retain(|user| user.id != Some(user_id));
+
+                return Err(err);
+            }
+        }
+    }
+
+    async fn get_user_ids_by_email(&self, email: &str) -> Result<(i64, Uuid), AppError> {
+        let user = self.get_user_by_email(email).await?;
+        let id = user.id.ok_or(AppError::NotFound)?;
+        let pid = user.pid.ok_or(AppError::NotFound)?;
+
+        let uuid = Uuid::from_slice(pid.as_slice()).internal("reading a user pid")?;
+
+        return Ok((id, uuid));
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<User, AppError> {
+        let mut tables = self.tables.lock().await;
+        let user = find_user(&mut tables, |user| user.email.as_deref() == Some(email))?;
+
+        return Ok(user.to_owned());
+    }
+
+    async fn get_user_by_id(&self, id: i64) -> Result<User, AppError> {
+        let mut tables = self.tables.lock().await;
+        let user = find_user(&mut tables, |user| user.id == Some(id))?;
+
+        return Ok(user.to_owned());
+    }
+
+    async fn get_user_by_pid(&self, pid: &str) -> Result<User, AppError> {
+        let pid = Uuid::try_parse(pid)
+            .internal("parsing a user pid")?
+            .as_bytes()
+            .to_vec();
+
+        let mut tables = self.tables.lock().await;
+        let user = find_user(&mut tables, |user| user.pid.as_ref() == Some(&pid))?;
+
+        return Ok(user.to_owned());
+    }
+
+    async fn search_users_by_email(
+        &self,
+        email_query: &str,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<User>, AppError> {
+        // LIKE is case insensitive for ASCII in SQLite
+        let email_query = email_query.to_ascii_lowercase();
+        let tables = self.tables.lock().await;
+
+        let mut users: Vec<User> = tables
+            .users
+            .iter()
+            .filter(|user| user.id.unwrap_or(0) > after_id)
+            .filter(|user| {
+                user.email
+                    .as_ref()
+                    .is_some_and(|email| email.to_ascii_lowercase().contains(&email_query))
+            })
+            .cloned()
+            .collect();
+
+        users.sort_by_key(|user| user.id);
+        users.truncate(limit.max(0) as usize);
+
+        return Ok(users);
+    }
+
+    async fn delete_user(&self, user_id: i64) -> Result<u64, AppError> {
+        let mut tables = self.tables.lock().await;
+        let email = find_user(&mut tables, |user| user.id == Some(user_id))?
+            .email
+            .to_owned();
+        let len_before = tables.users.len();
+
+        tables.users.retain(|user| user.id != Some(user_id));
+        tables
+            .registration_otps
+            .retain(|otp| email.is_none() || otp.email != email);
+
+        // Same as the ON DELETE CASCADE of the profiles and sessions tables
+        if tables.users.len() < len_before {
+            tables
+                .sessions
+                .retain(|session| session.user_id != Some(user_id));
+
+            let profile_ids: Vec<Option<i64>> = tables
+                .profiles
+                .iter()
+                .filter(|profile| profile.user_id == Some(user_id))
+                .map(|profile| profile.id)
+                .collect();
+
+            tables
+                .profiles
+                .retain(|profile| profile.user_id != Some(user_id));
+            tables
+                .profile_revisions
+                .retain(|revision| !profile_ids.contains(&revision.profile_id));
+        }
+
+        return Ok((len_before - tables.users.len()) as u64);
+    }
+}
+
+#[async_trait]
+impl ProfileRepository for MemoryRepository {
+    async fn create_profile(&self, params: ProfileParams) -> Result<Profile, AppError> {
+        let mut tables = self.tables.lock().await;
+        let user_id = params.user_id;
+
+        // Same as the foreign key and UNIQUE constraint on profiles.user_id
+        if find_user(&mut tables, |user| user.id == Some(user_id)).is_err()
+            || find_profile(&mut tables, |profile| profile.user_id == Some(user_id)).is_ok()
+        {
+            return Err(AppError::InternalServerError);
+        }
+
+        tables.last_profile_id += 1;
+
+        let profile = Profile {
+            id: Some(tables.last_profile_id),
+            pid: Some(Uuid::new_v4().as_bytes().to_vec()),
+            user_id: Some(user_id),
+            birth_date: Some(params.birth_date),
+            first_name: Some(params.first_name),
+            last_name: Some(params.last_name),
+            profile_video_id: None,
+            video_path: None,
+            location: Some(params.location),
+            is_visible: Some(params.is_visible),
+            is_hidden_by_moderation: Some(false),
+            created_at: Some(Utc::now().timestamp()),
+            updated_at: None,
+        };
+
+        tables.profiles.push(profile.to_owned());
+
+        return Ok(profile);
+    }
+
+    async fn get_profile_id_by_user_id(&self, user_id: i64) -> Result<i64, AppError> {
+        let profile = self.get_profile_by_user_id(user_id).await?;
+
+        return profile.id.ok_or(AppError::InternalServerError);
+    }
+
+    async fn get_profile_by_user_id(&self, user_id: i64) -> Result<Profile, AppError> {
+        let mut tables = self.tables.lock().await;
+        let profile = find_profile(&mut tables, |profile| profile.user_id == Some(user_id))?;
+
+        return Ok(profile.to_owned());
+    }
+
+    async fn get_profile_by_pid(&self, pid: &Uuid) -> Result<Profile, AppError> {
+        let pid = pid.as_bytes().to_vec();
+
+        let mut tables = self.tables.lock().await;
+        let profile = find_profile(&mut tables, |profile| profile.pid.as_ref() == Some(&pid))?;
+
+        return Ok(profile.to_owned());
+    }
+
+    async fn get_profiles_by_user_ids(&self, user_ids: &[i64]) -> Result<Vec<Profile>, AppError> {
+        let tables = self.tables.lock().await;
+
+        return Ok(tables
+            .profiles
+            .iter()
+            .filter(|profile| {
+                profile
+                    .user_id
+                    .is_some_and(|user_id| user_ids.contains(&user_id))
+            })
+            .cloned()
+            .collect());
+    }
+
+    // Likes and blocks aren't kept here, so nobody is left out for them
+    async fn get_user_ids_by_location(
+        &self,
+        filter: &DiscoverFilter,
+    ) -> Result<Vec<i64>, AppError> {
+        let tables = self.tables.lock().await;
+        let is_suspended = |user_id: Option<i64>| {
+            tables
+                .users
+                .iter()
+                .any(|user| user.id == user_id && user.suspended_at.is_some())
+        };
+        let birth_date_range =
+            filter.min_birth_date.unwrap_or(i64::MIN)..=filter.max_birth_date.unwrap_or(i64::MAX);
+
+        let mut profiles: Vec<&Profile> = tables
+            .profiles
+            .iter()
+            .filter(|profile| profile.location.as_ref() == Some(&filter.location))
+            .filter(|profile| profile.is_visible == Some(true))
+            .filter(|profile| profile.is_hidden_by_moderation != Some(true))
+            .filter(|profile| !is_suspended(profile.user_id))
+            .filter(|profile| {
+                profile
+                    .id
+                    .is_some_and(|id| id > filter.after_id && id != filter.exclude_profile_id)
+            })
+            .filter(|profile| {
+                profile
+                    .birth_date
+                    .is_some_and(|birth_date| birth_date_range.contains(&birth_date))
+            })
+            .collect();
+
+        profiles.sort_by_key(|profile| profile.id);
+        profiles.truncate(filter.limit.max(0) as usize);
+
+        return Ok(profiles
+            .into_iter()
+            .filter_map(|profile| profile.user_id)
+            .collect());
+    }
+
+    async fn update_profile(
+        &self,
+        user_id: i64,
+        update: &ProfileUpdate,
+        editor_user_id: i64,
+        audit: Option<AuditLogParams>,
+    ) -> Result<Profile, AppError> {
+        let mut tables = self.tables.lock().await;
+        let profile = find_profile(&mut tables, |profile| profile.user_id == Some(user_id))?;
+        let before = profile.to_owned();
+
+        if update.is_empty() {
+            return Ok(before);
+        }
+
+        if let Some(first_name) = &update.first_name {
+            profile.first_name = Some(first_name.to_owned());
+        }
+
+        if let Some(last_name) = &update.last_name {
+            profile.last_name = Some(last_name.to_owned());
+        }
+
+        if let Some(birth_date) = update.birth_date {
+            profile.birth_date = Some(birth_date);
+        }
+
+        if let Some(location) = &update.location {
+            profile.location = location.to_owned();
+        }
+
+        if let Some(is_visible) = update.is_visible {
+            profile.is_visible = Some(is_visible);
+        }
+
+        if let Some(is_hidden_by_moderation) = update.is_hidden_by_moderation {
+            profile.is_hidden_by_moderation = Some(is_hidden_by_moderation);
+        }
+
+        if let Some(profile_video_id) = update.profile_video_id {
+            profile.profile_video_id = profile_video_id;
+        }
+
+        if let Some(video_path) = &update.video_path {
+            profile.video_path = video_path.to_owned();
+        }
+
+        profile.updated_at = Some(Utc::now().timestamp());
+
+        let after = profile.to_owned();
+        let editor_user_pid = find_user(&mut tables, |user| user.id == Some(editor_user_id))
+            .ok()
+            .and_then(|user| user.pid.to_owned());
+
+        for change in get_profile_changes(&before, &after) {
+            tables.last_profile_revision_id += 1;
+
+            // Stored as JSON text like the profile_revisions table does
+            let to_text = |value: serde_json::Value| (!value.is_null()).then(|| value.to_string());
+
+            let revision = ProfileRevision {
+                id: Some(tables.last_profile_revision_id),
+                profile_id: after.id,
+                editor_user_id: Some(editor_user_id),
+                field: Some(change.field.to_string()),
+                old_value: to_text(change.old_value),
+                new_value: to_text(change.new_value),
+                created_at: after.updated_at,
+                editor_user_pid: editor_user_pid.to_owned(),
+            };
+
+            tables.profile_revisions.push(revision);
+        }
+
+        if let Some(audit) = audit {
+            tables.last_audit_log_id += 1;
+
+            let audit_log = AuditLog {
+                id: Some(tables.last_audit_log_id),
+                actor_user_id: audit.actor_user_id,
+                action: Some(audit.action.to_string()),
+                target_user_id: audit.target_user_id,
+                target_profile_id: after.id,
+                details: audit.details.map(|details| details.to_string()),
+                created_at: after.updated_at,
+                actor_user_pid: None,
+                target_user_pid: None,
+            };
+
+            tables.audit_logs.push(audit_log);
+        }
+
+        return Ok(after);
+    }
+
+    async fn get_profile_revisions(
+        &self,
+        profile_id: i64,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<ProfileRevision>, AppError> {
+        let tables = self.tables.lock().await;
+        let before_id = before_id.unwrap_or(i64::MAX);
+
+        let mut revisions: Vec<ProfileRevision> = tables
+            .profile_revisions
+            .iter()
+            .filter(|revision| revision.profile_id == Some(profile_id))
+            .filter(|revision| revision.id.unwrap_or(0) < before_id)
+            .cloned()
+            .collect();
+
+        revisions.sort_by_key(|revision| std::cmp::Reverse(revision.id));
+        revisions.truncate(limit.max(0) as usize);
+
+        return Ok(revisions);
+    }
+}
+
+#[async_trait]
+impl RegistrationOtpRepository for MemoryRepository {
+    async fn upsert_registration_otp(
+        &self,
+        params: RegistrationOtpParams,
+    ) -> Result<RegistrationOtp, AppError> {
+        let mut tables = self.tables.lock().await;
+
+        // An upsert keeps the id of the row it replaces
+        let id = match tables
+            .registration_otps
+            .iter()
+            .find(|otp| otp.email.as_deref() == Some(params.email.as_str()))
+        {
+            Some(otp) => otp.id,
+            None => {
+                tables.last_registration_otp_id += 1;
+                Some(tables.last_registration_otp_id)
+            }
+        };
+
+        let otp = RegistrationOtp {
+            id,
+            email: Some(params.email),
+            code_hash: Some(params.code_hash),
+            password_hash: Some(params.password_hash),
+            first_name: Some(params.first_name),
+            last_name: Some(params.last_name),
+            birth_date: Some(params.birth_date),
+            attempts: Some(0),
+            expires_at: Some(params.expires_at),
+            created_at: Some(Utc::now().timestamp()),
+        };
+
+        tables.registration_otps.retain(|other| other.id != id);
+        tables.registration_otps.push(otp.to_owned());
+
+        return Ok(otp);
+    }
+
+    async fn get_registration_otp_by_email(
+        &self,
+        email: &str,
+    ) -> Result<RegistrationOtp, AppError> {
+        let tables = self.tables.lock().await;
+
+        return tables
+            .registration_otps
+            .iter()
+            .find(|otp| otp.email.as_deref() == Some(email))
+            .cloned()
+            .ok_or(AppError::NotFound);
+    }
+
+    async fn increment_registration_otp_attempts(
+        &self,
+        id: i64,
+        max_attempts: i64,
+    ) -> Result<bool, AppError> {
+        let mut tables = self.tables.lock().await;
+
+        match tables
+            .registration_otps
+            .iter_mut()
+            .find(|otp| otp.id == Some(id) && otp.attempts.unwrap_or(0) < max_attempts)
+        {
+            Some(otp) => {
+                otp.attempts = Some(otp.attempts.unwrap_or(0) + 1);
+                return Ok(true);
+            }
+            None => return Ok(false),
+        }
+    }
+
+    async fn delete_registration_otp(&self, id: i64) -> Result<u64, AppError> {
+        let mut tables = self.tables.lock().await;
+        let len_before = tables.registration_otps.len();
+
+        tables.registration_otps.retain(|otp| otp.id != Some(id));
+
+        return Ok((len_before - tables.registration_otps.len()) as u64);
+    }
+
+    async fn complete_registration(
+        &self,
+        id: i64,
+        email: &str,
+        hashed_password: &str,
+        profile: ProfileParams,
+    ) -> Result<(i64, Uuid, Profile), AppError> {
+        let registration = match self.get_user_ids_by_email(email).await {
+            Ok((user_id, user_pid)) => match self.get_profile_id_by_user_id(user_id).await {
+                Ok(_) => return Err(AppError::UserAlreadyExist),
+                Err(AppError::NotFound) => {
+                    let profile = self
+                        .create_profile(ProfileParams { user_id, ..profile })
+                        .await?;
+
+                    (user_id, user_pid, profile)
+                }
+                Err(err) => return Err(err),
+            },
+            Err(AppError::UserDoesNotExist) => {
+                self.create_user_with_profile(email, hashed_password, profile)
+                    .await?
+            }
+            Err(err) => return Err(err),
+        };
+
+        self.delete_registration_otp(id).await?;
+
+        return Ok(registration);
+    }
+}
+
+fn find_session(
+    tables: &mut Tables,
+    predicate: impl Fn(&Session) -> bool,
+) -> Result<&mut Session, AppError> {
+    return tables
+        .sessions
+        .iter_mut()
+        .find(|session| predicate(session))
+        .ok_or(AppError::NotFound);
+}
+
+#[async_trait]
+impl SessionRepository for MemoryRepository {
+    async fn create_session(&self, params: SessionParams) -> Result<Session, AppError> {
+        let mut tables = self.tables.lock().await;
+        let now = Utc::now().timestamp();
+
+        tables.last_session_id += 1;
+
+        let session = Session {
+            id: Some(tables.last_session_id),
+            pid: Some(Uuid::new_v4().as_bytes().to_vec()),
+            user_id: Some(params.user_id),
+            refresh_token_hash: Some(params.refresh_token_hash),
+            device_label: params.device_label,
+            ip: params.ip,
+            expires_at: Some(params.expires_at),
+            last_used_at: Some(now),
+            revoked_at: None,
+            created_at: Some(now),
+        };
+
+        tables.sessions.push(session.to_owned());
+
+        return Ok(session);
+    }
+
+    async fn get_session_by_pid(&self, pid: &Uuid) -> Result<Session, AppError> {
+        let pid = pid.as_bytes().to_vec();
+
+        let mut tables = self.tables.lock().await;
+        let session = find_session(&mut tables, |session| session.pid.as_ref() == Some(&pid))?;
+
+        return Ok(session.to_owned());
+    }
+
+    async fn get_session_by_refresh_token_hash(
+        &self,
+        refresh_token_hash: &str,
+    ) -> Result<Session, AppError> {
+        let mut tables = self.tables.lock().await;
+        let session = find_session(&mut tables, |session| {
+            session.refresh_token_hash.as_deref() == Some(refresh_token_hash)
+        })?;
+
+        return Ok(session.to_owned());
+    }
+
+    async fn get_session_id_by_rotated_token_hash(
+        &self,
+        refresh_token_hash: &str,
+    ) -> Result<i64, AppError> {
+        let tables = self.tables.lock().await;
+
+        return tables
+            .rotated_refresh_tokens
+            .iter()
+            .find(|(token_hash, _)| token_hash == refresh_token_hash)
+            .map(|(_, session_id)| *session_id)
+            .ok_or(AppError::NotFound);
+    }
+
+    async fn rotate_session_refresh_token(
+        &self,
+        session_id: i64,
+        old_hash: &str,
+        new_hash: &str,
+        ip: &Option<String>,
+    ) -> Result<bool, AppError> {
+        let mut tables = self.tables.lock().await;
+        let Ok(session) = find_session(&mut tables, |session| {
+            session.id == Some(session_id)
+                && session.refresh_token_hash.as_deref() == Some(old_hash)
+                && session.revoked_at.is_none()
+        }) else {
+            return Ok(false);
+        };
+
+        session.refresh_token_hash = Some(new_hash.to_owned());
+        session.ip = ip.to_owned().or(session.ip.to_owned());
+        session.last_used_at = Some(Utc::now().timestamp());
+
+        tables
+            .rotated_refresh_tokens
+            .push((old_hash.to_owned(), session_id));
+
+        return Ok(true);
+    }
+
+    async fn get_active_sessions_by_user_id(&self, user_id: i64) -> Result<Vec<Session>, AppError> {
+        let tables = self.tables.lock().await;
+        let now = Utc::now().timestamp();
+
+        let mut sessions: Vec<Session> = tables
+            .sessions
+            .iter()
+            .filter(|session| session.user_id == Some(user_id))
+            .filter(|session| session.revoked_at.is_none())
+            .filter(|session| session.expires_at.unwrap_or(0) > now)
+            .cloned()
+            .collect();
+
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.last_used_at));
+
+        return Ok(sessions);
+    }
+
+    async fn revoke_session(&self, session_id: i64) -> Result<u64, AppError> {
+        let mut tables = self.tables.lock().await;
+
+        match find_session(&mut tables, |session| {
+            session.id == Some(session_id) && session.revoked_at.is_none()
+        }) {
+            Ok(session) => {
+                session.revoked_at = Some(Utc::now().timestamp());
+                return Ok(1);
+            }
+            Err(_) => return Ok(0),
+        }
+    }
+}
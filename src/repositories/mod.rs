@@ -0,0 +1,179 @@
+pub mod database;
+// Handlers run on it in tests, without a database
+#[cfg(test)]
+pub mod memory;
+
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::{
+    models::{
+        audit_log::AuditLogParams,
+        profile::{DiscoverFilter, Profile, ProfileUpdate},
+        profile_revision::ProfileRevision,
+        registration_otp::{RegistrationOtp, RegistrationOtpParams},
+        session::{Session, SessionParams},
+        user::User,
+    },
+    utils::app_error::AppError,
+    views::profile::ProfileParams,
+};
+
+/// Storage of user rows. Lookups of a user that doesn't exist fail with
+/// `AppError::UserDoesNotExist`.
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    //Production: The password must already be hashed, see utils::password::hash_password
+    async fn create_user(
+        &self,
+        email: &str,
+        hashed_password: &str,
+    ) -> Result<(i64, Uuid), AppError>;
+
+    /// Creates the user and their profile, or neither. `profile.user_id` is
+    /// replaced with the id of the new user.
+    async fn create_user_with_profile(
+        &self,
+        email: &str,
+        hashed_password: &str,
+        profile: ProfileParams,
+    ) -> Result<(i64, Uuid, Profile), AppError>;
+
+    async fn get_user_ids_by_email(&self, email: &str) -> Result<(i64, Uuid), AppError>;
+
+    async fn get_user_by_email(&self, email: &str) -> Result<User, AppError>;
+
+    async fn get_user_by_id(&self, id: i64) -> Result<User, AppError>;
+
+    async fn get_user_by_pid(&self, pid: &str) -> Result<User, AppError>;
+
+    /// Users whose email contains `email_query`, oldest first.
+    async fn search_users_by_email(
+        &self,
+        email_query: &str,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<User>, AppError>;
+
+    /// Deletes the user along with their profile and everything hanging off
+    /// it, and a pending registration for their email that would bring the
+    /// account back.
+    async fn delete_user(&self, user_id: i64) -> Result<u64, AppError>;
+}
+
+/// Storage of profile rows. Lookups of a profile that doesn't exist fail
+/// with `AppError::NotFound`.
+#[async_trait]
+pub trait ProfileRepository: Send + Sync {
+    async fn create_profile(&self, params: ProfileParams) -> Result<Profile, AppError>;
+
+    async fn get_profile_id_by_user_id(&self, user_id: i64) -> Result<i64, AppError>;
+
+    async fn get_profile_by_user_id(&self, user_id: i64) -> Result<Profile, AppError>;
+
+    async fn get_profile_by_pid(&self, pid: &Uuid) -> Result<Profile, AppError>;
+
+    /// Profiles of `user_ids` in no particular order, ids without a profile
+    /// are skipped.
+    async fn get_profiles_by_user_ids(&self, user_ids: &[i64]) -> Result<Vec<Profile>, AppError>;
+
+    /// Owners of the profiles `filter` lets through, ordered by profile id,
+    /// see `models::profile::get_user_ids_by_location`.
+    async fn get_user_ids_by_location(&self, filter: &DiscoverFilter)
+        -> Result<Vec<i64>, AppError>;
+
+    /// Applies `update` to the profile of `user_id` and returns the updated row.
+    /// Every field it changed is recorded as a revision by `editor_user_id`.
+    ///
+    /// `audit` is written with the update or not at all, its
+    /// `target_profile_id` is set to the updated profile. An empty update
+    /// writes nothing, not even `audit`.
+    async fn update_profile(
+        &self,
+        user_id: i64,
+        update: &ProfileUpdate,
+        editor_user_id: i64,
+        audit: Option<AuditLogParams>,
+    ) -> Result<Profile, AppError>;
+
+    /// Revisions of the profile older than `before_id`, newest first.
+    async fn get_profile_revisions(
+        &self,
+        profile_id: i64,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<ProfileRevision>, AppError>;
+}
+
+/// Storage of pending registrations. Lookups of a code that doesn't exist
+/// fail with `AppError::NotFound`.
+#[async_trait]
+pub trait RegistrationOtpRepository: Send + Sync {
+    /// Stores a pending registration, replacing any earlier code for the same email.
+    async fn upsert_registration_otp(
+        &self,
+        params: RegistrationOtpParams,
+    ) -> Result<RegistrationOtp, AppError>;
+
+    async fn get_registration_otp_by_email(&self, email: &str)
+        -> Result<RegistrationOtp, AppError>;
+
+    /// Counts a guess of the code unless `max_attempts` were already made.
+    /// Returns false when no guess is left.
+    async fn increment_registration_otp_attempts(
+        &self,
+        id: i64,
+        max_attempts: i64,
+    ) -> Result<bool, AppError>;
+
+    async fn delete_registration_otp(&self, id: i64) -> Result<u64, AppError>;
+
+    /// Creates the user and their profile and deletes the verified code, or
+    /// does none of it. A user left without a profile by an older
+    /// registration only gets the profile, a user who has one fails with
+    /// `AppError::UserAlreadyExist`.
+    async fn complete_registration(
+        &self,
+        id: i64,
+        email: &str,
+        hashed_password: &str,
+        profile: ProfileParams,
+    ) -> Result<(i64, Uuid, Profile), AppError>;
+}
+
+/// Storage of login sessions. Lookups of a session that doesn't exist fail
+/// with `AppError::NotFound`. Revoking every session of a user happens along
+/// with other writes, like a password change, so it stays in `models::session`.
+#[async_trait]
+pub trait SessionRepository: Send + Sync {
+    async fn create_session(&self, params: SessionParams) -> Result<Session, AppError>;
+
+    async fn get_session_by_pid(&self, pid: &Uuid) -> Result<Session, AppError>;
+
+    async fn get_session_by_refresh_token_hash(
+        &self,
+        refresh_token_hash: &str,
+    ) -> Result<Session, AppError>;
+
+    /// Returns the id of the session a rotated out refresh token belonged to.
+    async fn get_session_id_by_rotated_token_hash(
+        &self,
+        refresh_token_hash: &str,
+    ) -> Result<i64, AppError>;
+
+    /// Swaps the session refresh token, but only if `old_hash` is still current.
+    /// Returns false when another request rotated the token first.
+    async fn rotate_session_refresh_token(
+        &self,
+        session_id: i64,
+        old_hash: &str,
+        new_hash: &str,
+        ip: &Option<String>,
+    ) -> Result<bool, AppError>;
+
+    /// Sessions of the user that are neither revoked nor expired, most
+    /// recently used first.
+    async fn get_active_sessions_by_user_id(&self, user_id: i64) -> Result<Vec<Session>, AppError>;
+
+    async fn revoke_session(&self, session_id: i64) -> Result<u64, AppError>;
+}
@@ -0,0 +1,86 @@
+use std::{io::SeekFrom, path::PathBuf};
+
+use async_trait::async_trait;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tracing::error;
+
+use super::{VideoReader, VideoStore, VideoWriter};
+use crate::utils::app_error::{AppError, ResultExt};
+
+/// Stores videos as plain files under `root`.
+pub struct LocalVideoStore {
+    pub root: PathBuf,
+}
+
+impl LocalVideoStore {
+    fn path_for(&self, key: &str) -> Result<PathBuf, AppError> {
+        let is_safe_key = !key.is_empty()
+            && !key.contains("..")
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+
+        if !is_safe_key {
+            error!("Refusing unsafe storage key {:?}", key);
+            return Err(AppError::InternalServerError);
+        }
+
+        return Ok(self.root.join(key));
+    }
+}
+
+#[async_trait]
+impl VideoStore for LocalVideoStore {
+    async fn writer(&self, key: &str) -> Result<VideoWriter, AppError> {
+        let path = self.path_for(key)?;
+
+        tokio::fs::create_dir_all(&self.root)
+            .await
+            .internal("creating the video directory")?;
+
+        let file = tokio::fs::File::create(path)
+            .await
+            .internal("creating a video file")?;
+
+        return Ok(Box::pin(file));
+    }
+
+    async fn size(&self, key: &str) -> Result<u64, AppError> {
+        let path = self.path_for(key)?;
+
+        let metadata = tokio::fs::metadata(path).await.map_err(|err| {
+            error!("{:?}", err);
+            AppError::NotFound
+        })?;
+
+        return Ok(metadata.len());
+    }
+
+    async fn read_range(&self, key: &str, start: u64, len: u64) -> Result<VideoReader, AppError> {
+        let path = self.path_for(key)?;
+
+        let mut file = tokio::fs::File::open(path).await.map_err(|err| {
+            error!("{:?}", err);
+            AppError::NotFound
+        })?;
+
+        file.seek(SeekFrom::Start(start))
+            .await
+            .internal("seeking in a video file")?;
+
+        return Ok(Box::pin(file.take(len)));
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), AppError> {
+        let path = self.path_for(key)?;
+
+        match tokio::fs::remove_file(path).await {
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => {
+                error!("{:?}", err);
+                Err(AppError::InternalServerError)
+            }
+        }
+    }
+}
@@ -0,0 +1,26 @@
+pub mod local;
+
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::utils::app_error::AppError;
+
+pub type VideoWriter = Pin<Box<dyn AsyncWrite + Send>>;
+pub type VideoReader = Pin<Box<dyn AsyncRead + Send>>;
+
+/// Blob storage for profile videos. Keys are generated by the server and
+/// never come from user input.
+#[async_trait]
+pub trait VideoStore: Send + Sync {
+    /// Opens a writer for a new object, replacing any object with the same key.
+    async fn writer(&self, key: &str) -> Result<VideoWriter, AppError>;
+
+    async fn size(&self, key: &str) -> Result<u64, AppError>;
+
+    /// Reads `len` bytes starting at `start`.
+    async fn read_range(&self, key: &str, start: u64, len: u64) -> Result<VideoReader, AppError>;
+
+    async fn delete(&self, key: &str) -> Result<(), AppError>;
+}
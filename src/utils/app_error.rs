@@ -1,5 +1,16 @@
-use axum::{http::StatusCode, response::IntoResponse, Json};
+use std::{borrow::Cow, error::Error, fmt};
+
+use axum::{
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
 use serde_json::json;
+use tracing::error;
+
+use crate::utils::{request_id::current_request_id, validation::FieldError};
+
+pub type BoxError = Box<dyn Error + Send + Sync>;
 
 #[derive(Debug)]
 pub enum AppError {
@@ -8,27 +19,155 @@ pub enum AppError {
     MissingCredential,
     TokenCreation,
     Unauthorized,
+    /// A broken invariant with no underlying error, like a missing column.
     InternalServerError,
+    /// Something failed underneath, like the database or the file system. The
+    /// context and source are logged but never sent to the client.
+    Internal {
+        context: Cow<'static, str>,
+        source: BoxError,
+    },
     UserDoesNotExist,
     UserAlreadyExist,
     NotFound,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    Forbidden,
+    AccountSuspended,
+    TooManyRequests {
+        retry_after_secs: u64,
+    },
+    Validation(Vec<FieldError>),
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> axum::response::Response {
-        let (status, err_msg) = match self {
+impl AppError {
+    /// Wraps a failure of a dependency, `context` says what was being done.
+    pub fn internal(context: impl Into<Cow<'static, str>>, source: impl Into<BoxError>) -> Self {
+        return Self::Internal {
+            context: context.into(),
+            source: source.into(),
+        };
+    }
+
+    pub fn status_and_message(&self) -> (StatusCode, &'static str) {
+        return match self {
             Self::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid Token"),
             Self::WrongCredential => (StatusCode::NOT_ACCEPTABLE, "Wrong Credentials"),
             Self::MissingCredential => (StatusCode::NOT_ACCEPTABLE, "Missing Credentials"),
             Self::TokenCreation => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create Token"),
             Self::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized Request"),
-            Self::InternalServerError => {
+            Self::InternalServerError | Self::Internal { .. } => {
                 (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error")
             }
             Self::UserDoesNotExist => (StatusCode::NOT_FOUND, "User does not Exist"),
             Self::UserAlreadyExist => (StatusCode::CONFLICT, "User already exist"),
             Self::NotFound => (StatusCode::NOT_FOUND, "Could not find resource"),
+            Self::PayloadTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, "Payload too large"),
+            Self::UnsupportedMediaType => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, "Unsupported media type")
+            }
+            Self::Forbidden => (StatusCode::FORBIDDEN, "Forbidden"),
+            Self::AccountSuspended => (StatusCode::FORBIDDEN, "Account suspended"),
+            Self::TooManyRequests { .. } => (StatusCode::TOO_MANY_REQUESTS, "Too many requests"),
+            Self::Validation(_) => (StatusCode::UNPROCESSABLE_ENTITY, "Validation failed"),
         };
-        return (status, Json(json!({ "error": err_msg}))).into_response();
+    }
+
+    /// A stable, machine readable code. Clients should branch on this rather
+    /// than on the message.
+    pub fn code(&self) -> &'static str {
+        return match self {
+            Self::InvalidToken => "invalid_token",
+            Self::WrongCredential => "wrong_credential",
+            Self::MissingCredential => "missing_credential",
+            Self::TokenCreation => "token_creation_failed",
+            Self::Unauthorized => "unauthorized",
+            Self::InternalServerError | Self::Internal { .. } => "internal_error",
+            Self::UserDoesNotExist => "user_not_found",
+            Self::UserAlreadyExist => "user_already_exists",
+            Self::NotFound => "not_found",
+            Self::PayloadTooLarge => "payload_too_large",
+            Self::UnsupportedMediaType => "unsupported_media_type",
+            Self::Forbidden => "forbidden",
+            Self::AccountSuspended => "account_suspended",
+            Self::TooManyRequests { .. } => "rate_limited",
+            Self::Validation(_) => "validation_failed",
+        };
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Self::Internal { context, .. } => write!(f, "Failed {}", context),
+            _ => write!(f, "{}", self.status_and_message().1),
+        };
+    }
+}
+
+impl Error for AppError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        return match self {
+            Self::Internal { source, .. } => Some(source.as_ref()),
+            _ => None,
+        };
+    }
+}
+
+/// Formats the error followed by every error in its source chain.
+pub fn error_chain(err: &dyn Error) -> String {
+    let mut chain = err.to_string();
+    let mut source = err.source();
+
+    while let Some(err) = source {
+        chain.push_str(": ");
+        chain.push_str(&err.to_string());
+        source = err.source();
+    }
+
+    return chain;
+}
+
+pub trait ResultExt<T> {
+    /// Turns the error into `AppError::Internal`, `context` says what was being done.
+    fn internal(self, context: impl Into<Cow<'static, str>>) -> Result<T, AppError>;
+}
+
+impl<T, E: Into<BoxError>> ResultExt<T> for Result<T, E> {
+    fn internal(self, context: impl Into<Cow<'static, str>>) -> Result<T, AppError> {
+        return self.map_err(|err| AppError::internal(context, err));
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> axum::response::Response {
+        let (status, err_msg) = self.status_and_message();
+        let request_id = current_request_id();
+
+        // Logged here once, inside the request span, instead of at every call site
+        if status.is_server_error() {
+            error!(code = self.code(), "{}", error_chain(&self));
+        }
+
+        // Field errors are listed next to the usual message
+        let body = match &self {
+            Self::Validation(fields) => json!({
+                "error": err_msg,
+                "code": self.code(),
+                "request_id": request_id,
+                "fields": fields,
+            }),
+            _ => json!({ "error": err_msg, "code": self.code(), "request_id": request_id }),
+        };
+
+        let mut response = (status, Json(body)).into_response();
+
+        if let Self::TooManyRequests { retry_after_secs } = self {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, retry_after_secs.into());
+        }
+
+        return response;
     }
 }
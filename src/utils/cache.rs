@@ -0,0 +1,313 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    hash::{BuildHasher, Hash},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+// Requests for different keys rarely wait on each other with this many shards
+const SHARD_COUNT: usize = 16;
+
+struct CacheEntry<V> {
+    value: V,
+    expires_at: Instant,
+    last_used: u64,
+}
+
+type Shard<K, V> = HashMap<K, CacheEntry<V>>;
+
+/// A bounded in-process cache. Entries expire `ttl` after they were inserted
+/// and the least recently used entry of a shard is evicted once it is full.
+///
+/// Locks are plain mutexes held only for a map operation, never across an
+/// `.await`, so a lookup can't stall behind a slow request.
+pub struct Cache<K, V> {
+    name: &'static str,
+    shards: Vec<Mutex<Shard<K, V>>>,
+    shard_capacity: usize,
+    ttl: Duration,
+    hasher: RandomState,
+    clock: AtomicU64,
+    metrics: CacheMetrics,
+}
+
+#[derive(Default)]
+struct CacheMetrics {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    pub name: &'static str,
+    pub entries: usize,
+    pub capacity: usize,
+    pub ttl_secs: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub expirations: u64,
+    pub invalidations: u64,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Cache<K, V> {
+    pub fn new(name: &'static str, max_entries: usize, ttl: Duration) -> Self {
+        let shard_count = SHARD_COUNT.min(max_entries.max(1));
+
+        return Self {
+            name,
+            shards: (0..shard_count)
+                .map(|_| Mutex::new(HashMap::new()))
+                .collect(),
+            shard_capacity: max_entries.max(1).div_ceil(shard_count),
+            ttl,
+            hasher: RandomState::new(),
+            clock: AtomicU64::new(0),
+            metrics: CacheMetrics::default(),
+        };
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let now = Instant::now();
+        let mut shard = self.shard(key);
+
+        let is_expired = match shard.get_mut(key) {
+            Some(entry) if entry.expires_at > now => {
+                entry.last_used = self.tick();
+                self.metrics.hits.fetch_add(1, Ordering::Relaxed);
+
+                return Some(entry.value.clone());
+            }
+            Some(_) => true,
+            None => false,
+        };
+
+        if is_expired {
+            shard.remove(key);
+            self.metrics.expirations.fetch_add(1, Ordering::Relaxed);
+        }
+
+        self.metrics.misses.fetch_add(1, Ordering::Relaxed);
+
+        return None;
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        let now = Instant::now();
+        let mut shard = self.shard(&key);
+
+        if !shard.contains_key(&key) && shard.len() >= self.shard_capacity {
+            self.make_room(&mut shard, now);
+        }
+
+        shard.insert(
+            key,
+            CacheEntry {
+                value,
+                expires_at: now + self.ttl,
+                last_used: self.tick(),
+            },
+        );
+    }
+
+    /// Drops the entry of `key`, returns whether there was one.
+    pub fn invalidate(&self, key: &K) -> bool {
+        let is_removed = self.shard(key).remove(key).is_some();
+
+        if is_removed {
+            self.metrics.invalidations.fetch_add(1, Ordering::Relaxed);
+        }
+
+        return is_removed;
+    }
+
+    /// Drops every entry `predicate` matches. Every shard is scanned, so this is
+    /// meant for rare events like signing a user out everywhere.
+    pub fn invalidate_where(&self, predicate: impl Fn(&K, &V) -> bool) -> u64 {
+        let mut removed = 0;
+
+        for shard in &self.shards {
+            let mut shard = lock(shard);
+            let len_before = shard.len();
+
+            shard.retain(|key, entry| !predicate(key, &entry.value));
+            removed += (len_before - shard.len()) as u64;
+        }
+
+        self.metrics
+            .invalidations
+            .fetch_add(removed, Ordering::Relaxed);
+
+        return removed;
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let metrics = &self.metrics;
+
+        return CacheStats {
+            name: self.name,
+            entries: self.shards.iter().map(|shard| lock(shard).len()).sum(),
+            capacity: self.shard_capacity * self.shards.len(),
+            ttl_secs: self.ttl.as_secs(),
+            hits: metrics.hits.load(Ordering::Relaxed),
+            misses: metrics.misses.load(Ordering::Relaxed),
+            evictions: metrics.evictions.load(Ordering::Relaxed),
+            expirations: metrics.expirations.load(Ordering::Relaxed),
+            invalidations: metrics.invalidations.load(Ordering::Relaxed),
+        };
+    }
+
+    fn shard(&self, key: &K) -> MutexGuard<'_, Shard<K, V>> {
+        let hash = self.hasher.hash_one(key);
+
+        return lock(&self.shards[hash as usize % self.shards.len()]);
+    }
+
+    fn tick(&self) -> u64 {
+        return self.clock.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Drops the expired entries of a full shard, or its least recently used
+    /// entry when none has expired. Shards are small, so a scan is cheap enough.
+    fn make_room(&self, shard: &mut Shard<K, V>, now: Instant) {
+        let len_before = shard.len();
+        shard.retain(|_, entry| entry.expires_at > now);
+
+        let expired = (len_before - shard.len()) as u64;
+
+        if expired > 0 {
+            self.metrics
+                .expirations
+                .fetch_add(expired, Ordering::Relaxed);
+            return;
+        }
+
+        let least_recently_used = shard
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(key, _)| key.clone());
+
+        if let Some(key) = least_recently_used {
+            shard.remove(&key);
+            self.metrics.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// A panic while holding a shard leaves a map that is still usable, so a
+// poisoned lock is recovered instead of failing every later request
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    return mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+
+    use super::*;
+
+    // One shard, so which entry gets evicted doesn't depend on the hashes
+    fn single_shard_cache(capacity: usize, ttl: Duration) -> Cache<&'static str, i32> {
+        return Cache {
+            name: "test",
+            shards: vec![Mutex::new(HashMap::new())],
+            shard_capacity: capacity,
+            ttl,
+            hasher: RandomState::new(),
+            clock: AtomicU64::new(0),
+            metrics: CacheMetrics::default(),
+        };
+    }
+
+    #[test]
+    fn get_returns_what_was_inserted() {
+        let cache = Cache::new("test", 100, Duration::from_secs(60));
+        cache.insert("a", 1);
+        cache.insert("a", 2);
+
+        assert_eq!(cache.get(&"a"), Some(2));
+        assert_eq!(cache.get(&"b"), None);
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (1, 1, 1));
+    }
+
+    #[test]
+    fn entries_expire_after_the_ttl() {
+        let cache = Cache::new("test", 100, Duration::from_millis(20));
+        cache.insert("a", 1);
+        sleep(Duration::from_millis(30));
+
+        assert_eq!(cache.get(&"a"), None);
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.expirations), (0, 1));
+    }
+
+    #[test]
+    fn a_full_shard_evicts_the_least_recently_used_entry() {
+        let cache = single_shard_cache(2, Duration::from_secs(60));
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+
+        // Reading "a" makes "b" the least recently used
+        cache.get(&"a");
+        cache.insert("c", 3);
+
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"c"), Some(3));
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn a_full_shard_drops_expired_entries_first() {
+        let cache = single_shard_cache(2, Duration::from_millis(20));
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        sleep(Duration::from_millis(30));
+        cache.insert("c", 3);
+
+        let stats = cache.stats();
+        assert_eq!(
+            (stats.entries, stats.expirations, stats.evictions),
+            (1, 2, 0)
+        );
+    }
+
+    #[test]
+    fn replacing_an_entry_of_a_full_shard_evicts_nothing() {
+        let cache = single_shard_cache(2, Duration::from_secs(60));
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("a", 3);
+
+        assert_eq!(cache.get(&"a"), Some(3));
+        assert_eq!(cache.get(&"b"), Some(2));
+    }
+
+    #[test]
+    fn invalidate_drops_matching_entries() {
+        let cache = Cache::new("test", 100, Duration::from_secs(60));
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("c", 3);
+
+        assert!(cache.invalidate(&"a"));
+        assert!(!cache.invalidate(&"a"));
+        assert_eq!(cache.invalidate_where(|_, value| *value > 2), 1);
+
+        assert_eq!(cache.get(&"b"), Some(2));
+        assert_eq!(cache.stats().invalidations, 2);
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRef, FromRequestParts},
+    http::{header::USER_AGENT, request::Parts},
+};
+
+use crate::{app_state::AppState, config::IpRange};
+
+const FLY_CLIENT_IP: &str = "Fly-Client-IP";
+const X_FORWARDED_FOR: &str = "X-Forwarded-For";
+
+/// Proxies whose forwarding headers name the real client, from the
+/// `trusted_proxies` setting.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(pub Vec<IpRange>);
+
+impl TrustedProxies {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        return self.0.iter().any(|range| range.contains(ip));
+    }
+}
+
+impl FromRef<AppState> for TrustedProxies {
+    fn from_ref(app_state: &AppState) -> Self {
+        return Self(app_state.config.trusted_proxies.clone());
+    }
+}
+
+/// Best effort description of the client making the request.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    S: Send + Sync,
+    TrustedProxies: FromRef<S>,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let header_value = |name: &str| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        let peer_ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_canonical());
+
+        // INFO: Anyone can send forwarding headers, they only name the client when
+        // the connection comes from a trusted proxy. Fly's proxy sets Fly-Client-IP,
+        // otherwise the nearest X-Forwarded-For entry that isn't a trusted proxy.
+        let trusted_proxies = TrustedProxies::from_ref(state);
+        let forwarded_ip = match peer_ip {
+            Some(peer_ip) if trusted_proxies.contains(peer_ip) => header_value(FLY_CLIENT_IP)
+                .or_else(|| {
+                    header_value(X_FORWARDED_FOR).and_then(|value| {
+                        value
+                            .rsplit(',')
+                            .map(str::trim)
+                            .find(|ip| match ip.parse::<IpAddr>() {
+                                Ok(ip) => !trusted_proxies.contains(ip),
+                                Err(_) => true,
+                            })
+                            .map(str::to_string)
+                    })
+                }),
+            _ => None,
+        };
+
+        let ip = forwarded_ip.or_else(|| peer_ip.map(|ip| ip.to_string()));
+
+        let user_agent =
+            header_value(USER_AGENT.as_str()).map(|value| value.chars().take(255).collect());
+
+        return Ok(ClientInfo { ip, user_agent });
+    }
+}
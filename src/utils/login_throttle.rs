@@ -0,0 +1,291 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+
+/// What failed login attempts are counted against. An account is throttled
+/// on its own and an IP across every account it tries.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LoginThrottleKey {
+    Account(String),
+    Ip(String),
+}
+
+/// An attempt counted as failed before the password is checked, so concurrent
+/// guesses can't all slip past the limit. Released again when it succeeds.
+#[derive(Clone, Debug)]
+pub struct LoginAttempt {
+    pub key: LoginThrottleKey,
+    /// The lockout in seconds this attempt caused, if it turns out to fail.
+    pub lockout_secs: Option<u64>,
+    locked_until: Option<Instant>,
+}
+
+/// Tracks failed logins. Implementations backed by a shared store can
+/// replace the in-process one once the server runs on more than one machine.
+#[async_trait]
+pub trait LoginThrottle: Send + Sync {
+    /// Counts an attempt against the key unless it is locked out, in which
+    /// case the seconds left are returned instead.
+    async fn reserve_attempt(&self, key: &LoginThrottleKey) -> Result<LoginAttempt, u64>;
+
+    /// Takes back a reserved attempt that succeeded, along with the lockout
+    /// it caused.
+    async fn release_attempt(&self, attempt: &LoginAttempt);
+
+    async fn reset(&self, key: &LoginThrottleKey);
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LoginThrottlePolicy {
+    /// Failures allowed before the first lockout.
+    pub free_attempts: u32,
+    /// The first lockout, every failure after it doubles the lockout.
+    pub base_lockout: Duration,
+    pub max_lockout: Duration,
+    /// Failures older than this are forgotten.
+    pub window: Duration,
+}
+
+impl LoginThrottlePolicy {
+    fn lockout_for(&self, failures: u32) -> Option<Duration> {
+        if failures < self.free_attempts {
+            return None;
+        }
+
+        let doublings = (failures - self.free_attempts).min(16);
+        let lockout = self.base_lockout.saturating_mul(1 << doublings);
+
+        return Some(lockout.min(self.max_lockout));
+    }
+}
+
+struct ThrottleEntry {
+    failures: u32,
+    last_failure_at: Instant,
+    locked_until: Option<Instant>,
+}
+
+// Entries are pruned once the map grows past this many keys
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+pub struct MemoryLoginThrottle {
+    pub account_policy: LoginThrottlePolicy,
+    pub ip_policy: LoginThrottlePolicy,
+    entries: Mutex<HashMap<LoginThrottleKey, ThrottleEntry>>,
+}
+
+impl MemoryLoginThrottle {
+    pub fn new(account_policy: LoginThrottlePolicy, ip_policy: LoginThrottlePolicy) -> Self {
+        return Self {
+            account_policy,
+            ip_policy,
+            entries: Mutex::new(HashMap::new()),
+        };
+    }
+
+    fn policy_for(&self, key: &LoginThrottleKey) -> &LoginThrottlePolicy {
+        return match key {
+            LoginThrottleKey::Account(_) => &self.account_policy,
+            LoginThrottleKey::Ip(_) => &self.ip_policy,
+        };
+    }
+}
+
+fn seconds_until(now: Instant, until: Instant) -> u64 {
+    // Rounded up so a client never retries a moment too early
+    let remaining = until.saturating_duration_since(now);
+    return remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+}
+
+#[async_trait]
+impl LoginThrottle for MemoryLoginThrottle {
+    async fn reserve_attempt(&self, key: &LoginThrottleKey) -> Result<LoginAttempt, u64> {
+        let now = Instant::now();
+        let policy = *self.policy_for(key);
+        let mut entries = self.entries.lock().await;
+
+        if let Some(locked_until) = entries
+            .get(key)
+            .and_then(|entry| entry.locked_until)
+            .filter(|locked_until| *locked_until > now)
+        {
+            return Err(seconds_until(now, locked_until));
+        }
+
+        if entries.len() >= MAX_TRACKED_KEYS {
+            entries.retain(|key, entry| {
+                let window = self.policy_for(key).window;
+                let is_locked = entry.locked_until.is_some_and(|until| until > now);
+
+                is_locked || now.duration_since(entry.last_failure_at) < window
+            });
+        }
+
+        let entry = entries.entry(key.to_owned()).or_insert(ThrottleEntry {
+            failures: 0,
+            last_failure_at: now,
+            locked_until: None,
+        });
+
+        if now.duration_since(entry.last_failure_at) >= policy.window {
+            entry.failures = 0;
+        }
+
+        entry.failures += 1;
+        entry.last_failure_at = now;
+
+        let mut attempt = LoginAttempt {
+            key: key.to_owned(),
+            lockout_secs: None,
+            locked_until: None,
+        };
+
+        if let Some(lockout) = policy.lockout_for(entry.failures) {
+            let locked_until = now + lockout;
+
+            entry.locked_until = Some(locked_until);
+            attempt.lockout_secs = Some(seconds_until(now, locked_until));
+            attempt.locked_until = Some(locked_until);
+        }
+
+        return Ok(attempt);
+    }
+
+    async fn release_attempt(&self, attempt: &LoginAttempt) {
+        let mut entries = self.entries.lock().await;
+
+        let Some(entry) = entries.get_mut(&attempt.key) else {
+            return;
+        };
+
+        entry.failures = entry.failures.saturating_sub(1);
+
+        // A later failure may have locked the key since, that lockout stays
+        if attempt.locked_until.is_some() && entry.locked_until == attempt.locked_until {
+            entry.locked_until = None;
+        }
+    }
+
+    async fn reset(&self, key: &LoginThrottleKey) {
+        self.entries.lock().await.remove(key);
+    }
+}
+
+pub fn init_login_throttle() -> Arc<dyn LoginThrottle> {
+    return Arc::new(MemoryLoginThrottle::new(
+        LoginThrottlePolicy {
+            free_attempts: 5,
+            base_lockout: Duration::from_secs(30),
+            max_lockout: Duration::from_secs(60 * 60),
+            window: Duration::from_secs(15 * 60),
+        },
+        // An IP may sit behind a NAT shared by many honest users
+        LoginThrottlePolicy {
+            free_attempts: 20,
+            base_lockout: Duration::from_secs(60),
+            max_lockout: Duration::from_secs(60 * 60),
+            window: Duration::from_secs(15 * 60),
+        },
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: LoginThrottlePolicy = LoginThrottlePolicy {
+        free_attempts: 3,
+        base_lockout: Duration::from_secs(30),
+        max_lockout: Duration::from_secs(100),
+        window: Duration::from_secs(60),
+    };
+
+    fn account() -> LoginThrottleKey {
+        return LoginThrottleKey::Account("aiko@gsm.test".to_string());
+    }
+
+    async fn reserve_many(throttle: &MemoryLoginThrottle, count: usize) -> Vec<LoginAttempt> {
+        let mut attempts = Vec::new();
+
+        for _ in 0..count {
+            attempts.push(throttle.reserve_attempt(&account()).await.unwrap());
+        }
+
+        return attempts;
+    }
+
+    #[test]
+    fn lockout_doubles_up_to_the_max() {
+        assert_eq!(POLICY.lockout_for(2), None);
+        assert_eq!(POLICY.lockout_for(3), Some(Duration::from_secs(30)));
+        assert_eq!(POLICY.lockout_for(4), Some(Duration::from_secs(60)));
+        assert_eq!(POLICY.lockout_for(5), Some(Duration::from_secs(100)));
+        assert_eq!(POLICY.lockout_for(u32::MAX), Some(Duration::from_secs(100)));
+    }
+
+    #[tokio::test]
+    async fn the_attempt_reaching_the_limit_locks_the_key() {
+        let throttle = MemoryLoginThrottle::new(POLICY, POLICY);
+        let attempts = reserve_many(&throttle, 3).await;
+
+        assert_eq!(attempts[1].lockout_secs, None);
+        assert_eq!(attempts[2].lockout_secs, Some(30));
+        assert_eq!(throttle.reserve_attempt(&account()).await.unwrap_err(), 30);
+
+        // Other keys are counted on their own
+        let ip = LoginThrottleKey::Ip("127.0.0.1".to_string());
+        assert!(throttle.reserve_attempt(&ip).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn releasing_the_locking_attempt_lifts_the_lockout() {
+        let throttle = MemoryLoginThrottle::new(POLICY, POLICY);
+        let attempts = reserve_many(&throttle, 3).await;
+
+        throttle.release_attempt(&attempts[2]).await;
+
+        // Back to two failures, the next one locks again
+        let attempt = throttle.reserve_attempt(&account()).await.unwrap();
+        assert_eq!(attempt.lockout_secs, Some(30));
+    }
+
+    #[tokio::test]
+    async fn releasing_another_attempt_keeps_the_lockout() {
+        let throttle = MemoryLoginThrottle::new(POLICY, POLICY);
+        let attempts = reserve_many(&throttle, 3).await;
+
+        throttle.release_attempt(&attempts[0]).await;
+
+        assert!(throttle.reserve_attempt(&account()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn failures_are_forgotten_after_the_window() {
+        let policy = LoginThrottlePolicy {
+            window: Duration::from_millis(20),
+            ..POLICY
+        };
+        let throttle = MemoryLoginThrottle::new(policy, policy);
+        reserve_many(&throttle, 2).await;
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+
+        let attempt = throttle.reserve_attempt(&account()).await.unwrap();
+        assert_eq!(attempt.lockout_secs, None);
+    }
+
+    #[tokio::test]
+    async fn reset_clears_the_lockout() {
+        let throttle = MemoryLoginThrottle::new(POLICY, POLICY);
+        reserve_many(&throttle, 3).await;
+
+        throttle.reset(&account()).await;
+
+        assert!(throttle.reserve_attempt(&account()).await.is_ok());
+    }
+}
@@ -0,0 +1,80 @@
+use std::{path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use tokio::io::AsyncWriteExt;
+use tracing::info;
+
+use crate::{
+    config::Config,
+    utils::app_error::{AppError, ResultExt},
+};
+
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: Mail) -> Result<(), AppError>;
+}
+
+/// Writes outgoing mail to the server log. Used when no outbox is configured.
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, mail: Mail) -> Result<(), AppError> {
+        info!(
+            target = "Mailer event",
+            to = mail.to,
+            subject = mail.subject,
+            "{}",
+            mail.body
+        );
+
+        return Ok(());
+    }
+}
+
+/// Appends outgoing mail to a local file so flows can be completed offline.
+pub struct FileMailer {
+    pub path: PathBuf,
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, mail: Mail) -> Result<(), AppError> {
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .internal("opening the mail outbox")?;
+
+        let entry = format!(
+            "To: {}\nSubject: {}\n\n{}\n\n",
+            mail.to, mail.subject, mail.body
+        );
+
+        file.write_all(entry.as_bytes())
+            .await
+            .internal("writing to the mail outbox")?;
+
+        // tokio finishes the write in the background unless flushed
+        file.flush().await.internal("flushing the mail outbox")?;
+
+        return Ok(());
+    }
+}
+
+pub fn init_mailer(config: &Config) -> Arc<dyn Mailer> {
+    match &config.mailer_outbox_path {
+        Some(path) => Arc::new(FileMailer {
+            path: PathBuf::from(path),
+        }),
+        None => Arc::new(LogMailer),
+    }
+}
@@ -1,2 +1,10 @@
 pub mod app_error;
+pub mod cache;
+pub mod client_info;
+pub mod login_throttle;
+pub mod mailer;
 pub mod password;
+pub mod rate_limiter;
+pub mod request_id;
+pub mod token;
+pub mod validation;
@@ -2,28 +2,23 @@ use argon2::{
     password_hash::{rand_core::OsRng, SaltString},
     Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
 };
-use tracing::error;
 
 use crate::utils::app_error::AppError;
 
-pub fn hash_password(password: &String) -> Result<String, AppError> {
+pub fn hash_password(password: &str) -> Result<String, AppError> {
     let salt = SaltString::generate(&mut OsRng);
     let argon2 = Argon2::default();
     let password_hash = argon2
         .hash_password(password.as_bytes(), &salt)
-        .map_err(|err| {
-            error!("{:?}", err);
-            return AppError::InternalServerError;
-        })?;
+        // argon2 errors don't implement std::error::Error without its std feature
+        .map_err(|err| AppError::internal("hashing a password", err.to_string()))?;
 
     return Ok(password_hash.to_string());
 }
 
-pub fn verify_password(password: &String, hashed_password: &String) -> Result<bool, AppError> {
-    let parsed_hash = PasswordHash::new(hashed_password).map_err(|err| {
-        error!("{:?}", err);
-        return AppError::InternalServerError;
-    })?;
+pub fn verify_password(password: &str, hashed_password: &str) -> Result<bool, AppError> {
+    let parsed_hash = PasswordHash::new(hashed_password)
+        .map_err(|err| AppError::internal("parsing a password hash", err.to_string()))?;
 
     return Ok(Argon2::default()
         .verify_password(password.as_bytes(), &parsed_hash)
@@ -0,0 +1,98 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+
+use crate::config::RateLimitPolicy;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitDecision {
+    pub is_allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again.
+    pub reset_secs: u64,
+    /// Seconds until the next request is allowed, zero when it already is.
+    pub retry_after_secs: u64,
+}
+
+/// Token buckets by key. Implementations backed by a shared store can
+/// replace the in-process one once the server runs on more than one machine.
+#[async_trait]
+pub trait RateLimiter: Send + Sync {
+    /// Takes a token from the bucket of `key` when one is left.
+    async fn acquire(&self, key: &str, policy: &RateLimitPolicy) -> RateLimitDecision;
+}
+
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+    full_at: Instant,
+}
+
+// Full buckets are pruned once the map grows past this many keys
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+#[derive(Default)]
+pub struct MemoryRateLimiter {
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+fn ceil_secs(secs: f64) -> u64 {
+    return secs.max(0.0).ceil() as u64;
+}
+
+#[async_trait]
+impl RateLimiter for MemoryRateLimiter {
+    async fn acquire(&self, key: &str, policy: &RateLimitPolicy) -> RateLimitDecision {
+        let now = Instant::now();
+        let capacity = policy.requests as f64;
+        let refill_per_sec = capacity / policy.per_seconds as f64;
+        let mut buckets = self.buckets.lock().await;
+
+        if buckets.len() >= MAX_TRACKED_KEYS {
+            buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(TokenBucket {
+            tokens: capacity,
+            updated_at: now,
+            full_at: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * refill_per_sec).min(capacity);
+        bucket.updated_at = now;
+
+        let is_allowed = bucket.tokens >= 1.0;
+
+        if is_allowed {
+            bucket.tokens -= 1.0;
+        }
+
+        let reset_secs = (capacity - bucket.tokens) / refill_per_sec;
+        bucket.full_at = now + Duration::from_secs_f64(reset_secs);
+
+        let retry_after_secs = if is_allowed {
+            0
+        } else {
+            ceil_secs((1.0 - bucket.tokens) / refill_per_sec)
+        };
+
+        return RateLimitDecision {
+            is_allowed,
+            limit: policy.requests,
+            remaining: bucket.tokens.floor() as u32,
+            reset_secs: ceil_secs(reset_secs),
+            retry_after_secs,
+        };
+    }
+}
+
+pub fn init_rate_limiter() -> Arc<dyn RateLimiter> {
+    return Arc::new(MemoryRateLimiter::default());
+}
@@ -0,0 +1,38 @@
+use std::future::Future;
+
+use uuid::Uuid;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+// Longer or odd looking ids from clients are replaced, they end up in logs
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+/// Keeps a well formed id sent by the client or a proxy, so one id can follow
+/// a request across services. Anything else gets a new id.
+pub fn request_id_from_header(header: Option<&str>) -> String {
+    let is_valid = |id: &str| {
+        !id.is_empty()
+            && id.len() <= MAX_REQUEST_ID_LEN
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+
+    return match header {
+        Some(id) if is_valid(id) => id.to_string(),
+        _ => Uuid::new_v4().to_string(),
+    };
+}
+
+/// Runs `future` with `request_id` as the id of the current request.
+pub async fn with_request_id<F: Future>(request_id: String, future: F) -> F::Output {
+    return REQUEST_ID.scope(request_id, future).await;
+}
+
+/// The id of the request being handled, None outside of a request like in
+/// the CLI or in spawned tasks.
+pub fn current_request_id() -> Option<String> {
+    return REQUEST_ID.try_with(|request_id| request_id.clone()).ok();
+}
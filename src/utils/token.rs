@@ -0,0 +1,19 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
+
+/// Generates a random, URL safe token that is only ever handed to the client.
+pub fn generate_opaque_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+
+    return URL_SAFE_NO_PAD.encode(bytes);
+}
+
+/// Hashes an opaque token for storage. Tokens are high entropy, so a fast
+/// hash is enough and keeps lookups by hash possible.
+pub fn hash_opaque_token(token: &str) -> String {
+    let digest = Sha256::digest(token.as_bytes());
+
+    return URL_SAFE_NO_PAD.encode(digest);
+}
@@ -0,0 +1,220 @@
+use chrono::{DateTime, Months, Utc};
+use serde::Serialize;
+use sqids::Sqids;
+use tracing::warn;
+
+use crate::utils::app_error::AppError;
+
+// Every endpoint that takes these fields goes through the rules below, so a
+// value accepted at registration is also accepted by a profile update.
+pub const EMAIL_MAX_LEN: usize = 255;
+pub const PASSWORD_MAX_LEN: usize = 255;
+pub const NAME_MAX_CHARS: usize = 64;
+pub const LOCATION_MAX_CHARS: usize = 255;
+pub const MIN_AGE_YEARS: u32 = 18;
+// Birth dates are stored as i32 by the libsql crate, which reaches back to 1901
+pub const MAX_AGE_YEARS: u32 = 120;
+
+/// One invalid field. `code` is stable and meant for clients to branch on,
+/// `message` is for people and may change. `field` is empty when the error is
+/// about the request as a whole.
+#[derive(Clone, Debug, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// Collects every invalid field of a request so they can be reported at once.
+#[derive(Debug, Default)]
+pub struct Validator {
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn add(&mut self, field: &str, code: &'static str, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            field: field.to_string(),
+            code,
+            message: message.into(),
+        });
+    }
+
+    pub fn is_valid(&self) -> bool {
+        return self.errors.is_empty();
+    }
+
+    /// Fails with `AppError::Validation` when any field was invalid.
+    pub fn finish(self) -> Result<(), AppError> {
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+
+        warn!("From validation condition {:?}", self.errors);
+        return Err(AppError::Validation(self.errors));
+    }
+
+    /// Returns the trimmed, lowercased email.
+    pub fn email(&mut self, field: &str, email: &str) -> String {
+        let email = email.trim().to_lowercase();
+
+        if email.is_empty() {
+            self.add(field, "required", "Email is required");
+        } else if email.len() > EMAIL_MAX_LEN {
+            self.add(
+                field,
+                "too_long",
+                format!("Email is longer than {} bytes", EMAIL_MAX_LEN),
+            );
+        } else if !email.contains('@') {
+            self.add(field, "invalid_format", "Email is not an email address");
+        }
+
+        return email;
+    }
+
+    /// Returns the trimmed password.
+    pub fn password(&mut self, field: &str, password: &str) -> String {
+        let password = password.trim().to_string();
+
+        if password.is_empty() {
+            self.add(field, "required", "Password is required");
+        } else if password.len() > PASSWORD_MAX_LEN {
+            let message = format!("Password is longer than {} bytes", PASSWORD_MAX_LEN);
+            self.add(field, "too_long", message);
+        }
+
+        return password;
+    }
+
+    /// Returns the trimmed name.
+    pub fn name(&mut self, field: &str, name: &str) -> String {
+        let name = name.trim().to_string();
+
+        if name.is_empty() {
+            self.add(field, "required", "Name is required");
+        } else if name.chars().count() > NAME_MAX_CHARS {
+            let message = format!("Name is longer than {} characters", NAME_MAX_CHARS);
+            self.add(field, "too_long", message);
+        }
+
+        return name;
+    }
+
+    /// Returns the trimmed location in upper case, the way it is matched.
+    pub fn location(&mut self, field: &str, location: &str) -> String {
+        let location = location.trim().to_uppercase();
+
+        // TODO: Check if location exist in a location_map!
+        if location.is_empty() {
+            self.add(field, "required", "Location is required");
+        } else if location.chars().count() > LOCATION_MAX_CHARS {
+            let message = format!("Location is longer than {} characters", LOCATION_MAX_CHARS);
+            self.add(field, "too_long", message);
+        }
+
+        return location;
+    }
+
+    /// Checks a unix timestamp birth date against the age limits.
+    pub fn birth_date(&mut self, field: &str, birth_date: i64) -> i64 {
+        let now = Utc::now();
+
+        if DateTime::from_timestamp(birth_date, 0).is_none() {
+            self.add(
+                field,
+                "invalid_format",
+                "Birth date is not a unix timestamp",
+            );
+        } else if birth_date > youngest_birth_date(now) {
+            let message = format!("You must be at least {} years old", MIN_AGE_YEARS);
+            self.add(field, "too_young", message);
+        } else if birth_date < oldest_birth_date(now) {
+            let message = format!("Birth date is more than {} years ago", MAX_AGE_YEARS);
+            self.add(field, "too_old", message);
+        }
+
+        return birth_date;
+    }
+
+    /// Returns the trimmed text, `None` when it is missing or blank.
+    pub fn optional_text(
+        &mut self,
+        field: &str,
+        text: Option<String>,
+        max_chars: usize,
+    ) -> Option<String> {
+        let text = text
+            .map(|text| text.trim().to_string())
+            .filter(|text| !text.is_empty())?;
+
+        if text.chars().count() > max_chars {
+            let message = format!("Text is longer than {} characters", max_chars);
+            self.add(field, "too_long", message);
+        }
+
+        return Some(text);
+    }
+
+    /// Returns the page size, `default` when none was asked for.
+    pub fn limit(&mut self, field: &str, limit: Option<i64>, default: i64, max: i64) -> i64 {
+        let limit = limit.unwrap_or(default);
+
+        if !(1..=max).contains(&limit) {
+            let message = format!("Limit must be between 1 and {}", max);
+            self.add(field, "out_of_range", message);
+        }
+
+        return limit;
+    }
+
+    /// Returns the row id a page cursor handed out earlier points at.
+    pub fn cursor(&mut self, field: &str, cursor: Option<String>, sqids: &Sqids) -> Option<i64> {
+        let cursor = cursor?;
+
+        return match sqids.decode(cursor.as_str()).as_slice() {
+            [id] => Some(*id as i64),
+            _ => {
+                self.add(field, "invalid_format", "Cursor is not valid");
+                None
+            }
+        };
+    }
+
+    /// Adds an error for a JSON value of the wrong type.
+    pub fn invalid_type(&mut self, field: &str, expected: &str) {
+        self.add(field, "invalid_type", format!("Expected {}", expected));
+    }
+
+    /// Adds an error for a `null` sent to clear a field that can't be empty.
+    pub fn not_nullable(&mut self, field: &str) {
+        self.add(field, "not_nullable", "Can't be cleared");
+    }
+
+    /// Adds an error for a key the endpoint doesn't know about.
+    pub fn unknown_field(&mut self, field: &str) {
+        self.add(field, "unknown_field", "Unknown field");
+    }
+}
+
+/// The latest birth date of someone who is `MIN_AGE_YEARS` old at `now`.
+pub fn youngest_birth_date(now: DateTime<Utc>) -> i64 {
+    return birth_date_for_age(now, MIN_AGE_YEARS);
+}
+
+/// The earliest birth date of someone who is `MAX_AGE_YEARS` old at `now`.
+pub fn oldest_birth_date(now: DateTime<Utc>) -> i64 {
+    return birth_date_for_age(now, MAX_AGE_YEARS + 1) + 1;
+}
+
+/// The birth date of someone who turns `age` at `now`.
+pub fn birth_date_for_age(now: DateTime<Utc>, age: u32) -> i64 {
+    return now
+        .checked_sub_months(Months::new(age * 12))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC)
+        .timestamp();
+}
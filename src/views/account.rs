@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{moderation::ReportResponse, profile::ProfileResponse, session::SessionResponse};
+
+#[derive(Deserialize)]
+pub struct DeleteAccountParams {
+    pub password: String,
+}
+
+#[derive(Serialize)]
+pub struct AccountUserResponse {
+    pub pid: Option<Uuid>,
+    pub email: Option<String>,
+    pub role: Option<String>,
+    pub suspended_at: Option<i64>,
+    pub created_at: Option<i64>,
+    pub updated_at: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct AccountVideoResponse {
+    pub pid: Option<Uuid>,
+    pub mime_type: Option<String>,
+    pub size_bytes: Option<i64>,
+    pub is_profile_video: bool,
+    pub created_at: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct ProfileRelationResponse {
+    pub profile_pid: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_like: Option<bool>,
+    pub created_at: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct AccountMessageResponse {
+    pub pid: Option<Uuid>,
+    pub profile_pid: Option<Uuid>,
+    pub is_sent: bool,
+    pub body: Option<String>,
+    pub delivered_at: Option<i64>,
+    pub read_at: Option<i64>,
+    pub created_at: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct AccountProfileRevisionResponse {
+    // The editor is left out when it is a moderator
+    pub is_own_edit: bool,
+    pub field: Option<String>,
+    pub old_value: Option<serde_json::Value>,
+    pub new_value: Option<serde_json::Value>,
+    pub created_at: Option<i64>,
+}
+
+/// Everything the server holds about the caller. Reports filed against the
+/// caller are left out to protect the reporters.
+#[derive(Serialize)]
+pub struct AccountExportResponse {
+    pub exported_at: i64,
+    pub user: AccountUserResponse,
+    pub profile: Option<ProfileResponse>,
+    pub profile_revisions: Vec<AccountProfileRevisionResponse>,
+    pub videos: Vec<AccountVideoResponse>,
+    pub sessions: Vec<SessionResponse>,
+    pub swipes: Vec<ProfileRelationResponse>,
+    pub matches: Vec<ProfileRelationResponse>,
+    pub blocks: Vec<ProfileRelationResponse>,
+    pub messages: Vec<AccountMessageResponse>,
+    pub reports: Vec<ReportResponse>,
+}
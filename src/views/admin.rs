@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::profile::ProfileResponse;
+use crate::{models::user::Role, utils::cache::CacheStats};
+
+#[derive(Debug, Deserialize)]
+pub struct UserSearchParams {
+    pub email: String,
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminUserResponse {
+    pub pid: Option<Uuid>,
+    pub email: Option<String>,
+    pub role: Option<String>,
+    pub suspended_at: Option<i64>,
+    pub created_at: Option<i64>,
+    pub profile: Option<ProfileResponse>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminUsersResponse {
+    pub users: Vec<AdminUserResponse>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetRoleParams {
+    pub role: Role,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditLogsParams {
+    /// Only entries about this user
+    pub user: Option<Uuid>,
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditLogResponse {
+    pub actor_user_pid: Option<Uuid>,
+    pub action: Option<String>,
+    pub target_user_pid: Option<Uuid>,
+    pub details: Option<serde_json::Value>,
+    pub created_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditLogsResponse {
+    pub audit_logs: Vec<AuditLogResponse>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CacheStatsResponse {
+    pub caches: Vec<CacheStats>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProfileRevisionsParams {
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProfileRevisionResponse {
+    // None once the editor's account is deleted
+    pub editor_user_pid: Option<Uuid>,
+    pub field: Option<String>,
+    pub old_value: Option<serde_json::Value>,
+    pub new_value: Option<serde_json::Value>,
+    pub created_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProfileRevisionsResponse {
+    pub revisions: Vec<ProfileRevisionResponse>,
+    pub next_cursor: Option<String>,
+}
@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct ChatSocketParams {
+    // Browsers can't set headers on a WebSocket handshake
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MessagesParams {
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageResponse {
+    pub pid: Uuid,
+    pub from: Uuid,
+    pub to: Uuid,
+    pub body: String,
+    pub created_at: Option<i64>,
+    pub delivered_at: Option<i64>,
+    pub read_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessagesResponse {
+    pub messages: Vec<MessageResponse>,
+    pub next_cursor: Option<String>,
+}
+
+/// Events sent by a client over the chat socket. Profiles are referenced by pid.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatClientEvent {
+    Send {
+        to: Uuid,
+        body: String,
+        client_id: Option<String>,
+    },
+    Typing {
+        to: Uuid,
+        is_typing: bool,
+    },
+    /// Marks `message` and everything received before it in that chat as read.
+    Read {
+        message: Uuid,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatServerEvent {
+    Message {
+        message: MessageResponse,
+    },
+    /// Acknowledges a `send` to the socket it came from.
+    Sent {
+        client_id: Option<String>,
+        message: MessageResponse,
+    },
+    Delivered {
+        message: Uuid,
+        delivered_at: i64,
+    },
+    Read {
+        message: Uuid,
+        read_at: i64,
+    },
+    Typing {
+        from: Uuid,
+        is_typing: bool,
+    },
+    Error {
+        error: String,
+    },
+}
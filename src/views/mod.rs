@@ -1,2 +1,7 @@
+pub mod account;
+pub mod admin;
+pub mod chat;
+pub mod moderation;
 pub mod profile;
+pub mod session;
 pub mod user;
@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportReason {
+    Spam,
+    Harassment,
+    InappropriateContent,
+    FakeProfile,
+    Underage,
+    Other,
+}
+
+impl ReportReason {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            Self::Spam => "spam",
+            Self::Harassment => "harassment",
+            Self::InappropriateContent => "inappropriate_content",
+            Self::FakeProfile => "fake_profile",
+            Self::Underage => "underage",
+            Self::Other => "other",
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportStatus {
+    Open,
+    InReview,
+    Resolved,
+    Dismissed,
+}
+
+impl ReportStatus {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            Self::Open => "open",
+            Self::InReview => "in_review",
+            Self::Resolved => "resolved",
+            Self::Dismissed => "dismissed",
+        };
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportParams {
+    pub reason: ReportReason,
+    pub details: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportCreatedResponse {
+    pub pid: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportsParams {
+    pub status: Option<ReportStatus>,
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResolveReportParams {
+    /// Either `resolved` or `dismissed`
+    pub status: ReportStatus,
+    pub note: Option<String>,
+    #[serde(default)]
+    pub hide_profile: bool,
+    #[serde(default)]
+    pub suspend_user: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportResponse {
+    pub pid: Option<Uuid>,
+    pub reporter_profile_pid: Option<Uuid>,
+    pub reported_profile_pid: Option<Uuid>,
+    pub reason: Option<String>,
+    pub details: Option<String>,
+    pub status: Option<String>,
+    pub resolution_note: Option<String>,
+    pub created_at: Option<i64>,
+    pub updated_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportsResponse {
+    pub reports: Vec<ReportResponse>,
+    pub next_cursor: Option<String>,
+}
@@ -11,6 +11,35 @@ pub struct ProfileParams {
     pub is_visible: bool,
 }
 
+/// A field of a JSON Merge Patch (RFC 7396). A key left out of the patch
+/// keeps its value and `null` clears it.
+#[derive(Debug, Default)]
+pub enum Patch<T> {
+    #[default]
+    Unchanged,
+    Clear,
+    Set(T),
+}
+
+/// Body of a profile update, parsed field by field so every invalid or
+/// unknown key can be reported.
+#[derive(Debug, Default)]
+pub struct ProfilePatch {
+    pub first_name: Patch<String>,
+    pub last_name: Patch<String>,
+    pub birth_date: Patch<i64>,
+    pub location: Patch<String>,
+    pub is_visible: Patch<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VideoResponse {
+    pub pid: Uuid,
+    pub mime_type: String,
+    pub size_bytes: i64,
+    pub video_path: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ProfileResponse {
     pub pid: Option<Uuid>,
@@ -19,4 +48,32 @@ pub struct ProfileResponse {
     pub location: Option<String>,
     pub birth_date: Option<i64>,
     pub is_visible: Option<bool>,
+    pub video_path: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiscoverParams {
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+    pub min_age: Option<i64>,
+    pub max_age: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiscoverResponse {
+    pub profiles: Vec<ProfileResponse>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SwipeResponse {
+    pub pid: Uuid,
+    pub is_like: bool,
+    pub is_match: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MatchResponse {
+    pub profile: ProfileResponse,
+    pub matched_at: Option<i64>,
 }
@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct RefreshTokenParams {
+    pub refresh_token: String,
+}
+
+#[derive(Serialize)]
+pub struct TokenResponse {
+    pub auth_token: String,
+    pub refresh_token: String,
+}
+
+#[derive(Serialize)]
+pub struct SessionResponse {
+    pub pid: Option<Uuid>,
+    pub device_label: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: Option<i64>,
+    pub last_used_at: Option<i64>,
+    pub expires_at: Option<i64>,
+    pub is_current: bool,
+}
@@ -9,6 +9,20 @@ pub struct RegisterParams {
     pub birth_date: i64,
 }
 
+#[derive(Serialize)]
+pub struct RegisterStartResponse {
+    pub email: String,
+    pub expires_at: i64,
+}
+
+#[derive(Deserialize)]
+pub struct RegisterVerifyParams {
+    pub email: String,
+    pub code: String,
+    #[serde(default)]
+    pub device_label: Option<String>,
+}
+
 #[derive(Serialize)]
 pub struct RegisterResponse {
     pub first_name: Option<String>,
@@ -18,16 +32,37 @@ pub struct RegisterResponse {
     pub is_visible: Option<bool>,
     pub email: Option<String>,
     pub auth_token: Option<String>,
+    pub refresh_token: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct LoginParams {
     pub email: String,
     pub password: String,
+    #[serde(default)]
+    pub device_label: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct LoginResponse {
     pub email: Option<String>,
     pub auth_token: Option<String>,
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ForgotPasswordParams {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordParams {
+    pub token: String,
+    pub new_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordParams {
+    pub current_password: String,
+    pub new_password: String,
 }